@@ -1,4 +1,4 @@
-use crate::lib::{rotate_left, rotate_right, Direction, Heuristics, Protein};
+use crate::protein::{rotate_left, rotate_right, Direction, Heuristics, Protein};
 use rand::Rng;
 use std::collections::BinaryHeap;
 use std::collections::HashMap;
@@ -32,8 +32,7 @@ impl ACO {
                 // self.pheromone.insert((i as i32, r as i32), 1.0);
             }
             protein.direct = direct.clone();
-            let mut score = protein.calc_predict();
-            if score != -1 {
+            if let Ok(score) = protein.score() {
                 self.best_score = score;
                 self.protein = protein.clone();
                 break;
@@ -61,10 +60,8 @@ impl ACO {
                     let p = self.pheromone.get(&(i as i32, direct as i32)).unwrap();
                     let mut new_protein = protein.clone();
                     new_protein.direct[i] = direct.clone();
-                    let mut score = new_protein.calc_predict();
-                    if score == -1 {
-                        score = 0;
-                    }
+                    // 衝突する向きはH-H結合がないものとして扱う
+                    let score = new_protein.score().unwrap_or(0);
                     let dist = (-(score as f64) / self.gamma).exp();
                     let prob = p.powf(self.alpha) * dist.powf(self.beta);
                     probs.push(prob);
@@ -85,10 +82,7 @@ impl ACO {
             }
             let mut new_protein = protein.clone();
             new_protein.direct = route.clone();
-            let mut score = new_protein.calc_predict();
-            if score == -1 {
-                score = 0;
-            }
+            let score = new_protein.score().unwrap_or(0);
             new_proteins.push(new_protein.clone());
             all_routes.push(route.clone());
             all_scores.push(score);
@@ -1,91 +1,91 @@
-use crate::lib::{
-    rotate_down, rotate_left, rotate_right, rotate_up, Amino, AminoAcid, Direction, Protein,
-};
+use crate::error::Error;
+use crate::observer::SearchObserver;
+use crate::protein::Protein;
+use crate::render::{draw_protein, Camera, RenderOptions};
+use plotters::coord::Shift;
 use plotters::prelude::*;
-pub fn animation(protein: &mut Protein, dim: u8) {
-    let area = BitMapBackend::gif(
-        "./animated.gif", // アニメーションファイルの名前。この名前で保存される
-        (1200, 800),      //  グラフのサイズ（幅x高さ)
-        100,              //  1フレームの時間。単位は [ms]
-    )
-    .unwrap()
-    .into_drawing_area();
+use std::f64::consts::TAU;
 
-    let mut data = Vec::new();
-    data.push((0, 0, 0, protein.aminos[0].amino));
-    if dim == 2 {
-        data.push((5, 0, 0, protein.aminos[1].amino));
-    } else {
-        data.push((10, 0, 0, protein.aminos[1].amino));
+// 探索の途中経過を1フレームずつ書き足していくGIF
+pub struct Animation {
+    area: DrawingArea<BitMapBackend<'static>, Shift>,
+    dim: u8,
+    options: RenderOptions,
+}
+
+impl Animation {
+    pub fn new(path: &str, dim: u8, options: &RenderOptions) -> Result<Animation, Error> {
+        let area = BitMapBackend::gif(path, options.size, options.frame_delay)
+            .map_err(|e| Error::Render(format!("{}: {}", path, e)))?
+            .into_drawing_area();
+        Ok(Animation {
+            area,
+            dim,
+            options: *options,
+        })
     }
-    for i in 0..protein.direct.len() {
-        let mut prev_direction = (
-            data[i + 1].0 - data[i].0,
-            data[i + 1].1 - data[i].1,
-            data[i + 1].2 - data[i].2,
-        );
-        let (x, y, z, a) = match protein.direct[i] {
-            Direction::S => (
-                data[i + 1].0 + prev_direction.0,
-                data[i + 1].1 + prev_direction.1,
-                data[i + 1].2 + prev_direction.2,
-                protein.aminos[i + 2].amino,
-            ),
-            Direction::L => (
-                data[i + 1].0 + rotate_left(prev_direction).0,
-                data[i + 1].1 + rotate_left(prev_direction).1,
-                data[i + 1].2 + rotate_left(prev_direction).2,
-                protein.aminos[i + 2].amino,
-            ),
-            Direction::R => (
-                data[i + 1].0 + rotate_right(prev_direction).0,
-                data[i + 1].1 + rotate_right(prev_direction).1,
-                data[i + 1].2 + rotate_right(prev_direction).2,
-                protein.aminos[i + 2].amino,
-            ),
-            Direction::U => (
-                data[i + 1].0 + rotate_up(prev_direction).0,
-                data[i + 1].1 + rotate_up(prev_direction).1,
-                data[i + 1].2 + rotate_up(prev_direction).2,
-                protein.aminos[i + 2].amino,
-            ),
-            Direction::D => (
-                data[i + 1].0 + rotate_down(prev_direction).0,
-                data[i + 1].1 + rotate_down(prev_direction).1,
-                data[i + 1].2 + rotate_down(prev_direction).2,
-                protein.aminos[i + 2].amino,
-            ),
-        };
-        data.push((x, y, z, a));
+    pub fn push_frame(&self, protein: &mut Protein) -> Result<(), Error> {
+        draw_protein(&self.area, protein, self.dim, &self.options)?;
+        // グラフを更新する
+        self.area.present()?;
+        Ok(())
     }
+}
 
-    area.fill(&WHITE).unwrap();
-
-    let mut chart = ChartBuilder::on(&area)
-        .margin(20)
-        .caption("protein structure", ("sans-serif", 40))
-        .build_cartesian_3d(-100..100, -100..100, -100..100)
-        .unwrap();
+// 採用された候補をevery個ごとに1フレームとして記録する
+// 書き出しに失敗したらそれ以降は記録せず、errorに残す
+pub struct TraceRecorder {
+    pub animation: Animation,
+    pub every: usize,
+    pub error: Option<Error>,
+    count: usize,
+}
 
-    chart.configure_axes().draw().unwrap();
+impl TraceRecorder {
+    pub fn new(animation: Animation, every: usize) -> TraceRecorder {
+        TraceRecorder {
+            animation,
+            every,
+            error: None,
+            count: 0,
+        }
+    }
+}
 
-    chart
-        .draw_series(data.iter().map(|&(x, y, z, a)| {
-            if (a == Amino::H) {
-                TriangleMarker::new((x, y, z), 5, &RED)
-            } else {
-                TriangleMarker::new((x, y, z), 5, &BLUE)
+impl SearchObserver for TraceRecorder {
+    fn on_candidate_accepted(&mut self, candidate: &Protein, _score: i32) {
+        self.count += 1;
+        if self.count >= self.every && self.error.is_none() {
+            self.count = 0;
+            //visualize の処理
+            if let Err(e) = self.animation.push_frame(&mut candidate.clone()) {
+                self.error = Some(e);
             }
-        }))
-        .unwrap();
-
-    chart
-        .draw_series(LineSeries::new(
-            data.iter().map(|&(x, y, z, _)| (x, y, z)),
-            &BLACK.mix(0.3),
-        ))
-        .unwrap();
+        }
+    }
+    fn wants_candidates(&self) -> bool {
+        true
+    }
+}
 
-    // グラフを更新する
-    area.present().unwrap();
+// 完成した構造をy軸まわりに一周させるGIFを書き出す
+pub fn turntable(
+    protein: &mut Protein,
+    path: &str,
+    frames: u32,
+    options: &RenderOptions,
+) -> Result<(), Error> {
+    let area = BitMapBackend::gif(path, options.size, options.frame_delay)
+        .map_err(|e| Error::Render(format!("{}: {}", path, e)))?
+        .into_drawing_area();
+    for frame in 0..frames {
+        let camera = Camera {
+            yaw: options.camera.yaw + TAU * frame as f64 / frames as f64,
+            ..options.camera
+        };
+        let options = RenderOptions { camera, ..*options };
+        draw_protein(&area, protein, 3, &options)?;
+        area.present()?;
+    }
+    Ok(())
 }
@@ -1,51 +1,89 @@
-use crate::lib::{Direction, Protein};
-use rand::Rng;
+use crate::budget::Budget;
+use crate::checkpoint::{AnnealState, RngState, SolverState};
+use crate::conformation::value;
+use crate::init::Init;
+use crate::observer::{Progress, SearchObserver};
+use crate::protein::{Direction, Protein};
+use crate::solve::{Solver, SolverSettings};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+// on_progressとon_checkpointを呼ぶ間隔
+const REPORT_INTERVAL: usize = 1000;
+
 pub struct Annealing {
-    pub temperature: f64,
-    pub max_iter: i32,
-    pub now_ans: Protein,
-    pub now_score: i32,
-    pub best_ans: Protein,
-    pub best_score: i32,
-    pub num_direct: i32,
+    temperature: f64,
+    max_iter: i32,
+    now_ans: Protein,
+    now_score: i32,
+    best_ans: Protein,
+    best_score: i32,
+    num_direct: i32,
+    // 構造を評価した回数
+    evaluations: u64,
+    rng: ChaCha12Rng,
+    // 終わった反復の回数
+    iteration: usize,
+    budget: Budget,
+    // 最初の解の作り方
+    init: Init,
 }
 
 impl Annealing {
-    pub fn first_step(&mut self) {
-        while true {
-            let mut rng = rand::thread_rng();
-            let mut direct = Vec::new();
-            let mut protein = self.now_ans.clone();
-            for _ in 0..protein.size - 2 {
-                let r = rng.gen_range(0..self.num_direct);
-                match r {
-                    0 => direct.push(Direction::S),
-                    1 => direct.push(Direction::L),
-                    2 => direct.push(Direction::R),
-                    3 => direct.push(Direction::U),
-                    4 => direct.push(Direction::D),
-                    _ => {}
-                }
-            }
-            protein.direct = direct.clone();
-            let mut score = protein.calc_predict();
-            if score != -1 {
-                self.now_score = score;
-                self.now_ans = protein.clone();
-                self.best_ans = protein.clone();
-                self.best_score = score;
-                break;
-            }
+    pub fn new(
+        template: &Protein,
+        num_direct: i32,
+        settings: &SolverSettings,
+        seed: u64,
+        budget: Budget,
+    ) -> Annealing {
+        Annealing {
+            temperature: settings.temperature,
+            max_iter: settings.iterations.unwrap_or(100000) as i32,
+            now_ans: template.clone(),
+            now_score: 0,
+            best_ans: template.clone(),
+            best_score: 0,
+            num_direct,
+            evaluations: 0,
+            rng: ChaCha12Rng::seed_from_u64(seed),
+            iteration: 0,
+            budget,
+            init: settings.init_for(Solver::Anneal),
         }
     }
+    pub fn best_ans(&self) -> &Protein {
+        &self.best_ans
+    }
+    pub fn best_score(&self) -> i32 {
+        self.best_score
+    }
+    pub fn evaluations(&self) -> u64 {
+        self.evaluations
+    }
+    pub fn first_step(&mut self) {
+        let mut protein = self
+            .init
+            .build(&self.now_ans, self.num_direct, &mut self.rng);
+        // 初期解は自己回避歩行なので衝突しない
+        let score = protein.score().expect("initial walks are self-avoiding");
+        self.evaluations += 1;
+        self.now_score = score;
+        self.now_ans = protein.clone();
+        self.best_ans = protein;
+        self.best_score = score;
+    }
     pub fn one_step(&mut self) {
-        let mut rng = rand::thread_rng();
-        let mut direct = self.now_ans.direct.clone();
-        let mut score = self.now_score;
+        // 向きが1つもない(アミノ酸が2つ以下の)ときは変えられるところがない
+        if self.now_ans.direct.is_empty() {
+            return;
+        }
+        let rng = &mut self.rng;
+        let direct = self.now_ans.direct.clone();
         let mut new_direct = direct.clone();
         let mut protein = self.now_ans.clone();
         for _ in 0..self.num_direct {
-            let mut r = rng.gen_range(0..direct.len());
+            let r = rng.gen_range(0..direct.len());
             let mut new_r = rng.gen_range(0..self.num_direct);
             if direct[r] as i32 == new_r {
                 new_r += 1;
@@ -61,16 +99,17 @@ impl Annealing {
             }
         }
         protein.direct = new_direct.clone();
-        let new_score = protein.calc_predict();
-        let new_value = protein.get_value();
+        self.evaluations += 1;
 
-        if new_score != -1 {
-            if new_value > self.best_ans.get_value() {
+        if let Ok(new_score) = protein.score() {
+            let new_value = value(new_score, protein.calc_max_distance());
+            let best_value = value(self.best_score, self.best_ans.calc_max_distance());
+            if new_value > best_value {
                 self.now_score = new_score;
                 self.now_ans = protein.clone();
                 self.now_ans.direct = new_direct.clone();
             } else {
-                let diff = (new_value - self.best_ans.get_value()) as f64;
+                let diff = (new_value - best_value) as f64;
                 let prob = (diff / self.temperature).exp();
                 let p = rng.gen_range(0.0..1.0);
                 if p < prob {
@@ -81,9 +120,95 @@ impl Annealing {
             }
         }
 
-        if (self.now_score > self.best_score) {
+        if self.now_score > self.best_score {
             self.best_score = self.now_score;
             self.best_ans = self.now_ans.clone();
         }
     }
+    pub fn run(&mut self, observer: &mut dyn SearchObserver) {
+        if self.evaluations == 0 {
+            observer.on_step_start(0, "first step");
+            self.first_step();
+            observer.on_step_end(0, "first step", self.best_score);
+        }
+        for i in self.iteration..self.max_iter as usize {
+            if self.budget.is_exhausted(self.evaluations, self.best_score) {
+                // 打ち切った時点の状態も記録しておく
+                if i == 0 || i % REPORT_INTERVAL != 0 {
+                    observer.on_step_end(i, "anneal", self.best_score);
+                    observer.on_progress(&self.progress(i));
+                }
+                break;
+            }
+            let best_score = self.best_score;
+            self.one_step();
+            self.iteration = i + 1;
+            if self.best_score > best_score {
+                observer.on_new_best(&self.best_ans, self.best_score);
+            }
+            if (i + 1) % REPORT_INTERVAL == 0 || i + 1 == self.max_iter as usize {
+                observer.on_step_end(i + 1, "anneal", self.best_score);
+                observer.on_progress(&self.progress(i + 1));
+                observer.on_checkpoint(&self.state());
+            }
+        }
+        // 1回の反復は途中で打ち切られないので、止めた時点の状態からそのまま再開できる
+        observer.on_checkpoint(&self.state());
+        observer.on_finished(&self.best_ans, self.best_score);
+    }
+    pub fn state(&self) -> SolverState {
+        SolverState::Anneal(AnnealState {
+            iteration: self.iteration,
+            temperature: self.temperature,
+            now_ans: self.now_ans.clone(),
+            now_score: self.now_score,
+            best_ans: self.best_ans.clone(),
+            best_score: self.best_score,
+            evaluations: self.evaluations,
+            rng: RngState::new(&self.rng),
+        })
+    }
+    pub fn restore(&mut self, state: AnnealState) {
+        self.iteration = state.iteration;
+        self.temperature = state.temperature;
+        self.now_ans = state.now_ans;
+        self.now_score = state.now_score;
+        self.best_ans = state.best_ans;
+        self.best_score = state.best_score;
+        self.evaluations = state.evaluations;
+        self.rng = state.rng.to_rng();
+    }
+    pub fn progress(&self, iteration: usize) -> Progress {
+        Progress {
+            iteration,
+            evaluations: self.evaluations,
+            best_score: self.best_score,
+            current_score: self.now_score,
+            diversity: 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmark::{parse_amino_str, protein_from_aminos};
+
+    // アミノ酸が2つで向きがなくても止まらずに構造を返す
+    #[test]
+    fn handles_chains_without_directions() {
+        for sequence in ["HP", "HPH"] {
+            let protein = protein_from_aminos(&parse_amino_str(sequence));
+            let settings = SolverSettings {
+                iterations: Some(100),
+                ..SolverSettings::default()
+            };
+            let mut anneal = Annealing::new(&protein, 3, &settings, 1, Budget::default());
+            anneal.run(&mut ());
+            assert_eq!(
+                anneal.best_ans().clone().score().unwrap(),
+                anneal.best_score()
+            );
+        }
+    }
 }
@@ -1,425 +1,471 @@
-use crate::lib::{rotate_down, rotate_left, rotate_right, rotate_up, Amino, Direction, Protein};
-use core::num;
-use plotters::prelude::DrawingArea;
-use plotters::prelude::*;
+use crate::budget::Budget;
+use crate::checkpoint::{BeamState, RngState, SolverState};
+use crate::conformation::{evaluate, Conformation};
+use crate::init::Init;
+use crate::observer::{Progress, SearchObserver};
+use crate::packed::PackedMoves;
+use crate::protein::{Amino, Direction, Protein};
+use crate::refine::{replace, walks, PathTable, Refine};
+use crate::selection::{diverse, gaussian, sus, top_k, Selection};
+use crate::solve::{Solver, SolverSettings};
 use rand::seq::SliceRandom;
-use rand::thread_rng;
-use rand::Rng;
-use std::cmp::Ordering;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
 use std::collections::BinaryHeap;
-use std::collections::HashMap;
-use std::hash::{Hash, Hasher};
+use std::collections::{HashMap, HashSet};
 
-#[derive(Debug, PartialEq, PartialOrd)]
-struct OrdF32(f32);
-
-impl Eq for OrdF32 {}
-
-impl Ord for OrdF32 {
-    fn cmp(&self, other: &Self) -> Ordering {
-        // NaNの扱いに注意しつつ、PartialOrdの結果を利用
-        self.partial_cmp(other).unwrap_or(Ordering::Equal)
-    }
-}
-
-#[derive(Debug, PartialEq, PartialOrd)]
-struct FloatKey(f32);
-
-impl Eq for FloatKey {}
-
-impl Hash for FloatKey {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        // ビットパターンをハッシュ値に変換
-        self.0.to_bits().hash(state);
-    }
-}
-
-pub fn next_permutation<T: Ord>(a: &mut [T]) -> bool {
-    let Some(i) = a.windows(2).rposition(|w| w[0] < w[1]) else {
-        return false;
-    };
-    let j = a.iter().rposition(|x| x > &a[i]).unwrap();
-    a.swap(i, j);
-    a[i + 1..].reverse();
-    true
+// ビームに残す解。向きの列だけを詰めて持ち、座標は評価するときに求める
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BeamNode {
+    pub moves: PackedMoves,
+    pub score: i32,
 }
 
 pub struct Beam {
-    pub beam_width: i32,
-    pub nodes: Vec<Protein>,
-    pub best_score: i32,
-    pub best_ans: Protein,
-    pub num_direct: i32,
+    beam_width: usize,
+    nodes: Vec<BeamNode>,
+    best_score: i32,
+    best_ans: Protein,
+    num_direct: i32,
+    // 構造を評価した回数
+    evaluations: u64,
+    // 乱数はすべてここから取り、シードを固定すれば同じ結果になるようにする
+    // 状態をチェックポイントに保存できるようにChaCha12Rngを使う(StdRngと同じ乱数列になる)
+    rng: ChaCha12Rng,
+    // 終わった反復の回数。チェックポイントから再開したときはその続きから数える
+    iteration: usize,
+    // 時間や評価回数を使い切ったら、その時点のベストを残して探索を打ち切る
+    budget: Budget,
+    // 最初の解の作り方
+    init: Init,
+    // one_stepで次のビームに残す候補の選び方と、そのパラメータ
+    selection: Selection,
+    sharpness: f64,
+    diversity_weight: f64,
+    // local_one_stepで組み替える結合の数、組み替えるノードの間隔、経路の候補の作り方
+    window: usize,
+    stride: usize,
+    refine: Refine,
+    // Refine::Sampleで1つの窓について試す経路の数
+    refine_samples: usize,
+    // 長さwindowの経路の表。最初のlocal_one_stepで作る
+    paths: Option<PathTable>,
 }
 
+// (評価値, 候補, スコア)
+type Candidate = (i32, PackedMoves, i32);
+
 impl Beam {
+    // templateと同じ配列を、seedから作った乱数で探索する
+    pub fn new(
+        template: &Protein,
+        num_direct: i32,
+        settings: &SolverSettings,
+        seed: u64,
+        budget: Budget,
+    ) -> Beam {
+        Beam {
+            beam_width: settings.beam_width,
+            nodes: Vec::new(),
+            best_score: 0,
+            best_ans: template.clone(),
+            num_direct,
+            evaluations: 0,
+            rng: ChaCha12Rng::seed_from_u64(seed),
+            iteration: 0,
+            budget,
+            init: settings.init_for(Solver::Beam),
+            selection: settings.selection,
+            sharpness: settings.sharpness,
+            diversity_weight: settings.diversity_weight,
+            window: settings.window,
+            stride: settings.stride,
+            refine: settings.refine,
+            refine_samples: settings.refine_samples,
+            paths: None,
+        }
+    }
+    pub fn best_ans(&self) -> &Protein {
+        &self.best_ans
+    }
+    pub fn best_score(&self) -> i32 {
+        self.best_score
+    }
+    pub fn evaluations(&self) -> u64 {
+        self.evaluations
+    }
     pub fn first_step(&mut self) {
-        while true {
-            let mut rng = rand::thread_rng();
-            let mut direct = Vec::new();
-            let mut protein = self.best_ans.clone();
-            for _ in 0..protein.size - 2 {
-                let r = rng.gen_range(0..self.num_direct);
-                match r {
-                    0 => direct.push(Direction::S),
-                    1 => direct.push(Direction::L),
-                    2 => direct.push(Direction::R),
-                    3 => direct.push(Direction::U),
-                    4 => direct.push(Direction::D),
-                    _ => {}
+        let mut protein = self
+            .init
+            .build(&self.best_ans, self.num_direct, &mut self.rng);
+        // 初期解は自己回避歩行なので衝突しない
+        self.best_score = protein.score().expect("initial walks are self-avoiding");
+        self.evaluations += 1;
+        self.nodes = vec![BeamNode {
+            moves: PackedMoves::from_directions(&protein.direct, self.num_direct),
+            score: self.best_score,
+        }];
+        self.best_ans = protein;
+    }
+    fn aminos(&self) -> Vec<Amino> {
+        self.best_ans.aminos.iter().map(|a| a.amino).collect()
+    }
+    // 候補の座標を求めてProteinにする。ベストの更新と、候補を受け取るobserverへの通知にだけ使う
+    fn materialise(&self, aminos: &[Amino], moves: &PackedMoves) -> Option<Protein> {
+        Conformation::from_moves(aminos, &moves.to_directions())
+            .ok()
+            .map(|c| c.to_protein())
+    }
+    pub fn directions(&self) -> Vec<Direction> {
+        let mut directions = vec![Direction::S, Direction::L, Direction::R];
+        if self.num_direct == 5 {
+            directions.push(Direction::U);
+            directions.push(Direction::D);
+        }
+        directions
+    }
+    // nodeの連続するwindow個の結合を、両端の位置を変えずに組み替えた候補を列挙する
+    // 戻り値は(評価値, 候補, スコア)の列と評価した回数。seedはRefine::Sampleで経路を選ぶのに使う
+    fn local_candidates(
+        &self,
+        node: &BeamNode,
+        aminos: &[Amino],
+        seed: u64,
+    ) -> (Vec<Candidate>, u64) {
+        let mut candidates = Vec::new();
+        let mut evaluations = 0;
+        let mut rng = ChaCha12Rng::seed_from_u64(seed);
+        let Ok(conformation) = Conformation::from_moves(aminos, &node.moves.to_directions()) else {
+            return (candidates, evaluations);
+        };
+        let pos = conformation.positions();
+        let steps = conformation.steps();
+        let window = self.window;
+        // 最初の結合は(1, 0, 0)に固定されているので、1番目の結合からの窓を考える
+        for start in 1..steps.len().saturating_sub(window) + 1 {
+            let from = pos[start];
+            let to = pos[start + window];
+            let offset = (to.0 - from.0, to.1 - from.1, to.2 - from.2);
+            let paths: Vec<Vec<(i32, i32, i32)>> = match self.refine {
+                Refine::Exhaustive => self.paths.as_ref().unwrap().get(offset).to_vec(),
+                Refine::Sample => {
+                    let table = self.paths.as_ref().unwrap().get(offset);
+                    table
+                        .choose_multiple(&mut rng, self.refine_samples)
+                        .cloned()
+                        .collect()
+                }
+                Refine::Walk => {
+                    // 窓の中のアミノ酸以外が占めている位置は通れない
+                    let occupied: HashSet<(i32, i32, i32)> = pos
+                        .iter()
+                        .enumerate()
+                        .filter(|&(i, _)| i <= start || i >= start + window)
+                        .map(|(_, &p)| p)
+                        .collect();
+                    walks(
+                        from,
+                        to,
+                        window,
+                        &occupied,
+                        steps[start - 1],
+                        steps.get(start + window).copied(),
+                        self.num_direct,
+                    )
+                }
+            };
+            for path in paths {
+                let Some(direct) =
+                    replace(conformation.moves(), &steps, start, &path, self.num_direct)
+                else {
+                    continue;
+                };
+                evaluations += 1;
+                if let Some((value, score)) = evaluate(aminos, &direct) {
+                    let moves = PackedMoves::from_directions(&direct, self.num_direct);
+                    candidates.push((value, moves, score));
                 }
-            }
-            protein.direct = direct.clone();
-            let mut score = protein.calc_predict();
-            if score != -1 {
-                self.best_score = score;
-                self.best_ans = protein.clone();
-                break;
             }
         }
-        self.nodes = vec![self.best_ans.clone()];
+        (candidates, evaluations)
+    }
+    pub fn is_exhausted(&self) -> bool {
+        self.budget.is_exhausted(self.evaluations, self.best_score)
     }
-    pub fn local_one_step(&mut self) {
-        let step_size = 5;
+    // budgetを使い切って打ち切った場合はfalseを返す
+    pub fn local_one_step(&mut self, observer: &mut dyn SearchObserver) -> bool {
+        if self.is_exhausted() {
+            return false;
+        }
+        if self.refine != Refine::Walk && self.paths.is_none() {
+            self.paths = Some(PathTable::new(self.window, self.num_direct));
+        }
+        // 候補の列挙は並列に行い、結果はノードの順に取り込むことでスレッド数によらず同じ結果にする
+        // 乱数もノードごとのシードを先に決めておく
+        let aminos = self.aminos();
+        let nodes: Vec<(&BeamNode, u64)> = self
+            .nodes
+            .iter()
+            .step_by(self.stride.max(1))
+            .map(|node| (node, self.rng.gen()))
+            .collect();
+        let results: Vec<(Vec<Candidate>, u64)> = nodes
+            .par_iter()
+            .map(|&(node, seed)| self.local_candidates(node, &aminos, seed))
+            .collect();
         let mut heap = BinaryHeap::new();
         let mut map = HashMap::new();
-        for i in (0..self.nodes.len()).step_by(3) {
-            let mut node = self.nodes[i].clone();
-            for c in 0..self.best_ans.direct.len() - step_size {
-                let mut directions = vec![Direction::S, Direction::L, Direction::R];
-
-                if self.num_direct == 5 {
-                    directions.push(Direction::U);
-                    directions.push(Direction::D);
-                }
-                let first_pos = node.clone().aminos[c].pos;
-                let target_pos = node.clone().aminos[c + step_size].pos;
-
-                let x_diff = target_pos.0 - first_pos.0;
-                let y_diff = target_pos.1 - first_pos.1;
-                let z_diff = target_pos.2 - first_pos.2;
-                let mut residue =
-                    step_size as u32 - (x_diff.abs() + y_diff.abs() + z_diff.abs()) as u32;
-
-                let mut d = Vec::new();
-                for _ in 0..x_diff.abs() {
-                    if x_diff > 0 {
-                        d.push((1, 0, 0));
-                    } else {
-                        d.push((-1, 0, 0));
-                    }
-                }
-                for _ in 0..y_diff.abs() {
-                    if y_diff > 0 {
-                        d.push((0, 1, 0));
-                    } else {
-                        d.push((0, -1, 0));
-                    }
-                }
-                for _ in 0..z_diff.abs() {
-                    if z_diff > 0 {
-                        d.push((0, 0, 1));
-                    } else {
-                        d.push((0, 0, -1));
-                    }
-                }
-                let mut ds = Vec::new();
-                let mut base = 2 as u32;
-                if self.num_direct == 5 {
-                    base = 3 as u32;
-                }
-                for i in 0..(base.pow(residue / 2)) {
-                    let mut _d = d.clone();
-                    let mut cnt = i;
-                    let mut _residue = residue;
-                    while _residue > 0 {
-                        let r = cnt % base;
-                        cnt /= base;
-                        _residue -= 2;
-                        if r == 0 {
-                            _d.push((1, 0, 0));
-                            _d.push((-1, 0, 0));
-                        } else if r == 1 {
-                            _d.push((0, 1, 0));
-                            _d.push((0, -1, 0));
-                        } else if self.num_direct == 5 && r == 2 {
-                            _d.push((0, 0, 1));
-                            _d.push((0, 0, -1));
-                        }
-                    }
-                    ds.push(_d);
-                }
-                // println!("{:?}", ds);
-                let mut directions = vec![Direction::S, Direction::L, Direction::R];
-                if self.num_direct == 5 {
-                    directions.push(Direction::U);
-                    directions.push(Direction::D);
-                }
-
-                for i in 0..ds.len() {
-                    let mut d = ds[i].clone();
-                    while true {
-                        let mut new_node = node.clone();
-                        for j in c..c + step_size {
-                            let now_direct = (d[j - c].0, d[j - c].1, d[j - c].2);
-                            new_node.aminos[j + 2].pos = (
-                                new_node.aminos[j + 1].pos.0 + now_direct.0,
-                                new_node.aminos[j + 1].pos.1 + now_direct.1,
-                                new_node.aminos[j + 1].pos.2 + now_direct.2,
-                            );
-                            let prev_direct = (
-                                new_node.aminos[j + 1].pos.0 - new_node.aminos[j].pos.0,
-                                new_node.aminos[j + 1].pos.1 - new_node.aminos[j].pos.1,
-                                new_node.aminos[j + 1].pos.2 - new_node.aminos[j].pos.2,
-                            );
-                            if rotate_left(prev_direct) == now_direct {
-                                new_node.direct[j] = Direction::L;
-                            } else if rotate_right(prev_direct) == now_direct {
-                                new_node.direct[j] = Direction::R;
-                            } else if self.num_direct == 5 && rotate_up(prev_direct) == now_direct {
-                                new_node.direct[j] = Direction::U;
-                            } else if self.num_direct == 5 && rotate_down(prev_direct) == now_direct
-                            {
-                                new_node.direct[j] = Direction::D;
-                            } else {
-                                new_node.direct[j] = Direction::S;
-                            }
-                        }
-                        let score = new_node.calc_predict();
-                        if score != -1 {
-                            let mut value = new_node.get_value();
-                            if !map.contains_key(&value) {
-                                map.insert(value, (new_node, score));
-                                heap.push(value);
-                            }
-                        }
-                        if next_permutation(&mut d) == false {
-                            break;
-                        }
-                    }
+        for (candidates, evaluations) in results {
+            self.evaluations += evaluations;
+            for (value, moves, score) in candidates {
+                if let Entry::Vacant(e) = map.entry(value) {
+                    e.insert((moves, score));
+                    heap.push(value);
                 }
             }
         }
-        let mut new_nodes: Vec<Protein> = Vec::new();
+        let mut new_nodes: Vec<BeamNode> = Vec::new();
         for i in 0..self.beam_width {
             if let Some(value) = heap.pop() {
-                let (node, score) = map.remove(&value).unwrap();
-                if i == 0 {
-                    if self.best_score < score {
+                let (moves, score) = map.remove(&value).unwrap();
+                if i == 0 && self.best_score < score {
+                    if let Some(node) = self.materialise(&aminos, &moves) {
                         self.best_score = score;
-                        self.best_ans = node.clone();
+                        self.best_ans = node;
+                        observer.on_new_best(&self.best_ans, score);
+                    }
+                }
+                if observer.wants_candidates() {
+                    if let Some(node) = self.materialise(&aminos, &moves) {
+                        observer.on_candidate_accepted(&node, score);
                     }
                 }
-                new_nodes.push(node.clone());
+                new_nodes.push(BeamNode { moves, score });
             }
         }
         self.nodes = new_nodes;
+        true
     }
-    pub fn one_step(&mut self) {
+    // budgetを使い切って途中で打ち切った場合はfalseを返す
+    pub fn one_step(&mut self, observer: &mut dyn SearchObserver) -> bool {
+        let directions = self.directions();
+        let aminos = self.aminos();
         for c in 0..self.best_ans.direct.len() {
+            if self.is_exhausted() {
+                return false;
+            }
             let mut heap = BinaryHeap::new();
             let mut map = HashMap::new();
-            let mut directions = vec![Direction::S, Direction::L, Direction::R];
-
-            if self.num_direct == 5 {
-                directions.push(Direction::U);
-                directions.push(Direction::D);
-            }
 
-            for i in 0..self.nodes.len() {
-                let mut node = self.nodes[i].clone();
-                for j in 0..directions.len() {
-                    let direct = directions[j];
-                    let mut new_node = node.clone();
-                    new_node.direct[c] = direct;
-                    let score = new_node.calc_predict();
-                    if score != -1 {
-                        let mut value = new_node.get_value();
-                        if !map.contains_key(&value) {
-                            map.insert(value, (new_node, score));
-                            heap.push(value);
+            let results: Vec<Vec<Candidate>> = self
+                .nodes
+                .par_iter()
+                .map(|node| {
+                    let mut candidates = Vec::new();
+                    let mut direct = node.moves.to_directions();
+                    for &d in directions.iter() {
+                        direct[c] = d;
+                        if let Some((value, score)) = evaluate(&aminos, &direct) {
+                            let mut moves = node.moves.clone();
+                            moves.set(c, d);
+                            candidates.push((value, moves, score));
                         }
                     }
+                    candidates
+                })
+                .collect();
+            self.evaluations += (self.nodes.len() * directions.len()) as u64;
+            for (value, moves, score) in results.into_iter().flatten() {
+                // 多様性を考えて選ぶときは、評価値が同じでも構造が違えば別の候補として残す
+                // 詰めた向きの列の大小は向きの列の辞書順と同じなので、同じ評価値の中での順番も変わらない
+                let key = if self.selection == Selection::Diverse {
+                    (value, moves.clone())
+                } else {
+                    (value, PackedMoves::default())
+                };
+                if let Entry::Vacant(e) = map.entry(key.clone()) {
+                    e.insert((moves, score));
+                    heap.push(key);
                 }
             }
-            let mut new_nodes: Vec<Protein> = Vec::new();
-            let mut x = 0;
-            while heap.len() > 0 {
-                if let Some(value) = heap.pop() {
-                    let (node, score) = map.remove(&value).unwrap();
-                    if self.best_score < score {
+            // 評価値の良い順に並べてから選ぶ
+            let mut values = Vec::new();
+            let mut nodes = Vec::new();
+            while let Some(key) = heap.pop() {
+                let (moves, score) = map.remove(&key).unwrap();
+                if self.best_score < score {
+                    if let Some(best) = self.materialise(&aminos, &moves) {
                         self.best_score = score;
-                        self.best_ans = node.clone();
-                    }
-                    let exp = (-8.0 * (x * x) as f32
-                        / ((self.beam_width * self.beam_width) as f32))
-                        .exp();
-                    let mut rng = rand::thread_rng();
-                    let prob = rng.gen_range(0.0..1.0) as f32;
-                    if prob < exp {
-                        x += 1;
-                        new_nodes.push(node.clone());
+                        self.best_ans = best;
+                        observer.on_new_best(&self.best_ans, score);
                     }
                 }
-                if new_nodes.len() >= self.beam_width as usize {
-                    break;
+                values.push(key.0);
+                nodes.push(BeamNode { moves, score });
+            }
+            let width = self.beam_width;
+            let chosen = match self.selection {
+                Selection::TopK => top_k(&values, width),
+                Selection::Gaussian => gaussian(&values, width, self.sharpness, &mut self.rng),
+                Selection::Sus => sus(&values, width, &mut self.rng),
+                Selection::Diverse => {
+                    let contacts: Vec<HashSet<(usize, usize)>> = nodes
+                        .iter()
+                        .map(|node| {
+                            Conformation::from_moves(&aminos, &node.moves.to_directions())
+                                .map(|c| c.hh_contacts().into_iter().collect())
+                                .unwrap_or_default()
+                        })
+                        .collect();
+                    diverse(&values, &contacts, width, self.diversity_weight)
+                }
+            };
+            let wants_candidates = observer.wants_candidates();
+            let mut new_nodes: Vec<BeamNode> = Vec::new();
+            for i in chosen {
+                if wants_candidates {
+                    if let Some(node) = self.materialise(&aminos, &nodes[i].moves) {
+                        observer.on_candidate_accepted(&node, nodes[i].score);
+                    }
                 }
-                x += 1;
+                new_nodes.push(nodes[i].clone());
             }
             self.nodes = new_nodes;
         }
+        true
     }
-    pub fn vis_one_step(&mut self, vis_step: u8, dim: u8) {
-        let area = BitMapBackend::gif(
-            "./animated.gif", // アニメーションファイルの名前。この名前で保存される
-            (1200, 800),      //  グラフのサイズ（幅x高さ)
-            1,                //  1フレームの時間。単位は [ms]
-        )
-        .unwrap()
-        .into_drawing_area();
-        for _ in 0..4 {
+    // ランダムな初期解から始めて、one_stepとlocal_one_stepをiterations回繰り返す
+    // 初期解を変えたやり直しはrestart::RestartManagerで行う
+    // budgetを使い切ったらその時点で打ち切る。restoreした後ならその続きから始める
+    pub fn run(&mut self, iterations: usize, observer: &mut dyn SearchObserver) {
+        if self.evaluations == 0 {
+            observer.on_step_start(0, "first step");
             self.first_step();
-            println!("first step completed");
-
-            for i in 0..10 {
-                for c in 0..self.best_ans.direct.len() {
-                    let mut heap = BinaryHeap::new();
-                    let mut map = HashMap::new();
-                    let mut directions = vec![Direction::S, Direction::L, Direction::R];
-
-                    if self.num_direct == 5 {
-                        directions.push(Direction::U);
-                        directions.push(Direction::D);
-                    }
-
-                    for i in 0..self.nodes.len() {
-                        let mut node = self.nodes[i].clone();
-                        for j in 0..directions.len() {
-                            let direct = directions[j];
-                            let mut new_node = node.clone();
-                            new_node.direct[c] = direct;
-                            let score = new_node.calc_predict();
-                            if score != -1 {
-                                let mut value = new_node.get_value();
-                                if !map.contains_key(&value) {
-                                    map.insert(value, (new_node, score));
-                                    heap.push(value);
-                                }
-                            }
-                        }
-                    }
-                    let mut new_nodes: Vec<Protein> = Vec::new();
-                    let mut x = 0;
-                    let mut count = 0;
-                    while heap.len() > 0 {
-                        if let Some(value) = heap.pop() {
-                            let (node, score) = map.remove(&value).unwrap();
-                            if self.best_score < score {
-                                self.best_score = score;
-                                self.best_ans = node.clone();
-                            }
-                            let exp = (-8.0 * (x * x) as f32
-                                / ((self.beam_width * self.beam_width) as f32))
-                                .exp();
-                            let mut rng = rand::thread_rng();
-                            let prob = rng.gen_range(0.0..1.0) as f32;
-                            if prob < exp {
-                                x += 1;
-                                new_nodes.push(node.clone());
-                                count += 1;
-                                if count == vis_step {
-                                    count = 0;
-                                    //visualize の処理
-                                    let mut data = Vec::new();
-                                    data.push((0, 0, 0, node.aminos[0].amino));
-                                    if dim == 2 {
-                                        data.push((5, 0, 0, node.aminos[1].amino));
-                                    } else {
-                                        data.push((10, 0, 0, node.aminos[1].amino));
-                                    }
-                                    for i in 0..node.direct.len() {
-                                        let mut prev_direction = (
-                                            data[i + 1].0 - data[i].0,
-                                            data[i + 1].1 - data[i].1,
-                                            data[i + 1].2 - data[i].2,
-                                        );
-                                        let (x, y, z, a) = match node.direct[i] {
-                                            Direction::S => (
-                                                data[i + 1].0 + prev_direction.0,
-                                                data[i + 1].1 + prev_direction.1,
-                                                data[i + 1].2 + prev_direction.2,
-                                                node.aminos[i + 2].amino,
-                                            ),
-                                            Direction::L => (
-                                                data[i + 1].0 + rotate_left(prev_direction).0,
-                                                data[i + 1].1 + rotate_left(prev_direction).1,
-                                                data[i + 1].2 + rotate_left(prev_direction).2,
-                                                node.aminos[i + 2].amino,
-                                            ),
-                                            Direction::R => (
-                                                data[i + 1].0 + rotate_right(prev_direction).0,
-                                                data[i + 1].1 + rotate_right(prev_direction).1,
-                                                data[i + 1].2 + rotate_right(prev_direction).2,
-                                                node.aminos[i + 2].amino,
-                                            ),
-                                            Direction::U => (
-                                                data[i + 1].0 + rotate_up(prev_direction).0,
-                                                data[i + 1].1 + rotate_up(prev_direction).1,
-                                                data[i + 1].2 + rotate_up(prev_direction).2,
-                                                node.aminos[i + 2].amino,
-                                            ),
-                                            Direction::D => (
-                                                data[i + 1].0 + rotate_down(prev_direction).0,
-                                                data[i + 1].1 + rotate_down(prev_direction).1,
-                                                data[i + 1].2 + rotate_down(prev_direction).2,
-                                                node.aminos[i + 2].amino,
-                                            ),
-                                        };
-                                        data.push((x, y, z, a));
-                                    }
+            observer.on_step_end(0, "first step", self.best_score);
+            observer.on_checkpoint(&self.state());
+        }
 
-                                    area.fill(&WHITE).unwrap();
+        for i in self.iteration..iterations {
+            if self.is_exhausted() {
+                // 1回も反復できなかった場合も初期解の状態は記録しておく
+                if i == 0 {
+                    observer.on_progress(&self.progress(0));
+                }
+                break;
+            }
+            observer.on_step_start(i, "one step");
+            let completed = self.one_step(observer);
+            observer.on_step_end(i, "one step", self.best_score);
+            observer.on_step_start(i, "local step");
+            let completed = self.local_one_step(observer) && completed;
+            observer.on_step_end(i, "local step", self.best_score);
+            observer.on_progress(&self.progress(i + 1));
+            // 途中で打ち切った反復の状態は再開に使えないので、最後まで終わった反復だけ保存する
+            if completed {
+                self.iteration = i + 1;
+                observer.on_checkpoint(&self.state());
+            }
+        }
+        observer.on_finished(&self.best_ans, self.best_score);
+    }
+    pub fn state(&self) -> SolverState {
+        SolverState::Beam(BeamState {
+            iteration: self.iteration,
+            nodes: self.nodes.clone(),
+            best_ans: self.best_ans.clone(),
+            best_score: self.best_score,
+            evaluations: self.evaluations,
+            rng: RngState::new(&self.rng),
+        })
+    }
+    pub fn restore(&mut self, state: BeamState) {
+        self.iteration = state.iteration;
+        self.nodes = state.nodes;
+        self.best_ans = state.best_ans;
+        self.best_score = state.best_score;
+        self.evaluations = state.evaluations;
+        self.rng = state.rng.to_rng();
+    }
+    pub fn progress(&self, iteration: usize) -> Progress {
+        Progress {
+            iteration,
+            evaluations: self.evaluations,
+            best_score: self.best_score,
+            current_score: self.nodes.iter().map(|node| node.score).max().unwrap_or(0),
+            diversity: self.diversity(),
+        }
+    }
+    // ビーム内の解どうしで向きが異なる箇所の割合の平均
+    pub fn diversity(&self) -> f64 {
+        let len = self.nodes.first().map_or(0, |node| node.moves.len());
+        mean_distance(self.nodes.len(), len, |i, j| {
+            self.nodes[i].moves.distance(&self.nodes[j].moves)
+        })
+    }
+}
 
-                                    let mut chart = ChartBuilder::on(&area)
-                                        .margin(20)
-                                        .caption("protein structure", ("sans-serif", 40))
-                                        .build_cartesian_3d(-100..100, -100..100, -100..100)
-                                        .unwrap();
+// 解どうしで向きが異なる箇所の割合の平均(0~1)
+pub fn diversity(nodes: &[Protein]) -> f64 {
+    let len = nodes.first().map_or(0, |node| node.direct.len());
+    mean_distance(nodes.len(), len, |i, j| distance(&nodes[i], &nodes[j]))
+}
 
-                                    chart.configure_axes().draw().unwrap();
+// n個の解のすべての組について、向きが異なる箇所の数をdistanceで求めて割合の平均を取る
+fn mean_distance(n: usize, len: usize, distance: impl Fn(usize, usize) -> usize) -> f64 {
+    if n < 2 || len == 0 {
+        return 0.0;
+    }
+    let mut total = 0;
+    for i in 0..n {
+        for j in i + 1..n {
+            total += distance(i, j);
+        }
+    }
+    let pairs = n * (n - 1) / 2;
+    total as f64 / (pairs * len) as f64
+}
 
-                                    chart
-                                        .draw_series(data.iter().map(|&(x, y, z, a)| {
-                                            if (a == Amino::H) {
-                                                TriangleMarker::new((x, y, z), 5, &RED)
-                                            } else {
-                                                TriangleMarker::new((x, y, z), 5, &BLUE)
-                                            }
-                                        }))
-                                        .unwrap();
+// 2つの解で向きが異なる箇所の数
+pub fn distance(a: &Protein, b: &Protein) -> usize {
+    a.direct
+        .iter()
+        .zip(b.direct.iter())
+        .filter(|(x, y)| x != y)
+        .count()
+}
 
-                                    chart
-                                        .draw_series(LineSeries::new(
-                                            data.iter().map(|&(x, y, z, _)| (x, y, z)),
-                                            &BLACK.mix(0.3),
-                                        ))
-                                        .unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmark::{parse_amino_str, protein_from_aminos};
 
-                                    // グラフを更新する
-                                    area.present().unwrap();
-                                }
-                            }
-                        }
-                        if new_nodes.len() >= self.beam_width as usize {
-                            break;
-                        }
-                        x += 1;
-                    }
-                    self.nodes = new_nodes;
-                }
+    fn run_with_threads(threads: usize, num_direct: i32) -> (Protein, i32) {
+        let protein = protein_from_aminos(&parse_amino_str("HPHPPHHPHPPHPHHPPHPH"));
+        let settings = SolverSettings {
+            beam_width: 30,
+            ..SolverSettings::default()
+        };
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        pool.install(|| {
+            let mut beam = Beam::new(&protein, num_direct, &settings, 7, Budget::default());
+            beam.run(2, &mut ());
+            (beam.best_ans().clone(), beam.best_score())
+        })
+    }
 
-                println!("one step completed");
-                println!("beam {}: {}", i, self.best_score);
+    // 候補の展開を並列にしても、シードが同じならスレッド数によらず同じ結果になる
+    #[test]
+    fn same_result_for_any_thread_count() {
+        for num_direct in [3, 5] {
+            let single = run_with_threads(1, num_direct);
+            for threads in [2, 4] {
+                assert_eq!(run_with_threads(threads, num_direct), single);
             }
         }
     }
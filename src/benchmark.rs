@@ -0,0 +1,174 @@
+use crate::error::Error;
+use crate::protein::{Amino, AminoAcid, Direction, Protein};
+
+pub static PROTEIN_DATA: [&str; 22] = [
+    "H4",
+    "(HP)2PH2PHP2HPH2P2HPH",
+    "H2(P2H)7H",
+    "P2HP2(H2P4)3H2",
+    "P3H2P2H2P5H7P2H2P4H2P2HP2",
+    "P2H(P2H2)2P5H10P6(H2P2)2HP2H5",
+    "H2(PH)3PH4PH(P3H)2P4H(P3H)2PHPH4(HP)3H2",
+    "P2H3PH8P3H10PHP3H12P4H6PH2PHP",
+    "H12(PH)2(P2H2)2P2HP2H2PPH2P2HP2(H2P2)2(HP)2H12",
+    "H4P4H12P6(H12P3)3HP2(H2P2)2HPH",
+    "P3H2P2H4P2H3(PH2)2PH4P8H6P2H6P9HPH2PH11P2H3PH2PHP2HPH3P6H3",
+    "P6HPH2P5H3PH5PH2P4H2P2H2PH5PH10PH2PH7p11H7P2HPH3P6HPH2",
+    "HPH2P2H4PH3P2H2P2HPH2PHPH2P2H2P3HP8H2",
+    "H4PH2PH5P2HP2H2P2HP6HP2HP3HP2H2P2H3PH",
+    "PHPH2PH6P2HPHP2HPH2(PH)2P3H(P2H2)2P2HPHP2HP",
+    "PHPH2P2HPH3P2H2PH2P3H5P2HPH2(PH)2P4HP2(HP)2",
+    "P2HP3HPH4P2H4PH2PH3P2(HP)2HP2HP6H2PH2PH",
+    "H3P3H2PH(PH2)3PHP7HPHP2HP3HP2H6PH",
+    "PHP4HPH3PHPH4PH2PH2P3HPHP3H3(P2H2)2P3H",
+    "PH2PH3PH4P2H3P6HPH2P2H2PHP3H2(PH)2PH2P3",
+    "(PH)2P4(HP)2HP2HPH6P2H3PHP2HPH2P2HPH3P4H",
+    "PH2P6H2P3H3PHP2HPH2(P2H)2P2H2P2H7P2H2",
+];
+
+pub fn parse_amino_str(input: &str) -> Vec<Amino> {
+    let mut result = Vec::new();
+    let mut i = 0;
+    let chars: Vec<char> = input.chars().collect();
+    while i < chars.len() {
+        match chars[i] {
+            'H' => result.push(Amino::H),
+            'P' => result.push(Amino::P),
+            '(' => {
+                let mut j = i + 1;
+                while j < chars.len() && chars[j] != ')' {
+                    j += 1;
+                }
+                let sequence = &chars[i + 1..j];
+                let inner_result = parse_amino_str(&sequence.iter().collect::<String>());
+                i = j + 1;
+                let mut multiplier = 0;
+                while i < chars.len() && chars[i].is_numeric() {
+                    multiplier = multiplier * 10 + chars[i].to_digit(10).unwrap() as i32;
+                    i += 1;
+                }
+                for _ in 0..multiplier {
+                    result.append(&mut inner_result.clone());
+                }
+                continue;
+            }
+            c if c.is_ascii_digit() => {
+                let mut multiplier = 0;
+                while i < chars.len() && chars[i].is_numeric() {
+                    multiplier = multiplier * 10 + chars[i].to_digit(10).unwrap() as i32;
+                    i += 1;
+                }
+                if let Some(&last) = result.last() {
+                    for _ in 0..multiplier - 1 {
+                        result.push(last);
+                    }
+                }
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    result
+}
+
+pub static SAMPLE_PROTEIN_POINTS: [i32; 22] = [
+    4, 9, 9, 8, 14, 23, 21, 36, 42, 53, 50, 48, 32, 34, 34, 33, 32, 32, 32, 31, 34, 33,
+];
+// 論文の最適値は1~11番が2次元、12番以降が3次元のもの
+pub fn known_optimum(id: usize, dim: u8) -> Option<i32> {
+    let is_3d_benchmark = id >= 12;
+    if id == 0 || id >= SAMPLE_PROTEIN_POINTS.len() || is_3d_benchmark != (dim == 3) {
+        return None;
+    }
+    Some(SAMPLE_PROTEIN_POINTS[id])
+}
+// 配列から、最初の2つのアミノ酸だけを置いた向きの決まっていないタンパク質を作る
+pub fn protein_from_aminos(aminos: &[Amino]) -> Protein {
+    let mut amino_acids = Vec::new();
+    for &amino in aminos.iter() {
+        amino_acids.push(AminoAcid {
+            amino,
+            pos: (0, 0, 0),
+        });
+    }
+    let mut protein = Protein {
+        size: aminos.len() as i32,
+        aminos: amino_acids,
+        direct: Vec::new(),
+        predict: 0,
+    };
+    protein.aminos[0].pos = (0, 0, 0);
+    protein.aminos[1].pos = (1, 0, 0);
+    protein
+}
+// コマンドラインなどから与えられた配列。parse_amino_strと違って知らない文字は読み飛ばさない
+pub fn parse_sequence(input: &str) -> Result<Protein, Error> {
+    if let Some(c) = input
+        .chars()
+        .find(|c| !matches!(c, 'H' | 'P' | '(' | ')') && !c.is_ascii_digit())
+    {
+        return Err(Error::Parse(format!(
+            "unexpected character in sequence: {:?}",
+            c
+        )));
+    }
+    let aminos = parse_amino_str(input);
+    if aminos.len() < 2 {
+        return Err(Error::Parse(format!(
+            "sequence must have at least 2 residues: {:?}",
+            input
+        )));
+    }
+    Ok(protein_from_aminos(&aminos))
+}
+// 配列の文字列があればそれを、なければ組み込みのベンチマークのid番目を読む
+pub fn load_protein(sequence: Option<&str>, id: usize) -> Result<Protein, Error> {
+    if let Some(sequence) = sequence {
+        return parse_sequence(sequence);
+    }
+    let mut proteins = setup();
+    if id >= proteins.len() {
+        return Err(Error::Parse(format!(
+            "unknown benchmark id: {} (0 to {})",
+            id,
+            proteins.len() - 1
+        )));
+    }
+    Ok(proteins.swap_remove(id))
+}
+// proteinと同じ配列の組み込みのベンチマークの番号
+pub fn find_benchmark(protein: &Protein) -> Option<usize> {
+    let aminos: Vec<Amino> = protein.aminos.iter().map(|a| a.amino).collect();
+    PROTEIN_DATA
+        .iter()
+        .position(|amino_str| parse_amino_str(amino_str) == aminos)
+}
+pub fn setup() -> Vec<Protein> {
+    let mut sample_proteins: Vec<Protein> = PROTEIN_DATA
+        .iter()
+        .map(|amino_str| protein_from_aminos(&parse_amino_str(amino_str)))
+        .collect();
+    sample_proteins[0].direct = vec![Direction::L, Direction::L];
+    sample_proteins[1].direct = vec![
+        Direction::L,
+        Direction::S,
+        Direction::L,
+        Direction::L,
+        Direction::R,
+        Direction::R,
+        Direction::L,
+        Direction::R,
+        Direction::L,
+        Direction::L,
+        Direction::S,
+        Direction::L,
+        Direction::R,
+        Direction::R,
+        Direction::L,
+        Direction::L,
+        Direction::S,
+        Direction::L,
+    ];
+    sample_proteins
+}
@@ -0,0 +1,153 @@
+use crate::protein::{Amino, Protein};
+
+// 格子上のm点の間で隣接する組の数の上限(dim次元)
+pub fn max_edges(m: usize, dim: usize) -> usize {
+    match (m, dim) {
+        (0, _) => 0,
+        (_, 1) => m - 1,
+        // 正方形に近い形が最も多い
+        (_, 2) => 2 * m - (2.0 * (m as f64).sqrt() - 1e-9).ceil() as usize,
+        _ => layer_bound(m, dim, m, m),
+    }
+}
+
+// n点をlayers枚以下の層(1枚にcap点以下)に分けたときの隣接する組の数の上限
+// 層の中はdim-1次元の上限、隣り合う層の間は少ない方の点の数を超えない
+pub fn layer_bound(n: usize, dim: usize, layers: usize, cap: usize) -> usize {
+    let cap = cap.min(n);
+    let inner: Vec<usize> = (0..=cap).map(|m| max_edges(m, dim - 1)).collect();
+    // best[r][last]: 残りr点を、直前の層がlast点のときに置いて増やせる組の数(層の数はfor文で増やす)
+    let mut best = vec![vec![None; cap + 1]; n + 1];
+    best[0] = vec![Some(0); cap + 1];
+    for _ in 0..layers {
+        let mut next = best.clone();
+        for r in 1..=n {
+            for (last, value) in next[r].iter_mut().enumerate() {
+                let candidate = (1..=cap.min(r))
+                    .filter_map(|s| best[r - s][s].map(|rest| inner[s] + last.min(s) + rest))
+                    .max();
+                if candidate > *value {
+                    *value = candidate;
+                }
+            }
+        }
+        best = next;
+    }
+    best[n][0].unwrap_or(0)
+}
+
+// H-H結合の数の上限
+#[derive(Clone, Copy, Debug)]
+pub struct Bounds {
+    // 格子は2色に塗り分けられるので、H-H結合は番号の偶奇が異なるHの間にしかできない
+    // 偶数番目と奇数番目のHそれぞれが作れる結合の数の合計の少ない方
+    pub parity: i32,
+    // Hを置く点の集合で隣接する組の数の上限から、鎖上で隣り合うHの組の数を引いたもの
+    pub core: i32,
+    // 探索で示した上限(hcoreがまだ否定できていない最も良いスコアや、列挙し終えた最適解)
+    pub search: Option<i32>,
+}
+
+impl Bounds {
+    pub fn new(protein: &Protein, dim: u8) -> Bounds {
+        let dim = dim as usize;
+        let hp: Vec<bool> = protein.aminos.iter().map(|a| a.amino == Amino::H).collect();
+        let len = hp.len();
+        // 鎖の途中のアミノ酸は両隣と結合しているので、結合に使える隣接点は2×dim-2個。端は1個多い
+        let capacity = |i: usize| {
+            if i == 0 || i + 1 == len {
+                2 * dim - 1
+            } else {
+                2 * dim - 2
+            }
+        };
+        let mut sides = [0; 2];
+        let mut counts = [0; 2];
+        for (i, &h) in hp.iter().enumerate() {
+            if h {
+                sides[i % 2] += capacity(i);
+                counts[i % 2] += 1;
+            }
+        }
+        let parity = sides[0].min(sides[1]);
+        // コアの隣接する組も偶数番目と奇数番目のHの間にしかないので、少ない方の色の点の隣接点の数も超えない
+        let n = counts[0] + counts[1];
+        let edges = max_edges(n, dim).min(2 * dim * counts[0].min(counts[1]));
+        let bonded = hp.windows(2).filter(|w| w[0] && w[1]).count();
+        Bounds {
+            parity: parity as i32,
+            core: edges.saturating_sub(bonded) as i32,
+            search: None,
+        }
+    }
+    // 探索で示した上限も使う。探索が最適解を示していれば、上限はそのスコアになる
+    pub fn with_search(self, bound: Option<i32>) -> Bounds {
+        Bounds {
+            search: match (self.search, bound) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+            ..self
+        }
+    }
+    pub fn best(&self) -> i32 {
+        self.parity
+            .min(self.core)
+            .min(self.search.unwrap_or(i32::MAX))
+    }
+    // 上限とscoreとの差を表示用にまとめる
+    pub fn report(&self, score: i32) -> String {
+        let bound = self.best();
+        let gap = bound - score;
+        let ratio = if bound > 0 {
+            gap as f64 / bound as f64 * 100.0
+        } else {
+            0.0
+        };
+        let search = self
+            .search
+            .map_or(String::new(), |s| format!(", search {}", s));
+        format!(
+            "upper bound: {} (parity {}, core {}{}), gap: {} ({:.1}%)",
+            bound, self.parity, self.core, search, gap, ratio
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmark::protein_from_aminos;
+    use crate::budget::Budget;
+    use crate::enumerate::Enumerator;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha12Rng;
+
+    // 短いランダムな配列で、上限が全ての構造を調べた最適解を下回らない
+    #[test]
+    fn bounds_are_not_below_the_optimum() {
+        let mut rng = ChaCha12Rng::seed_from_u64(1);
+        for dim in [2u8, 3] {
+            let num_direct = if dim == 2 { 3 } else { 5 };
+            for _ in 0..30 {
+                let len = rng.gen_range(4..=if dim == 2 { 12 } else { 9 });
+                let aminos: Vec<Amino> = (0..len)
+                    .map(|_| {
+                        if rng.gen_bool(0.6) {
+                            Amino::H
+                        } else {
+                            Amino::P
+                        }
+                    })
+                    .collect();
+                let protein = protein_from_aminos(&aminos);
+                let mut enumerator = Enumerator::new(&protein, num_direct, Budget::default());
+                enumerator.run();
+                assert!(enumerator.complete);
+                let bounds = Bounds::new(&protein, dim);
+                assert!(bounds.parity >= enumerator.best_score, "{:?}", aminos);
+                assert!(bounds.core >= enumerator.best_score, "{:?}", aminos);
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// 探索を打ち切る条件。cloneしたものは停止のフラグを共有するので、並列のやり直しに配っておけば
+// Ctrl-Cや目標スコアへの到達でまとめて止められる
+// 時間の制限はBudgetを作ってからの経過時間、評価回数の制限は1回の探索ごとに数える
+#[derive(Clone, Debug)]
+pub struct Budget {
+    pub time_limit: Option<Duration>,
+    // 1回の探索で構造を評価できる回数の上限
+    pub max_evaluations: Option<u64>,
+    // このスコア(H-H結合の数)に達したら止める
+    pub target_score: Option<i32>,
+    start: Instant,
+    // Ctrl-Cなどで全体を止めるためのフラグ
+    stop: Arc<AtomicBool>,
+    // 目標スコアに達したことを同じ探索アルゴリズムのやり直しに伝えるフラグ
+    reached: Arc<AtomicBool>,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Budget::new(None, None, None)
+    }
+}
+
+impl Budget {
+    pub fn new(
+        time_limit: Option<Duration>,
+        max_evaluations: Option<u64>,
+        target_score: Option<i32>,
+    ) -> Budget {
+        Budget {
+            time_limit,
+            max_evaluations,
+            target_score,
+            start: Instant::now(),
+            stop: Arc::new(AtomicBool::new(false)),
+            reached: Arc::new(AtomicBool::new(false)),
+        }
+    }
+    // 停止のフラグと開始時刻は共有したまま、目標への到達だけをやり直したもの
+    // --compareで別の探索アルゴリズムを走らせるときに使う
+    pub fn for_solver(&self) -> Budget {
+        Budget {
+            reached: Arc::new(AtomicBool::new(false)),
+            ..self.clone()
+        }
+    }
+    // limitsの制限で新しく数え始め、停止のフラグだけはselfと共有したもの
+    // benchで配列ごとに制限をかけるときに使う
+    pub fn for_sequence(&self, limits: Budget) -> Budget {
+        Budget {
+            stop: self.stop.clone(),
+            ..limits
+        }
+    }
+    pub fn request_stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+    pub fn stop_requested(&self) -> bool {
+        self.stop.load(Ordering::SeqCst)
+    }
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+    pub fn target_reached(&self) -> bool {
+        self.reached.load(Ordering::SeqCst)
+    }
+    // 止めるべきならtrue。目標スコアに達した場合は他のやり直しも止まるようにフラグを立てる
+    pub fn is_exhausted(&self, evaluations: u64, best_score: i32) -> bool {
+        if self.stop_requested() || self.target_reached() {
+            return true;
+        }
+        if self.target_score.is_some_and(|target| best_score >= target) {
+            self.reached.store(true, Ordering::SeqCst);
+            return true;
+        }
+        if self.time_limit.is_some_and(|limit| self.elapsed() >= limit) {
+            return true;
+        }
+        self.max_evaluations
+            .is_some_and(|max_evaluations| evaluations >= max_evaluations)
+    }
+}
@@ -0,0 +1,350 @@
+use crate::beam::BeamNode;
+use crate::error::Error;
+use crate::mcts::Node;
+use crate::observer::SearchObserver;
+use crate::packed::PackedMoves;
+use crate::protein::Protein;
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// 乱数生成器の状態。シードと何語目まで使ったかを持っておけば同じ続きから再開できる
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RngState {
+    pub seed: [u8; 32],
+    pub stream: u64,
+    pub word_pos: u128,
+}
+
+impl RngState {
+    pub fn new(rng: &ChaCha12Rng) -> RngState {
+        RngState {
+            seed: rng.get_seed(),
+            stream: rng.get_stream(),
+            word_pos: rng.get_word_pos(),
+        }
+    }
+    pub fn to_rng(&self) -> ChaCha12Rng {
+        use rand::SeedableRng;
+        let mut rng = ChaCha12Rng::from_seed(self.seed);
+        rng.set_stream(self.stream);
+        rng.set_word_pos(self.word_pos);
+        rng
+    }
+}
+
+// ビームサーチの反復の区切りでの状態
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BeamState {
+    // 終わった反復の回数
+    pub iteration: usize,
+    pub nodes: Vec<BeamNode>,
+    pub best_ans: Protein,
+    pub best_score: i32,
+    pub evaluations: u64,
+    pub rng: RngState,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AnnealState {
+    pub iteration: usize,
+    pub temperature: f64,
+    pub now_ans: Protein,
+    pub now_score: i32,
+    pub best_ans: Protein,
+    pub best_score: i32,
+    pub evaluations: u64,
+    pub rng: RngState,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GeneticState {
+    // 終わった世代の数
+    pub iteration: usize,
+    pub population: Vec<Protein>,
+    pub values: Vec<i32>,
+    pub best_ans: Protein,
+    pub best_score: i32,
+    pub evaluations: u64,
+    pub rng: RngState,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TabuState {
+    pub iteration: usize,
+    pub now_ans: Protein,
+    pub now_score: i32,
+    pub best_ans: Protein,
+    pub best_score: i32,
+    pub evaluations: u64,
+    pub rng: RngState,
+    // 禁止している解。古い順
+    pub tabu: Vec<PackedMoves>,
+    pub last_improvement: usize,
+}
+
+// 探索木は根から届くノードだけを持つ
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MctsState {
+    pub iteration: usize,
+    pub nodes: Vec<Node>,
+    pub root: usize,
+    pub best_ans: Protein,
+    pub best_score: i32,
+    pub evaluations: u64,
+    pub rng: RngState,
+}
+
+// acoは現在ビルドしていないので、フェロモンの表はまだ保存の対象にしていない
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum SolverState {
+    Beam(BeamState),
+    Anneal(AnnealState),
+    Genetic(GeneticState),
+    Tabu(TabuState),
+    Mcts(MctsState),
+}
+
+// チェックポイントのファイルの形式の版。状態の持ち方を変えたら上げる
+// 2: ビームのノードとタブーリストを向きの列を詰めたもの(PackedMoves)で持つ
+pub const CHECKPOINT_VERSION: u32 = 2;
+
+// チェックポイントのファイルの中身。やり直しごとの状態を"beam#0"のようなキーで持つ
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    // 版を書いていない古いファイルは1とみなす
+    #[serde(default = "first_version")]
+    pub version: u32,
+    pub seed: u64,
+    pub id: u8,
+    // 探索した配列(HとPの列)。これを書いていない古いファイルはidだけで確かめる
+    #[serde(default)]
+    pub sequence: Option<String>,
+    pub dim: u8,
+    pub states: BTreeMap<String, SolverState>,
+}
+
+impl Checkpoint {
+    pub fn new(seed: u64, id: u8, sequence: String, dim: u8) -> Checkpoint {
+        Checkpoint {
+            version: CHECKPOINT_VERSION,
+            seed,
+            id,
+            sequence: Some(sequence),
+            dim,
+            states: BTreeMap::new(),
+        }
+    }
+    pub fn load(path: &str) -> Result<Checkpoint, Error> {
+        let text = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+        let parse_error = |e: serde_json::Error| Error::Parse(format!("{}: {}", path, e));
+        // 形式の違う状態を読もうとしてわかりにくいエラーになる前に、版だけを先に調べる
+        #[derive(Deserialize)]
+        struct Version {
+            #[serde(default = "first_version")]
+            version: u32,
+        }
+        let Version { version } = serde_json::from_str(&text).map_err(parse_error)?;
+        if version != CHECKPOINT_VERSION {
+            return Err(Error::Parse(format!(
+                "{}: checkpoint format version {} is not supported (expected {}); start a new run",
+                path, version, CHECKPOINT_VERSION
+            )));
+        }
+        serde_json::from_str(&text).map_err(parse_error)
+    }
+    // 書き込み中に止められても壊れないように、一時ファイルに書いてから置き換える
+    pub fn save(&self, path: &str) -> Result<(), Error> {
+        let tmp = format!("{}.tmp", path);
+        let text = serde_json::to_string(self)
+            .map_err(|e| Error::Serialize(format!("{}: {}", path, e)))?;
+        fs::write(&tmp, text).map_err(|e| Error::io(&tmp, e))?;
+        fs::rename(&tmp, path).map_err(|e| Error::io(path, e))
+    }
+    pub fn key(label: &str, restart: usize) -> String {
+        format!("{}#{}", label, restart)
+    }
+}
+
+fn first_version() -> u32 {
+    1
+}
+
+// on_checkpointで受け取った状態をinterval以上の間隔でファイルに書き出す
+// cloneしたものは同じファイルを共有するので、並列のやり直しに配ってよい
+// 書き出しに失敗しても探索は続け、最後の失敗をtake_errorで受け取る
+#[derive(Clone)]
+pub struct Checkpointer {
+    checkpoint: Arc<Mutex<Checkpoint>>,
+    error: Arc<Mutex<Option<Error>>>,
+    path: String,
+    interval: Duration,
+    last_saved: Instant,
+    label: String,
+    restart: usize,
+}
+
+impl Checkpointer {
+    // 探索アルゴリズムごとにwith_labelで名前をつけてから使う
+    pub fn new(checkpoint: Checkpoint, path: &str, interval: Duration) -> Checkpointer {
+        Checkpointer {
+            checkpoint: Arc::new(Mutex::new(checkpoint)),
+            error: Arc::new(Mutex::new(None)),
+            path: path.to_string(),
+            interval,
+            last_saved: Instant::now(),
+            label: String::new(),
+            restart: 0,
+        }
+    }
+    // 同じファイルに書き込む、別の探索アルゴリズム用のもの
+    pub fn with_label(&self, label: &str) -> Checkpointer {
+        Checkpointer {
+            label: label.to_string(),
+            restart: 0,
+            ..self.clone()
+        }
+    }
+    pub fn take_error(&self) -> Option<Error> {
+        self.error.lock().unwrap().take()
+    }
+    fn save(&mut self) {
+        if let Err(e) = self.checkpoint.lock().unwrap().save(&self.path) {
+            *self.error.lock().unwrap() = Some(e);
+        }
+        self.last_saved = Instant::now();
+    }
+}
+
+impl SearchObserver for Checkpointer {
+    fn on_restart(&mut self, restart: usize) {
+        self.restart = restart;
+    }
+    fn on_checkpoint(&mut self, state: &SolverState) {
+        let key = Checkpoint::key(&self.label, self.restart);
+        self.checkpoint
+            .lock()
+            .unwrap()
+            .states
+            .insert(key, state.clone());
+        if self.last_saved.elapsed() >= self.interval {
+            self.save();
+        }
+    }
+    // 終わったとき(打ち切ったときも含む)は必ず書き出す
+    fn on_finished(&mut self, _best: &Protein, _score: i32) {
+        self.save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anneal::Annealing;
+    use crate::beam::Beam;
+    use crate::benchmark::{parse_amino_str, protein_from_aminos};
+    use crate::budget::Budget;
+    use crate::genetic::Genetic;
+    use crate::solve::SolverSettings;
+    use crate::tabu::Tabu;
+
+    // on_checkpointで受け取った状態を全て持っておく
+    #[derive(Default)]
+    struct Recorder(Vec<SolverState>);
+
+    impl SearchObserver for Recorder {
+        fn on_checkpoint(&mut self, state: &SolverState) {
+            self.0.push(state.clone());
+        }
+    }
+
+    fn json(state: &SolverState) -> String {
+        serde_json::to_string(state).unwrap()
+    }
+
+    // 途中のチェックポイントをファイルと同じJSONを通して読み戻して再開し、
+    // 止めずに走らせたときと最後の状態(乱数の位置も含む)が一致することを確かめる
+    fn check_resume(run: impl Fn(Option<SolverState>, &mut Recorder)) {
+        let mut whole = Recorder::default();
+        run(None, &mut whole);
+        assert!(whole.0.len() > 2);
+        let middle = serde_json::from_str(&json(&whole.0[whole.0.len() / 2])).unwrap();
+        let mut resumed = Recorder::default();
+        run(Some(middle), &mut resumed);
+        assert_eq!(
+            json(resumed.0.last().unwrap()),
+            json(whole.0.last().unwrap())
+        );
+    }
+
+    fn protein() -> Protein {
+        protein_from_aminos(&parse_amino_str("HPHPPHHPHPPHPHHPPHPH"))
+    }
+
+    #[test]
+    fn anneal_resumes_the_same_trajectory() {
+        let protein = protein();
+        let settings = SolverSettings {
+            iterations: Some(2000),
+            ..SolverSettings::default()
+        };
+        check_resume(|state, recorder| {
+            let mut solver = Annealing::new(&protein, 3, &settings, 11, Budget::default());
+            if let Some(SolverState::Anneal(state)) = state {
+                solver.restore(state);
+            }
+            solver.run(recorder);
+        });
+    }
+
+    #[test]
+    fn tabu_resumes_the_same_trajectory() {
+        let protein = protein();
+        let settings = SolverSettings {
+            iterations: Some(200),
+            ..SolverSettings::default()
+        };
+        check_resume(|state, recorder| {
+            let mut solver = Tabu::new(&protein, 5, &settings, 11, Budget::default());
+            if let Some(SolverState::Tabu(state)) = state {
+                solver.restore(state);
+            }
+            solver.run(recorder);
+        });
+    }
+
+    #[test]
+    fn beam_resumes_the_same_trajectory() {
+        let protein = protein();
+        let settings = SolverSettings {
+            beam_width: 20,
+            ..SolverSettings::default()
+        };
+        check_resume(|state, recorder| {
+            let mut solver = Beam::new(&protein, 3, &settings, 11, Budget::default());
+            if let Some(SolverState::Beam(state)) = state {
+                solver.restore(state);
+            }
+            solver.run(4, recorder);
+        });
+    }
+
+    #[test]
+    fn genetic_resumes_the_same_trajectory() {
+        let protein = protein();
+        let settings = SolverSettings {
+            population: 30,
+            ..SolverSettings::default()
+        };
+        check_resume(|state, recorder| {
+            let mut solver = Genetic::new(&protein, 3, &settings, 11, Budget::default());
+            if let Some(SolverState::Genetic(state)) = state {
+                solver.restore(state);
+            }
+            solver.run(20, recorder);
+        });
+    }
+}
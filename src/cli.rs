@@ -0,0 +1,486 @@
+use clap::{Args, Parser, Subcommand};
+use protein_structure::config::{
+    at_least_one, parse_init, positive, probability, seconds, MAX_SEED,
+};
+use protein_structure::dock::DockSolver;
+use protein_structure::init::Init;
+use protein_structure::mcts::Rollout;
+use protein_structure::refine::Refine;
+use protein_structure::render::{Camera, ImageFormat, RenderOptions};
+use protein_structure::selection::Selection;
+use protein_structure::solve::Solver;
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// 1つの配列を探索アルゴリズムで折りたたむ
+    Fold(Box<FoldArgs>),
+    /// ベンチマークの配列をまとめて解き、既知の最適解や上限と比べる
+    Bench(Box<BenchArgs>),
+    /// 与えた構造のH-H結合の数を数える
+    Eval(EvalArgs),
+    /// 保存した構造を画像に描く
+    Render(RenderArgs),
+    /// 短い配列の全ての構造を分枝限定法で調べて最適解を求める
+    Enumerate(EnumerateArgs),
+    /// 組み込みのベンチマークの配列と既知の最適解を表示する
+    List(ListArgs),
+    /// 複数の鎖を同じ格子に置き、折りたたみと鎖どうしの結合を同時に探索する
+    Dock(DockArgs),
+}
+
+// 折りたたむ配列の指定。--sequenceがなければ組み込みのベンチマークの--id番目を使う
+#[derive(Args, Debug)]
+pub struct ProteinArgs {
+    #[arg(short, long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(2..=3))]
+    pub dim: u8,
+
+    #[arg(short, long, default_value_t = 10)]
+    pub id: u8,
+
+    /// HとPの配列。(HP)2P3のような省略記法も使える。指定すると--idは使わない
+    #[arg(long, conflicts_with = "id")]
+    pub sequence: Option<String>,
+}
+
+impl ProteinArgs {
+    // 組み込みのベンチマークを使う場合はその番号
+    pub fn benchmark_id(&self) -> Option<usize> {
+        self.sequence.is_none().then_some(self.id as usize)
+    }
+}
+
+// 画像の書き出しの設定
+#[derive(Args, Debug)]
+pub struct ImageArgs {
+    /// 最終結果を書き出す画像ファイル(.png、.svgまたは.gif)
+    #[arg(short, long, default_value = "./animated.gif")]
+    pub out: String,
+
+    /// 最終結果の画像の形式。指定しなければ--outの拡張子から決める
+    #[arg(long)]
+    pub format: Option<ImageFormat>,
+
+    /// 画像の幅
+    #[arg(long, default_value_t = 1200)]
+    pub width: u32,
+
+    /// 画像の高さ
+    #[arg(long, default_value_t = 800)]
+    pub height: u32,
+
+    /// 最終結果やturntableのGIFの1フレームの時間 [ms]
+    #[arg(long, default_value_t = 100)]
+    pub frame_delay: u32,
+
+    /// 画像にアミノ酸の番号を表示する
+    #[arg(short, long)]
+    pub labels: bool,
+
+    /// 3次元で描画するときの視点の水平方向の角度(ラジアン)
+    #[arg(long, default_value_t = 0.5)]
+    pub yaw: f64,
+
+    /// 3次元で描画するときの視点の仰角(ラジアン)
+    #[arg(long, default_value_t = 0.15)]
+    pub pitch: f64,
+
+    /// 3次元で描画するときの拡大率
+    #[arg(long, default_value_t = 1.0)]
+    pub scale: f64,
+
+    /// 3次元のとき--outに正面・側面・上面の3方向の図を並べて書き出す
+    #[arg(long)]
+    pub views: bool,
+
+    /// 3次元のとき最終結果を回転させるGIFを書き出す
+    #[arg(long)]
+    pub turntable: Option<String>,
+}
+
+impl ImageArgs {
+    pub fn options(&self) -> RenderOptions {
+        RenderOptions {
+            size: (self.width, self.height),
+            frame_delay: self.frame_delay,
+            format: self.format,
+            show_labels: self.labels,
+            camera: Camera {
+                yaw: self.yaw,
+                pitch: self.pitch,
+                scale: self.scale,
+            },
+            ..RenderOptions::default()
+        }
+    }
+}
+
+// 探索アルゴリズムとその設定
+#[derive(Args, Debug)]
+pub struct SolverArgs {
+    /// 使う探索アルゴリズム(beam、anneal、genetic、tabu、mcts、hcore)
+    #[arg(long, default_value = "beam")]
+    pub solver: Solver,
+
+    /// 乱数のシード。指定すればスレッド数によらず同じ結果になる
+    #[arg(long, value_parser = clap::value_parser!(u64).range(..=MAX_SEED))]
+    pub seed: Option<u64>,
+
+    /// 探索に使うスレッド数。0なら全てのコアを使う
+    #[arg(long, default_value_t = 0)]
+    pub threads: usize,
+
+    /// 焼きなまし法の温度(正の数)
+    #[arg(long, default_value_t = 5.0, value_parser = parse_positive)]
+    pub temperature: f64,
+
+    /// ビーム幅
+    #[arg(long, default_value_t = 200, value_parser = parse_count)]
+    pub beam_width: usize,
+
+    /// ビームサーチで次のビームに残す候補の選び方(top-k、gaussian、sus、diverse)
+    #[arg(long, default_value = "gaussian")]
+    pub selection: Selection,
+
+    /// gaussianでx番目の候補を残す確率exp(-sharpness x²/w²)の係数
+    #[arg(long, default_value_t = 8.0)]
+    pub sharpness: f64,
+
+    /// diverseで、選んだ候補とH-H結合の組が似ている候補の評価値から引く量(H-H結合の数の単位)
+    #[arg(long, default_value_t = 1.0)]
+    pub diversity_weight: f64,
+
+    /// ビームサーチの局所的な組み替えで、両端を固定して組み替える結合の数
+    #[arg(long, default_value_t = 5)]
+    pub window: usize,
+
+    /// ビームの何個おきのノードを局所的に組み替えるか
+    #[arg(long, default_value_t = 3)]
+    pub stride: usize,
+
+    /// 組み替える経路の候補の作り方(exhaustive、sample、walk)
+    #[arg(long, default_value = "exhaustive")]
+    pub refine: Refine,
+
+    /// sampleで1つの窓について試す経路の数
+    #[arg(long, default_value_t = 20)]
+    pub refine_samples: usize,
+
+    /// 初期解を変えて探索をやり直す回数。--visでなければ並列に実行する
+    #[arg(long, default_value_t = 4, value_parser = parse_count)]
+    pub restarts: usize,
+
+    /// 遺伝的アルゴリズムの個体数
+    #[arg(long, default_value_t = 100, value_parser = parse_count)]
+    pub population: usize,
+
+    /// 遺伝的アルゴリズムで交叉を行う確率
+    #[arg(long, default_value_t = 0.9, value_parser = parse_probability)]
+    pub crossover_rate: f64,
+
+    /// 遺伝的アルゴリズムで突然変異を行う確率
+    #[arg(long, default_value_t = 0.3, value_parser = parse_probability)]
+    pub mutation_rate: f64,
+
+    /// トーナメント選択で比べる個体の数
+    #[arg(long, default_value_t = 3)]
+    pub tournament_size: usize,
+
+    /// そのまま次の世代に残す上位の個体の数
+    #[arg(long, default_value_t = 2)]
+    pub elitism: usize,
+
+    /// 向きが異なる箇所の割合がこれより小さい個体を同じニッチとみなす(0なら無効)
+    #[arg(long, default_value_t = 0.1)]
+    pub niche_radius: f64,
+
+    /// 交叉で衝突したときに切る位置を変えて試す回数
+    #[arg(long, default_value_t = 10)]
+    pub crossover_attempts: usize,
+
+    /// タブーサーチで一度離れた解に戻ることを禁止する反復の回数
+    #[arg(long, default_value_t = 50)]
+    pub tabu_tenure: usize,
+
+    /// タブーサーチでベストがこの回数更新されなければ解をかき混ぜる
+    #[arg(long, default_value_t = 50)]
+    pub stagnation: usize,
+
+    /// タブーサーチで解をかき混ぜるときに変える向きの数
+    #[arg(long, default_value_t = 5)]
+    pub perturbation: usize,
+
+    /// MCTSで1つのアミノ酸の位置を決めるまでに行うプレイアウトの回数
+    #[arg(long, default_value_t = 200, value_parser = parse_count)]
+    pub playouts: usize,
+
+    /// MCTSのUCTの探索項の係数
+    #[arg(long, default_value_t = 0.5)]
+    pub exploration: f64,
+
+    /// MCTSのプレイアウトで鎖を伸ばす方法(randomまたはgreedy)
+    #[arg(long, default_value = "greedy")]
+    pub rollout: Rollout,
+
+    /// H-コア解法で1つの段階で列挙するコアの数の上限
+    #[arg(long, default_value_t = 10000)]
+    pub max_cores: usize,
+
+    /// H-コア解法で1つのコアに配列を通すときに調べるノードの数の上限
+    #[arg(long, default_value_t = 1_000_000)]
+    pub max_nodes: u64,
+
+    /// H-コア解法で調べるコアの段階(H-H結合の数)の数の上限
+    #[arg(long, default_value_t = 4)]
+    pub core_levels: usize,
+
+    /// 最初の解の作り方(random、greedy、compact)。beam=greedyのように探索アルゴリズムごとにも指定できる
+    #[arg(long, value_parser = parse_init)]
+    pub init: Vec<(Option<Solver>, Init)>,
+
+    /// 反復回数。指定しなければビームサーチは10回、焼きなまし法は100000回、遺伝的アルゴリズムは200世代、タブーサーチは2000回
+    #[arg(long)]
+    pub iterations: Option<usize>,
+}
+
+// 探索を打ち切る条件
+#[derive(Args, Debug)]
+pub struct BudgetArgs {
+    /// 探索全体の制限時間 [s]。過ぎたらその時点のベストを出力する
+    #[arg(long, value_parser = parse_seconds)]
+    pub time_limit: Option<f64>,
+
+    /// 1回の探索で評価できる回数の上限
+    #[arg(long)]
+    pub max_evaluations: Option<u64>,
+
+    /// このスコア(H-H結合の数)に達したら探索を止める
+    #[arg(long)]
+    pub target: Option<i32>,
+
+    /// 既知の最適解のスコアに達したら探索を止める
+    #[arg(long)]
+    pub stop_at_optimum: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct FoldArgs {
+    /// 設定ファイル(.toml、.yaml、.ymlまたは.json)。コマンドラインで指定したものが優先される
+    #[arg(long)]
+    pub config: Option<String>,
+
+    #[command(flatten)]
+    pub protein: ProteinArgs,
+
+    #[command(flatten)]
+    pub solver: SolverArgs,
+
+    #[command(flatten)]
+    pub budget: BudgetArgs,
+
+    #[command(flatten)]
+    pub image: ImageArgs,
+
+    #[arg(short, long)]
+    pub vis: bool,
+
+    #[arg(short, long, default_value_t = 5)]
+    pub step: u8,
+
+    /// --visのときに探索の途中経過を書き出すGIFファイル
+    #[arg(long, default_value = "./trace.gif")]
+    pub trace: String,
+
+    /// 途中経過のGIFの1フレームの時間 [ms]
+    #[arg(long, default_value_t = 1)]
+    pub trace_delay: u32,
+
+    /// 探索中のイベントを書き出すCSVファイル
+    #[arg(long)]
+    pub log_csv: Option<String>,
+
+    /// 全ての探索アルゴリズムを順に実行して比較する
+    #[arg(long)]
+    pub compare: bool,
+
+    /// 評価回数・経過時間・エネルギーなどの推移を書き出すCSVファイル
+    #[arg(long)]
+    pub trajectory: Option<String>,
+
+    /// エネルギーの推移のグラフを書き出す画像ファイル
+    #[arg(long)]
+    pub convergence: Option<String>,
+
+    /// 最終結果の構造を書き出すJSONファイル。evalやrenderで読み込める
+    #[arg(long)]
+    pub save: Option<String>,
+
+    /// 探索の状態を定期的に書き出すチェックポイントのファイル
+    #[arg(long)]
+    pub checkpoint: Option<String>,
+
+    /// チェックポイントを書き出す間隔 [s]
+    #[arg(long, default_value_t = 60.0, value_parser = parse_seconds)]
+    pub checkpoint_interval: f64,
+
+    /// チェックポイントのファイルから探索を再開する。--checkpointがなければ同じファイルに書き出し続ける
+    #[arg(long)]
+    pub resume: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct BenchArgs {
+    /// 設定ファイル(.toml、.yaml、.ymlまたは.json)。コマンドラインで指定したものが優先される
+    #[arg(long)]
+    pub config: Option<String>,
+
+    #[arg(short, long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(2..=3))]
+    pub dim: u8,
+
+    /// 解くベンチマークの番号(1,2,3のように並べる)。指定しなければその次元の既知の最適解がある全て
+    #[arg(long, value_delimiter = ',')]
+    pub ids: Vec<usize>,
+
+    #[command(flatten)]
+    pub solver: SolverArgs,
+
+    /// 配列ごとの打ち切りの条件。制限時間も配列ごとに数える
+    #[command(flatten)]
+    pub budget: BudgetArgs,
+}
+
+// 構造の指定。ファイルを指定しなければ、配列と--directionsか--coordsから作る
+#[derive(Args, Debug)]
+pub struct ConformationArgs {
+    /// fold --saveなどで書き出した構造のJSONファイル
+    #[arg(conflicts_with_all = ["directions", "sequence", "coords"])]
+    pub file: Option<String>,
+
+    #[command(flatten)]
+    pub protein: ProteinArgs,
+
+    /// 3つ目のアミノ酸から順に、結合の向きをS、L、R、U、Dで並べた文字列
+    #[arg(long, required_unless_present_any = ["file", "coords"], conflicts_with = "coords")]
+    pub directions: Option<String>,
+
+    /// --directionsを最初の結合からの絶対的な向き(R、L、U、Dが±x、±y、F、Bが±z)として読む
+    #[arg(long, requires = "directions")]
+    pub absolute: bool,
+
+    /// 1行に1つのアミノ酸の座標を「x y」か「x y z」で書いたファイル。先頭にHかPを付ければ配列も読む
+    #[arg(long)]
+    pub coords: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct EvalArgs {
+    #[command(flatten)]
+    pub conformation: ConformationArgs,
+
+    /// 構造を向きの列で表してJSONファイルに書き出す。renderなどで読み込める
+    #[arg(long)]
+    pub save: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct RenderArgs {
+    #[command(flatten)]
+    pub conformation: ConformationArgs,
+
+    #[command(flatten)]
+    pub image: ImageArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct EnumerateArgs {
+    #[command(flatten)]
+    pub protein: ProteinArgs,
+
+    /// 制限時間 [s]。過ぎたらそれまでに見つけた最も良い構造を出力する
+    #[arg(long, value_parser = parse_seconds)]
+    pub time_limit: Option<f64>,
+
+    /// 見つけた最適解の1つを書き出すJSONファイル
+    #[arg(long)]
+    pub save: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct ListArgs {
+    /// 指定した次元の既知の最適解があるものだけを表示する
+    #[arg(short, long, value_parser = clap::value_parser!(u8).range(2..=3))]
+    pub dim: Option<u8>,
+}
+
+#[derive(Args, Debug)]
+pub struct DockArgs {
+    #[arg(short, long, default_value_t = 2, value_parser = clap::value_parser!(u8).range(2..=3))]
+    pub dim: u8,
+
+    /// 鎖ごとのHとPの配列をカンマで区切って並べる(HPPH,(HP)2P3のように省略記法も使える)
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub sequences: Vec<String>,
+
+    /// 乱数のシード
+    #[arg(long, value_parser = clap::value_parser!(u64).range(..=MAX_SEED))]
+    pub seed: Option<u64>,
+
+    /// 使う探索アルゴリズム(anneal、tabu、beam)。tabuとbeamは鎖の形を変える動きと鎖全体の動きを全て調べる
+    #[arg(long, default_value = "anneal")]
+    pub solver: DockSolver,
+
+    /// 反復回数。指定しなければ焼きなまし法は200000回、タブーサーチは1000回、ビームサーチは100回
+    #[arg(long)]
+    pub iterations: Option<usize>,
+
+    /// 焼きなまし法の最初の温度(正の数)。最後にはその1%まで下げる
+    #[arg(long, default_value_t = 5.0, value_parser = parse_positive)]
+    pub temperature: f64,
+
+    /// 焼きなまし法で、1回の動きで鎖全体を平行移動か回転させる確率
+    #[arg(long, default_value_t = 0.2, value_parser = parse_probability)]
+    pub rigid: f64,
+
+    /// タブーサーチで一度離れた複合体に戻ることを禁止する反復の回数
+    #[arg(long, default_value_t = 50)]
+    pub tabu_tenure: usize,
+
+    /// ビームサーチで残す複合体の数
+    #[arg(long, default_value_t = 50, value_parser = parse_count)]
+    pub beam_width: usize,
+
+    #[command(flatten)]
+    pub budget: BudgetArgs,
+
+    /// 最終結果を書き出すPDBファイル。鎖ごとに別のチェーンID(A、B、…)を付ける
+    #[arg(long)]
+    pub pdb: Option<String>,
+}
+
+// 値の確かめ方は設定ファイルと同じ(protein_structure::config)
+
+fn parse_positive(s: &str) -> Result<f64, String> {
+    let x: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    positive(x)
+}
+
+fn parse_probability(s: &str) -> Result<f64, String> {
+    let p: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    probability(p)
+}
+
+fn parse_seconds(s: &str) -> Result<f64, String> {
+    let x: f64 = s.parse().map_err(|e| format!("{}", e))?;
+    seconds(x)
+}
+
+fn parse_count(s: &str) -> Result<usize, String> {
+    let n: usize = s.parse().map_err(|e| format!("{}", e))?;
+    at_least_one(n)
+}
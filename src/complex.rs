@@ -0,0 +1,370 @@
+use crate::conformation::{max_distance, transforms, value, walk, Point};
+use crate::error::Error;
+use crate::moves::{direction_neighbourhood, directions, Move};
+use crate::protein::{Amino, Direction};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+const NEIGHBOURS: [Point; 6] = [
+    (1, 0, 0),
+    (-1, 0, 0),
+    (0, 1, 0),
+    (0, -1, 0),
+    (0, 0, 1),
+    (0, 0, -1),
+];
+
+// 複合体の中の1本の鎖。向きの列で形を決め、格子の対称操作(transformsのorientation番目)と
+// 平行移動(offset)で置く場所を決める。最初のアミノ酸はoffsetに来る
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Chain {
+    pub aminos: Vec<Amino>,
+    pub direct: Vec<Direction>,
+    pub orientation: usize,
+    pub offset: Point,
+}
+
+impl Chain {
+    // まっすぐに伸ばした鎖をoffsetに置く
+    pub fn straight(aminos: &[Amino], offset: Point) -> Chain {
+        Chain {
+            aminos: aminos.to_vec(),
+            direct: vec![Direction::S; aminos.len().saturating_sub(2)],
+            orientation: 0,
+            offset,
+        }
+    }
+    pub fn positions(&self, dim: usize) -> Vec<Point> {
+        let t = &transforms(dim)[self.orientation];
+        let o = self.offset;
+        walk(&self.direct, self.aminos.len())
+            .into_iter()
+            .map(|p| {
+                let p = t(p);
+                (p.0 + o.0, p.1 + o.1, p.2 + o.2)
+            })
+            .collect()
+    }
+}
+
+// H-H結合を、鎖の中のものと鎖の組の間(界面)のものに分けたもの
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ComplexContacts {
+    // intra[c]: c番目の鎖の中の結合(鎖上で隣り合うものは除く)
+    pub intra: Vec<Vec<(usize, usize)>>,
+    // interface[(a, b)]: a番目とb番目(a < b)の鎖の間の結合を(aのアミノ酸, bのアミノ酸)で並べたもの
+    pub interface: BTreeMap<(usize, usize), Vec<(usize, usize)>>,
+}
+
+impl ComplexContacts {
+    pub fn intra_score(&self) -> i32 {
+        self.intra.iter().map(|c| c.len() as i32).sum()
+    }
+    pub fn interface_score(&self) -> i32 {
+        self.interface.values().map(|c| c.len() as i32).sum()
+    }
+    // 鎖の中と界面を合わせたH-H結合の数
+    pub fn score(&self) -> i32 {
+        self.intra_score() + self.interface_score()
+    }
+}
+
+// 同じ格子に置いた複数の鎖
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Complex {
+    pub chains: Vec<Chain>,
+    pub num_direct: i32,
+}
+
+impl Complex {
+    // 各鎖をまっすぐに伸ばし、y方向に2つずつずらして並べる
+    pub fn new(sequences: &[Vec<Amino>], num_direct: i32) -> Complex {
+        Complex {
+            chains: sequences
+                .iter()
+                .enumerate()
+                .map(|(c, aminos)| Chain::straight(aminos, (0, 2 * c as i32, 0)))
+                .collect(),
+            num_direct,
+        }
+    }
+    pub fn dim(&self) -> usize {
+        if self.num_direct == 5 {
+            3
+        } else {
+            2
+        }
+    }
+    pub fn positions(&self) -> Vec<Vec<Point>> {
+        self.chains
+            .iter()
+            .map(|chain| chain.positions(self.dim()))
+            .collect()
+    }
+    // 鎖をまたいだ衝突も調べてH-H結合を数える
+    pub fn contacts(&self) -> Result<ComplexContacts, Error> {
+        let positions = self.positions();
+        let mut occupied: HashMap<Point, (usize, usize)> = HashMap::new();
+        for (c, pos) in positions.iter().enumerate() {
+            for (i, &p) in pos.iter().enumerate() {
+                if let Some((with_chain, with)) = occupied.insert(p, (c, i)) {
+                    return Err(Error::ChainCollision {
+                        chain: c,
+                        residue: i,
+                        with_chain,
+                        with,
+                    });
+                }
+            }
+        }
+        let mut contacts = ComplexContacts {
+            intra: vec![Vec::new(); self.chains.len()],
+            interface: BTreeMap::new(),
+        };
+        for (c, pos) in positions.iter().enumerate() {
+            for (i, &p) in pos.iter().enumerate() {
+                if self.chains[c].aminos[i] != Amino::H {
+                    continue;
+                }
+                for n in NEIGHBOURS {
+                    let q = (p.0 + n.0, p.1 + n.1, p.2 + n.2);
+                    let Some(&(d, j)) = occupied.get(&q) else {
+                        continue;
+                    };
+                    if self.chains[d].aminos[j] != Amino::H {
+                        continue;
+                    }
+                    if d == c && j > i + 1 {
+                        contacts.intra[c].push((i, j));
+                    } else if d > c {
+                        contacts.interface.entry((c, d)).or_default().push((i, j));
+                    }
+                }
+            }
+        }
+        for intra in contacts.intra.iter_mut() {
+            intra.sort();
+        }
+        for interface in contacts.interface.values_mut() {
+            interface.sort();
+        }
+        Ok(contacts)
+    }
+    // (評価値, スコア)。評価値は1本の鎖と同じく、複合体全体で最も離れた2つのアミノ酸の距離で広がりを測る
+    pub fn evaluate(&self) -> Result<(i32, i32), Error> {
+        let score = self.contacts()?.score();
+        let positions: Vec<Point> = self.positions().into_iter().flatten().collect();
+        Ok((value(score, max_distance(&positions)), score))
+    }
+}
+
+// 複合体を少しだけ変える動き
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChainMove {
+    // chain番目の鎖の形を変える
+    Residue { chain: usize, mv: Move },
+    // chain番目の鎖全体を隣の格子点へ平行移動する
+    Translate { chain: usize, step: Point },
+    // chain番目の鎖全体の向きをorientation番目の対称操作に変える。重心はなるべく動かさない
+    Rotate { chain: usize, orientation: usize },
+}
+
+impl ChainMove {
+    // ランダムな動きを1つ選ぶ。鎖が2本以上あれば、rigidの確率で鎖全体を平行移動か回転させる
+    pub fn random<R: Rng>(complex: &Complex, rigid: f64, rng: &mut R) -> ChainMove {
+        let chain = rng.gen_range(0..complex.chains.len());
+        let len = complex.chains[chain].direct.len();
+        let dim = complex.dim();
+        if len == 0 || (complex.chains.len() > 1 && rng.gen_bool(rigid)) {
+            if rng.gen_bool(0.5) {
+                let step = NEIGHBOURS[rng.gen_range(0..2 * dim)];
+                return ChainMove::Translate { chain, step };
+            }
+            let orientation = rng.gen_range(0..transforms(dim).len());
+            return ChainMove::Rotate { chain, orientation };
+        }
+        let mv = if rng.gen_bool(0.5) {
+            Move::Pivot {
+                index: rng.gen_range(0..len),
+                direction: *directions(complex.num_direct).choose(rng).unwrap(),
+            }
+        } else {
+            Move::Swap {
+                index: rng.gen_range(0..len),
+                gap: rng.gen_range(1..=2),
+            }
+        };
+        ChainMove::Residue { chain, mv }
+    }
+    // complexから1回の動きで移れる候補を全て列挙する。鎖が2本以上あれば鎖全体の動きも含める
+    pub fn neighbourhood(complex: &Complex) -> Vec<ChainMove> {
+        let dim = complex.dim();
+        let mut moves = Vec::new();
+        for (c, chain) in complex.chains.iter().enumerate() {
+            for mv in direction_neighbourhood(&chain.direct, complex.num_direct) {
+                moves.push(ChainMove::Residue { chain: c, mv });
+            }
+            if complex.chains.len() < 2 {
+                continue;
+            }
+            for &step in NEIGHBOURS[..2 * dim].iter() {
+                moves.push(ChainMove::Translate { chain: c, step });
+            }
+            for orientation in 0..transforms(dim).len() {
+                if orientation != chain.orientation {
+                    moves.push(ChainMove::Rotate {
+                        chain: c,
+                        orientation,
+                    });
+                }
+            }
+        }
+        moves
+    }
+    // 動かした複合体を返す。向きで表せないか、何も変わらない場合はNone(衝突は調べない)
+    pub fn apply(&self, complex: &Complex) -> Option<Complex> {
+        let mut moved = complex.clone();
+        match *self {
+            ChainMove::Residue { chain, mv } => {
+                let chain = &mut moved.chains[chain];
+                chain.direct = mv.apply_directions(&chain.direct, complex.num_direct)?;
+            }
+            ChainMove::Translate { chain, step } => {
+                let o = &mut moved.chains[chain].offset;
+                *o = (o.0 + step.0, o.1 + step.1, o.2 + step.2);
+            }
+            ChainMove::Rotate { chain, orientation } => {
+                if complex.chains[chain].orientation == orientation {
+                    return None;
+                }
+                let dim = complex.dim();
+                let before = centroid(&complex.chains[chain].positions(dim));
+                let chain = &mut moved.chains[chain];
+                chain.orientation = orientation;
+                let after = centroid(&chain.positions(dim));
+                let o = chain.offset;
+                chain.offset = (
+                    o.0 + before.0 - after.0,
+                    o.1 + before.1 - after.1,
+                    o.2 + before.2 - after.2,
+                );
+            }
+        }
+        Some(moved)
+    }
+}
+
+// 座標の平均を格子点に丸めたもの
+fn centroid(pos: &[Point]) -> Point {
+    let n = pos.len().max(1) as f64;
+    let sum = pos
+        .iter()
+        .fold((0, 0, 0), |s, p| (s.0 + p.0, s.1 + p.1, s.2 + p.2));
+    (
+        (sum.0 as f64 / n).round() as i32,
+        (sum.1 as f64 / n).round() as i32,
+        (sum.2 as f64 / n).round() as i32,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmark::parse_amino_str;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+
+    fn complex(sequences: &[&str], num_direct: i32) -> Complex {
+        let sequences: Vec<Vec<Amino>> = sequences.iter().map(|s| parse_amino_str(s)).collect();
+        Complex::new(&sequences, num_direct)
+    }
+
+    // 全てのアミノ酸の組を調べてH-H結合を数える
+    fn count_contacts(complex: &Complex) -> i32 {
+        let residues: Vec<(usize, usize, Point)> = complex
+            .positions()
+            .into_iter()
+            .enumerate()
+            .flat_map(|(c, pos)| pos.into_iter().enumerate().map(move |(i, p)| (c, i, p)))
+            .filter(|&(c, i, _)| complex.chains[c].aminos[i] == Amino::H)
+            .collect();
+        let mut count = 0;
+        for (k, &(c, i, p)) in residues.iter().enumerate() {
+            for &(d, j, q) in residues[k + 1..].iter() {
+                let distance = (p.0 - q.0).abs() + (p.1 - q.1).abs() + (p.2 - q.2).abs();
+                if distance == 1 && !(c == d && j == i + 1) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn counts_interface_contacts_per_chain_pair() {
+        let mut complex = complex(&["HH", "HH", "PH"], 3);
+        complex.chains[1].offset = (0, 1, 0);
+        complex.chains[2].offset = (0, -1, 0);
+        let contacts = complex.contacts().unwrap();
+        assert_eq!(contacts.intra, vec![Vec::new(); 3]);
+        let interface: Vec<_> = contacts.interface.into_iter().collect();
+        assert_eq!(
+            interface,
+            vec![((0, 1), vec![(0, 0), (1, 1)]), ((0, 2), vec![(1, 1)])]
+        );
+        assert_eq!(complex.evaluate().unwrap().1, 3);
+    }
+
+    #[test]
+    fn counts_intra_contacts_separately() {
+        // U字に曲げたHPPHは両端が隣り合う
+        let mut complex = complex(&["HPPH", "PP"], 3);
+        complex.chains[0].direct = vec![Direction::L, Direction::L];
+        complex.chains[1].offset = (0, 5, 0);
+        let contacts = complex.contacts().unwrap();
+        assert_eq!(contacts.intra, vec![vec![(0, 3)], Vec::new()]);
+        assert!(contacts.interface.is_empty());
+        assert_eq!((contacts.intra_score(), contacts.interface_score()), (1, 0));
+    }
+
+    #[test]
+    fn rejects_chains_on_the_same_point() {
+        let mut complex = complex(&["HPH", "HPH"], 5);
+        complex.chains[1].offset = (2, 0, 0);
+        assert!(matches!(
+            complex.contacts(),
+            Err(Error::ChainCollision {
+                chain: 1,
+                residue: 0,
+                with_chain: 0,
+                with: 2,
+            })
+        ));
+        assert!(complex.evaluate().is_err());
+    }
+
+    // ランダムに動かした複合体で、数えた結合が全ての組を調べた数と合う
+    #[test]
+    fn contacts_match_pairwise_count() {
+        for num_direct in [3, 5] {
+            let mut rng = ChaCha12Rng::seed_from_u64(3);
+            let mut now = complex(&["HPHHPH", "HHPPH", "PHHH"], num_direct);
+            let mut checked = 0;
+            for _ in 0..2000 {
+                let mv = ChainMove::random(&now, 0.3, &mut rng);
+                let Some(moved) = mv.apply(&now) else {
+                    continue;
+                };
+                let Ok(contacts) = moved.contacts() else {
+                    continue;
+                };
+                assert_eq!(contacts.score(), count_contacts(&moved));
+                checked += 1;
+                now = moved;
+            }
+            assert!(checked > 100);
+        }
+    }
+}
@@ -0,0 +1,356 @@
+use crate::budget::Budget;
+use crate::error::Error;
+use crate::init::Init;
+use crate::solve::{Solver, SolverSettings};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+// TOMLの整数はi64なので、シードはこれ以下に限る
+pub const MAX_SEED: u64 = i64::MAX as u64;
+
+// 実行の設定ファイル。キーはコマンドラインのオプションと同じ名前(ハイフンは_)で、
+// コマンドラインで指定したものが優先される。書いていない項目はオプションの既定値になる
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RunConfig {
+    pub seed: Option<u64>,
+    pub sequence: SequenceConfig,
+    pub lattice: LatticeConfig,
+    pub objective: ObjectiveConfig,
+    pub solver: SolverConfig,
+    pub output: OutputConfig,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SequenceConfig {
+    pub id: Option<u8>,
+    pub sequence: Option<String>,
+    // benchで解くベンチマークの番号
+    pub ids: Option<Vec<usize>>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LatticeConfig {
+    pub dim: Option<u8>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ObjectiveConfig {
+    pub target: Option<i32>,
+    pub stop_at_optimum: Option<bool>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SolverConfig {
+    // --solver
+    pub name: Option<String>,
+    pub compare: Option<bool>,
+    pub restarts: Option<usize>,
+    pub iterations: Option<usize>,
+    pub time_limit: Option<f64>,
+    pub max_evaluations: Option<u64>,
+    pub threads: Option<usize>,
+    pub init: Option<Vec<String>>,
+    pub temperature: Option<f64>,
+    pub beam_width: Option<usize>,
+    pub selection: Option<String>,
+    pub sharpness: Option<f64>,
+    pub diversity_weight: Option<f64>,
+    pub window: Option<usize>,
+    pub stride: Option<usize>,
+    pub refine: Option<String>,
+    pub refine_samples: Option<usize>,
+    pub population: Option<usize>,
+    pub crossover_rate: Option<f64>,
+    pub mutation_rate: Option<f64>,
+    pub tournament_size: Option<usize>,
+    pub elitism: Option<usize>,
+    pub niche_radius: Option<f64>,
+    pub crossover_attempts: Option<usize>,
+    pub tabu_tenure: Option<usize>,
+    pub stagnation: Option<usize>,
+    pub perturbation: Option<usize>,
+    pub playouts: Option<usize>,
+    pub exploration: Option<f64>,
+    pub rollout: Option<String>,
+    pub max_cores: Option<usize>,
+    pub max_nodes: Option<u64>,
+    pub core_levels: Option<usize>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub out: Option<String>,
+    pub format: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub frame_delay: Option<u32>,
+    pub labels: Option<bool>,
+    pub yaw: Option<f64>,
+    pub pitch: Option<f64>,
+    pub scale: Option<f64>,
+    pub views: Option<bool>,
+    pub turntable: Option<String>,
+    pub vis: Option<bool>,
+    pub step: Option<u8>,
+    pub trace: Option<String>,
+    pub trace_delay: Option<u32>,
+    pub log_csv: Option<String>,
+    pub trajectory: Option<String>,
+    pub convergence: Option<String>,
+    pub save: Option<String>,
+    pub checkpoint: Option<String>,
+    pub checkpoint_interval: Option<f64>,
+}
+
+impl RunConfig {
+    // 拡張子が.yamlか.ymlならYAML、.jsonならJSON、それ以外はTOMLとして読む
+    pub fn load(path: &str) -> Result<RunConfig, Error> {
+        let text = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        match extension.as_str() {
+            "yaml" | "yml" => serde_yaml::from_str(&text).map_err(|e| e.to_string()),
+            "json" => serde_json::from_str(&text).map_err(|e| e.to_string()),
+            _ => toml::from_str(&text).map_err(|e| e.to_string()),
+        }
+        .map_err(|e| Error::Parse(format!("{}: {}", path, e)))
+    }
+    pub fn to_toml(&self) -> Result<String, Error> {
+        toml::to_string(self).map_err(|e| Error::Serialize(e.to_string()))
+    }
+}
+
+impl RunConfig {
+    // 探索を打ち切る条件。制限時間と評価回数は[solver]、目標のスコアは[objective]から作る
+    pub fn budget(&self, optimum: Option<i32>) -> Result<Budget, Error> {
+        let time_limit = self.solver.time_limit.map(seconds).transpose();
+        let time_limit = time_limit.map_err(|e| Error::Parse(format!("time_limit: {}", e)))?;
+        Ok(Budget::new(
+            time_limit.map(Duration::from_secs_f64),
+            self.solver.max_evaluations,
+            self.objective.target(optimum),
+        ))
+    }
+}
+
+impl ObjectiveConfig {
+    // targetがなければ、stop_at_optimumのときだけ既知の最適解を目標にする
+    pub fn target(&self, optimum: Option<i32>) -> Option<i32> {
+        self.target.or_else(|| {
+            self.stop_at_optimum
+                .unwrap_or(false)
+                .then_some(optimum)
+                .flatten()
+        })
+    }
+}
+
+impl SolverConfig {
+    // 探索アルゴリズムに渡す設定。書いていない項目はSolverSettingsの既定値になる
+    pub fn settings(&self) -> Result<SolverSettings, Error> {
+        let d = SolverSettings::default();
+        let init: Option<Result<Vec<_>, String>> = self
+            .init
+            .as_ref()
+            .map(|init| init.iter().map(|s| parse_init(s)).collect());
+        Ok(SolverSettings {
+            iterations: self.iterations,
+            init: value("init", init, d.init)?,
+            temperature: value("temperature", self.temperature.map(positive), d.temperature)?,
+            beam_width: value(
+                "beam_width",
+                self.beam_width.map(at_least_one),
+                d.beam_width,
+            )?,
+            selection: value("selection", parsed(&self.selection), d.selection)?,
+            sharpness: self.sharpness.unwrap_or(d.sharpness),
+            diversity_weight: self.diversity_weight.unwrap_or(d.diversity_weight),
+            window: self.window.unwrap_or(d.window),
+            stride: self.stride.unwrap_or(d.stride),
+            refine: value("refine", parsed(&self.refine), d.refine)?,
+            refine_samples: self.refine_samples.unwrap_or(d.refine_samples),
+            population: value(
+                "population",
+                self.population.map(at_least_one),
+                d.population,
+            )?,
+            crossover_rate: value(
+                "crossover_rate",
+                self.crossover_rate.map(probability),
+                d.crossover_rate,
+            )?,
+            mutation_rate: value(
+                "mutation_rate",
+                self.mutation_rate.map(probability),
+                d.mutation_rate,
+            )?,
+            tournament_size: self.tournament_size.unwrap_or(d.tournament_size),
+            elitism: self.elitism.unwrap_or(d.elitism),
+            niche_radius: self.niche_radius.unwrap_or(d.niche_radius),
+            crossover_attempts: self.crossover_attempts.unwrap_or(d.crossover_attempts),
+            tabu_tenure: self.tabu_tenure.unwrap_or(d.tabu_tenure),
+            stagnation: self.stagnation.unwrap_or(d.stagnation),
+            perturbation: self.perturbation.unwrap_or(d.perturbation),
+            playouts: value("playouts", self.playouts.map(at_least_one), d.playouts)?,
+            exploration: self.exploration.unwrap_or(d.exploration),
+            rollout: value("rollout", parsed(&self.rollout), d.rollout)?,
+            max_cores: self.max_cores.unwrap_or(d.max_cores),
+            max_nodes: self.max_nodes.unwrap_or(d.max_nodes),
+            core_levels: self.core_levels.unwrap_or(d.core_levels),
+        })
+    }
+    // 探索アルゴリズム。書いていなければビームサーチ
+    pub fn solver(&self) -> Result<Solver, Error> {
+        value("name", parsed(&self.name), Solver::Beam)
+    }
+    // 初期解を変えて探索をやり直す回数。書いていなければ4回
+    pub fn restarts(&self) -> Result<usize, Error> {
+        value("restarts", self.restarts.map(at_least_one), 4)
+    }
+}
+
+// 値が書いてあれば確かめて使い、なければ既定値を使う
+fn value<T>(key: &str, value: Option<Result<T, String>>, default: T) -> Result<T, Error> {
+    match value {
+        Some(value) => value.map_err(|e| Error::Parse(format!("{}: {}", key, e))),
+        None => Ok(default),
+    }
+}
+
+fn parsed<T: FromStr<Err = String>>(value: &Option<String>) -> Option<Result<T, String>> {
+    value.as_deref().map(T::from_str)
+}
+
+// 以下はコマンドラインの引数と設定ファイルの値を確かめるのにも使う
+
+// "greedy"なら全ての探索アルゴリズム、"beam=greedy"ならビームサーチだけの初期解の作り方
+pub fn parse_init(s: &str) -> Result<(Option<Solver>, Init), String> {
+    match s.split_once('=') {
+        Some((solver, init)) => Ok((Some(solver.parse()?), init.parse()?)),
+        None => Ok((None, s.parse()?)),
+    }
+}
+
+pub fn positive(x: f64) -> Result<f64, String> {
+    if x > 0.0 {
+        Ok(x)
+    } else {
+        Err(format!("{} is not positive", x))
+    }
+}
+
+pub fn probability(p: f64) -> Result<f64, String> {
+    if (0.0..=1.0).contains(&p) {
+        Ok(p)
+    } else {
+        Err(format!("{} is not in 0..=1", p))
+    }
+}
+
+// Durationにできるのは有限で負でない秒数だけ
+pub fn seconds(x: f64) -> Result<f64, String> {
+    if x.is_finite() && x >= 0.0 {
+        Ok(x)
+    } else {
+        Err(format!("{} is not a valid number of seconds", x))
+    }
+}
+
+// 回数や個数は1以上でないと結果が作れない
+pub fn at_least_one(n: usize) -> Result<usize, String> {
+    if n >= 1 {
+        Ok(n)
+    } else {
+        Err(format!("{} is less than 1", n))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::selection::Selection;
+
+    #[test]
+    fn settings_fill_in_the_defaults() {
+        let config = SolverConfig {
+            beam_width: Some(30),
+            selection: Some("sus".to_string()),
+            init: Some(vec!["compact".to_string(), "beam=greedy".to_string()]),
+            ..SolverConfig::default()
+        };
+        let settings = config.settings().unwrap();
+        let defaults = SolverSettings::default();
+        assert_eq!(settings.beam_width, 30);
+        assert_eq!(settings.selection, Selection::Sus);
+        assert_eq!(settings.init_for(Solver::Beam), Init::Greedy);
+        assert_eq!(settings.init_for(Solver::Tabu), Init::Compact);
+        assert_eq!(settings.temperature, defaults.temperature);
+        assert_eq!(settings.population, defaults.population);
+        assert_eq!(config.solver().unwrap(), Solver::Beam);
+        assert_eq!(config.restarts().unwrap(), 4);
+    }
+
+    #[test]
+    fn settings_reject_invalid_values() {
+        let invalid = [
+            SolverConfig {
+                beam_width: Some(0),
+                ..SolverConfig::default()
+            },
+            SolverConfig {
+                temperature: Some(-1.0),
+                ..SolverConfig::default()
+            },
+            SolverConfig {
+                mutation_rate: Some(2.0),
+                ..SolverConfig::default()
+            },
+            SolverConfig {
+                rollout: Some("sideways".to_string()),
+                ..SolverConfig::default()
+            },
+            SolverConfig {
+                init: Some(vec!["beam=nothing".to_string()]),
+                ..SolverConfig::default()
+            },
+        ];
+        for config in invalid {
+            assert!(config.settings().is_err(), "{:?}", config);
+        }
+        let config = SolverConfig {
+            restarts: Some(0),
+            ..SolverConfig::default()
+        };
+        assert!(config.restarts().is_err());
+    }
+
+    #[test]
+    fn budget_uses_the_solver_and_objective_sections() {
+        let mut config = RunConfig::default();
+        config.solver.time_limit = Some(1.5);
+        config.solver.max_evaluations = Some(100);
+        let budget = config.budget(Some(9)).unwrap();
+        assert_eq!(budget.time_limit, Some(Duration::from_millis(1500)));
+        assert_eq!(budget.max_evaluations, Some(100));
+        assert_eq!(budget.target_score, None);
+        // 既知の最適解はstop_at_optimumのときだけ目標にし、targetがあればそちらを使う
+        config.objective.stop_at_optimum = Some(true);
+        assert_eq!(config.budget(Some(9)).unwrap().target_score, Some(9));
+        config.objective.target = Some(5);
+        assert_eq!(config.budget(Some(9)).unwrap().target_score, Some(5));
+        config.solver.time_limit = Some(f64::NAN);
+        assert!(config.budget(None).is_err());
+    }
+}
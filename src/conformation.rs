@@ -0,0 +1,438 @@
+use crate::error::Error;
+use crate::protein::{relative_direction, turn, Amino, AminoAcid, Direction, Protein};
+use std::collections::HashMap;
+
+pub type Point = (i32, i32, i32);
+
+const NEIGHBOURS: [Point; 6] = [
+    (1, 0, 0),
+    (-1, 0, 0),
+    (0, 1, 0),
+    (0, -1, 0),
+    (0, 0, 1),
+    (0, 0, -1),
+];
+
+// 配列と向きの列、そこから求めた座標と各格子点を占めるアミノ酸を、常に整合した状態で持つ構造
+// 作れるのは自己回避でつながった構造だけで、最初のアミノ酸は原点、次は(1, 0, 0)に置く
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conformation {
+    aminos: Vec<Amino>,
+    moves: Vec<Direction>,
+    pos: Vec<Point>,
+    occupied: HashMap<Point, usize>,
+    score: i32,
+}
+
+impl Conformation {
+    // 3つ目のアミノ酸から、ひとつ前の結合に対する向きに従って置いていく
+    pub fn from_moves(aminos: &[Amino], moves: &[Direction]) -> Result<Conformation, Error> {
+        if aminos.len() != moves.len() + 2 {
+            return Err(Error::LengthMismatch {
+                residues: aminos.len(),
+                directions: moves.len(),
+            });
+        }
+        let mut conformation = Conformation {
+            aminos: aminos.to_vec(),
+            moves: moves.to_vec(),
+            pos: Vec::with_capacity(aminos.len()),
+            occupied: HashMap::with_capacity(aminos.len()),
+            score: 0,
+        };
+        conformation.place((0, 0, 0))?;
+        conformation.place((1, 0, 0))?;
+        let mut prev = (1, 0, 0);
+        for &d in moves {
+            prev = turn(prev, d);
+            let last = conformation.pos[conformation.pos.len() - 1];
+            conformation.place((last.0 + prev.0, last.1 + prev.1, last.2 + prev.2))?;
+        }
+        Ok(conformation)
+    }
+
+    // 座標を平行移動・回転・鏡映して最初の結合を+x方向に合わせ、向きの列で表す
+    // 3次元の向きの表し方では±yの直後に±zへ曲がれないので、どの向きに合わせても表せないことがある
+    pub fn from_coordinates(
+        aminos: &[Amino],
+        pos: &[Point],
+        num_direct: i32,
+    ) -> Result<Conformation, Error> {
+        if aminos.len() != pos.len() || pos.len() < 2 {
+            return Err(Error::LengthMismatch {
+                residues: aminos.len(),
+                directions: pos.len().saturating_sub(2),
+            });
+        }
+        for (i, w) in pos.windows(2).enumerate() {
+            if manhattan(w[0], w[1]) != 1 {
+                return Err(Error::InvalidMove(format!(
+                    "bond {}-{} is broken: {:?} and {:?} are {} apart",
+                    i,
+                    i + 1,
+                    w[0],
+                    w[1],
+                    manhattan(w[0], w[1])
+                )));
+            }
+        }
+        let mut occupied = HashMap::new();
+        for (i, &p) in pos.iter().enumerate() {
+            if let Some(with) = occupied.insert(p, i) {
+                return Err(Error::Collision { residue: i, with });
+            }
+        }
+        let dim = if num_direct == 5 { 3 } else { 2 };
+        if dim == 2 && pos.iter().any(|p| p.2 != pos[0].2) {
+            return Err(Error::InvalidMove(
+                "the conformation is not on a 2D lattice".to_string(),
+            ));
+        }
+        let origin = pos[0];
+        for t in transforms(dim) {
+            let moved: Vec<Point> = pos
+                .iter()
+                .map(|&p| t((p.0 - origin.0, p.1 - origin.1, p.2 - origin.2)))
+                .collect();
+            if moved[1] != (1, 0, 0) {
+                continue;
+            }
+            let steps: Vec<Point> = moved
+                .windows(2)
+                .map(|w| (w[1].0 - w[0].0, w[1].1 - w[0].1, w[1].2 - w[0].2))
+                .collect();
+            let moves: Option<Vec<Direction>> = steps
+                .windows(2)
+                .map(|w| relative_direction(w[0], w[1], num_direct))
+                .collect();
+            if let Some(moves) = moves {
+                return Conformation::from_moves(aminos, &moves);
+            }
+        }
+        Err(Error::InvalidMove(
+            "the 3D encoding cannot turn to ±z right after ±y".to_string(),
+        ))
+    }
+
+    // 次のアミノ酸をpに置き、増えたH-H結合を数える
+    fn place(&mut self, p: Point) -> Result<(), Error> {
+        let i = self.pos.len();
+        if let Some(&with) = self.occupied.get(&p) {
+            return Err(Error::Collision { residue: i, with });
+        }
+        if self.aminos[i] == Amino::H {
+            for n in NEIGHBOURS {
+                let q = (p.0 + n.0, p.1 + n.1, p.2 + n.2);
+                if let Some(&j) = self.occupied.get(&q) {
+                    if j + 1 < i && self.aminos[j] == Amino::H {
+                        self.score += 1;
+                    }
+                }
+            }
+        }
+        self.occupied.insert(p, i);
+        self.pos.push(p);
+        Ok(())
+    }
+
+    // i番目の向きを変える。衝突する場合は元のまま
+    pub fn set_move(&mut self, i: usize, d: Direction) -> Result<(), Error> {
+        let mut moves = self.moves.clone();
+        moves[i] = d;
+        *self = Conformation::from_moves(&self.aminos, &moves)?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.aminos.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.aminos.is_empty()
+    }
+    pub fn aminos(&self) -> &[Amino] {
+        &self.aminos
+    }
+    pub fn moves(&self) -> &[Direction] {
+        &self.moves
+    }
+    pub fn positions(&self) -> &[Point] {
+        &self.pos
+    }
+    // pにあるアミノ酸の番号
+    pub fn residue_at(&self, p: Point) -> Option<usize> {
+        self.occupied.get(&p).copied()
+    }
+    // H-H結合(鎖上で隣り合うものは除く)の数
+    pub fn score(&self) -> i32 {
+        self.score
+    }
+    // 各結合の向きを絶対座標で表した列
+    pub fn steps(&self) -> Vec<Point> {
+        self.pos
+            .windows(2)
+            .map(|w| (w[1].0 - w[0].0, w[1].1 - w[0].1, w[1].2 - w[0].2))
+            .collect()
+    }
+    // 隣接しているH同士の組(鎖上で隣り合うものは除く)
+    pub fn hh_contacts(&self) -> Vec<(usize, usize)> {
+        let mut contacts = Vec::new();
+        for (i, &p) in self.pos.iter().enumerate() {
+            if self.aminos[i] != Amino::H {
+                continue;
+            }
+            for n in NEIGHBOURS {
+                let q = (p.0 + n.0, p.1 + n.1, p.2 + n.2);
+                if let Some(j) = self.residue_at(q) {
+                    if j > i + 1 && self.aminos[j] == Amino::H {
+                        contacts.push((i, j));
+                    }
+                }
+            }
+        }
+        contacts.sort();
+        contacts
+    }
+    pub fn max_distance(&self) -> f32 {
+        max_distance(&self.pos)
+    }
+    pub fn value(&self) -> i32 {
+        value(self.score, self.max_distance())
+    }
+    pub fn above_cube(&self) -> (f32, f32) {
+        above_cube(&self.aminos, &self.pos)
+    }
+    pub fn canonical_key(&self) -> Vec<Point> {
+        canonical_key(&self.pos)
+    }
+    pub fn to_protein(&self) -> Protein {
+        Protein {
+            size: self.aminos.len() as i32,
+            aminos: self
+                .aminos
+                .iter()
+                .zip(self.pos.iter())
+                .map(|(&amino, &pos)| AminoAcid { amino, pos })
+                .collect(),
+            direct: self.moves.clone(),
+            predict: self.score,
+        }
+    }
+}
+
+// 向きの列から、衝突を気にせずにlen個のアミノ酸の座標を求める
+pub fn walk(moves: &[Direction], len: usize) -> Vec<Point> {
+    let mut pos = vec![(0, 0, 0), (1, 0, 0)];
+    let mut prev = (1, 0, 0);
+    for &d in moves {
+        prev = turn(prev, d);
+        let last = pos[pos.len() - 1];
+        pos.push((last.0 + prev.0, last.1 + prev.1, last.2 + prev.2));
+    }
+    pos.truncate(len);
+    pos
+}
+
+pub fn manhattan(a: Point, b: Point) -> i32 {
+    (a.0 - b.0).abs() + (a.1 - b.1).abs() + (a.2 - b.2).abs()
+}
+
+// 探索で候補を比べる評価値。H-H結合が多く、広がっていない構造ほど大きい
+pub fn value(score: i32, max_distance: f32) -> i32 {
+    ((score as f32 - max_distance / 3.0) * 10.0).round() as i32
+}
+
+// 向きの列から座標を求めて(評価値, スコア)を返す。衝突する場合はNone
+pub fn evaluate(aminos: &[Amino], moves: &[Direction]) -> Option<(i32, i32)> {
+    let conformation = Conformation::from_moves(aminos, moves).ok()?;
+    Some((conformation.value(), conformation.score()))
+}
+
+// 最も離れた2つのアミノ酸の間のユークリッド距離
+pub fn max_distance(pos: &[Point]) -> f32 {
+    let mut max_distance = 0.0;
+    for &(x1, y1, z1) in pos {
+        for &(x2, y2, z2) in pos {
+            let distance = (((x1 - x2) * (x1 - x2) + (y1 - y2) * (y1 - y2) + (z1 - z2) * (z1 - z2))
+                as f32)
+                .sqrt();
+            if distance > max_distance {
+                max_distance = distance;
+            }
+        }
+    }
+    max_distance
+}
+
+// Hを囲む直方体について、(辺の長さの平均の立方体との体積の差, 体積)。2次元では面積
+pub fn above_cube(aminos: &[Amino], pos: &[Point]) -> (f32, f32) {
+    let (mut min_x, mut max_x) = (1000, -1000);
+    let (mut min_y, mut max_y) = (1000, -1000);
+    let (mut min_z, mut max_z) = (1000, -1000);
+    for (&amino, &(x, y, z)) in aminos.iter().zip(pos.iter()) {
+        if amino != Amino::H {
+            continue;
+        }
+        min_x = min_x.min(x);
+        max_x = max_x.max(x);
+        min_y = min_y.min(y);
+        max_y = max_y.max(y);
+        min_z = min_z.min(z);
+        max_z = max_z.max(z);
+    }
+    if min_z == max_z {
+        let average = (((max_x - min_x) as f32) + ((max_y - min_y) as f32)) / 2.0;
+        return (
+            average * average - (max_x - min_x) as f32 * (max_y - min_y) as f32,
+            (max_x - min_x) as f32 * (max_y - min_y) as f32,
+        );
+    }
+    let average = ((max_x as f32 - min_x as f32)
+        + (max_y as f32 - min_y as f32)
+        + (max_z as f32 - min_z as f32))
+        / 3.0;
+    (
+        average * average * average
+            - (max_x - min_x) as f32 * (max_y - min_y) as f32 * (max_z - min_z) as f32,
+        (max_x - min_x) as f32 * (max_y - min_y) as f32 * (max_z - min_z) as f32,
+    )
+}
+
+// 格子の対称操作(回転・鏡映)で重なる構造が同じ値になるように正規化した座標列
+pub fn canonical_key(pos: &[Point]) -> Vec<Point> {
+    let origin = pos[0];
+    let pos: Vec<Point> = pos
+        .iter()
+        .map(|p| (p.0 - origin.0, p.1 - origin.1, p.2 - origin.2))
+        .collect();
+    transforms(3)
+        .into_iter()
+        .map(|t| pos.iter().map(|&p| t(p)).collect::<Vec<Point>>())
+        .min()
+        .unwrap()
+}
+
+// 格子の対称操作(2次元ならz=0の面を保つもの)
+pub fn transforms(dim: usize) -> Vec<impl Fn(Point) -> Point> {
+    let perms: Vec<[usize; 3]> = if dim == 2 {
+        vec![[0, 1, 2], [1, 0, 2]]
+    } else {
+        vec![
+            [0, 1, 2],
+            [0, 2, 1],
+            [1, 0, 2],
+            [1, 2, 0],
+            [2, 0, 1],
+            [2, 1, 0],
+        ]
+    };
+    let signs = if dim == 2 { 4 } else { 8 };
+    let mut result = Vec::new();
+    for perm in perms {
+        for s in 0..signs {
+            let sign = move |k: usize| if (s >> k) & 1 == 1 { -1 } else { 1 };
+            result.push(move |p: Point| {
+                let v = [p.0, p.1, p.2];
+                (
+                    v[perm[0]] * sign(0),
+                    v[perm[1]] * sign(1),
+                    v[perm[2]] * sign(2),
+                )
+            });
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moves::directions;
+    use rand::seq::SliceRandom;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha12Rng;
+
+    // 衝突しないランダムな構造
+    fn random_conformation(rng: &mut ChaCha12Rng, num_direct: i32) -> Conformation {
+        let directions = directions(num_direct);
+        loop {
+            let len = rng.gen_range(3..20);
+            let aminos: Vec<Amino> = (0..len)
+                .map(|_| {
+                    if rng.gen_bool(0.5) {
+                        Amino::H
+                    } else {
+                        Amino::P
+                    }
+                })
+                .collect();
+            let moves: Vec<Direction> = (0..len - 2)
+                .map(|_| *directions.choose(rng).unwrap())
+                .collect();
+            if let Ok(conformation) = Conformation::from_moves(&aminos, &moves) {
+                return conformation;
+            }
+        }
+    }
+
+    // 座標と占有している格子点が一致している
+    fn assert_consistent(c: &Conformation) {
+        assert_eq!(c.occupied.len(), c.len());
+        for (i, &p) in c.positions().iter().enumerate() {
+            assert_eq!(c.residue_at(p), Some(i));
+        }
+        assert_eq!(c.positions(), walk(c.moves(), c.len()).as_slice());
+        assert_eq!(c.score() as usize, c.hh_contacts().len());
+    }
+
+    #[test]
+    fn coordinates_round_trip() {
+        let mut rng = ChaCha12Rng::seed_from_u64(1);
+        for num_direct in [3, 5] {
+            for _ in 0..100 {
+                let c = random_conformation(&mut rng, num_direct);
+                let back =
+                    Conformation::from_coordinates(c.aminos(), c.positions(), num_direct).unwrap();
+                // 3次元では同じ座標を表す向きの列が1つとは限らないので、座標で比べる
+                assert_eq!(back.positions(), c.positions());
+                assert_eq!(back.score(), c.score());
+                assert_consistent(&back);
+                // 平行移動・回転しても同じ構造に戻る
+                let t = &transforms(if num_direct == 5 { 3 } else { 2 })[3];
+                let moved: Vec<Point> = c
+                    .positions()
+                    .iter()
+                    .map(|&p| {
+                        let q = t(p);
+                        (q.0 + 4, q.1 - 7, q.2)
+                    })
+                    .collect();
+                let back = Conformation::from_coordinates(c.aminos(), &moved, num_direct);
+                if let Ok(back) = back {
+                    assert_eq!(back.score(), c.score());
+                    assert_eq!(back.canonical_key(), c.canonical_key());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn set_move_keeps_occupied_consistent() {
+        let mut rng = ChaCha12Rng::seed_from_u64(2);
+        for num_direct in [3, 5] {
+            let directions = directions(num_direct);
+            let mut c = random_conformation(&mut rng, num_direct);
+            for _ in 0..500 {
+                let i = rng.gen_range(0..c.moves().len());
+                let d = *directions.choose(&mut rng).unwrap();
+                let before = c.clone();
+                match c.set_move(i, d) {
+                    Ok(()) => assert_eq!(c.moves()[i], d),
+                    // 衝突する場合は元のまま
+                    Err(Error::Collision { .. }) => assert_eq!(c, before),
+                    Err(e) => panic!("{}", e),
+                }
+                assert_consistent(&c);
+            }
+        }
+    }
+}
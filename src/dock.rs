@@ -0,0 +1,498 @@
+use crate::budget::Budget;
+use crate::complex::{ChainMove, Complex};
+use crate::conformation::Point;
+use crate::observer::{Progress, SearchObserver};
+use crate::packed::PackedMoves;
+use rand::Rng;
+use rand_chacha::ChaCha12Rng;
+use rayon::prelude::*;
+use std::cmp::Reverse;
+use std::collections::{HashSet, VecDeque};
+use std::str::FromStr;
+
+// on_progressを呼ぶ間隔
+const REPORT_INTERVAL: usize = 1000;
+// タブーサーチとビームサーチは1回の反復で近傍を全て調べるので、こちらの間隔で知らせる
+const SEARCH_REPORT_INTERVAL: usize = 10;
+// 最後の反復での温度の、最初の温度に対する比
+const COOLING: f64 = 0.01;
+
+// 複合体の探索アルゴリズム
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DockSolver {
+    Anneal,
+    Tabu,
+    Beam,
+}
+
+impl FromStr for DockSolver {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "anneal" => Ok(DockSolver::Anneal),
+            "tabu" => Ok(DockSolver::Tabu),
+            "beam" => Ok(DockSolver::Beam),
+            _ => Err(format!("unknown dock solver: {}", s)),
+        }
+    }
+}
+
+impl DockSolver {
+    // 反復回数を指定しなかったときの既定値
+    pub fn default_iterations(&self) -> usize {
+        match self {
+            DockSolver::Anneal => 200000,
+            DockSolver::Tabu => 1000,
+            DockSolver::Beam => 100,
+        }
+    }
+}
+
+// (評価値, スコア, 複合体)
+type Candidate = (i32, i32, Complex);
+
+// 同じ複合体かを見分けるための、鎖ごとの向きの列・対称操作・位置
+type Key = Vec<(PackedMoves, usize, Point)>;
+
+fn key(complex: &Complex) -> Key {
+    complex
+        .chains
+        .iter()
+        .map(|chain| {
+            let moves = PackedMoves::from_directions(&chain.direct, complex.num_direct);
+            (moves, chain.orientation, chain.offset)
+        })
+        .collect()
+}
+
+// complexから1回の動きで移れる、衝突していない複合体を(評価値, スコア, 複合体)で列挙する
+// 評価は並列に行い、結果は動きを列挙した順に並べるのでスレッド数によらない
+fn neighbours(complex: &Complex) -> (Vec<Candidate>, u64) {
+    let moves = ChainMove::neighbourhood(complex);
+    let candidates = moves
+        .par_iter()
+        .filter_map(|m| {
+            let candidate = m.apply(complex)?;
+            let (value, score) = candidate.evaluate().ok()?;
+            Some((value, score, candidate))
+        })
+        .collect();
+    (candidates, moves.len() as u64)
+}
+
+// 複数の鎖の折りたたみと結合を同時に探す焼きなまし法
+// 1つの鎖の形を変える動きと、鎖全体を平行移動・回転させる動きを混ぜて使う
+pub struct Docking {
+    // 最初の温度。反復が進むにつれてtemperature * COOLINGまで下げる
+    temperature: f64,
+    max_iter: usize,
+    // 鎖全体を動かす確率
+    rigid: f64,
+    now: Complex,
+    now_value: i32,
+    now_score: i32,
+    best: Complex,
+    best_score: i32,
+    // 複合体を評価した回数
+    evaluations: u64,
+    rng: ChaCha12Rng,
+    // 終わった反復の回数
+    iteration: usize,
+    budget: Budget,
+}
+
+impl Docking {
+    pub fn new(
+        complex: Complex,
+        temperature: f64,
+        max_iter: usize,
+        rigid: f64,
+        rng: ChaCha12Rng,
+        budget: Budget,
+    ) -> Docking {
+        Docking {
+            temperature,
+            max_iter,
+            rigid,
+            now: complex.clone(),
+            now_value: i32::MIN,
+            now_score: -1,
+            best: complex,
+            best_score: -1,
+            evaluations: 0,
+            rng,
+            iteration: 0,
+            budget,
+        }
+    }
+    // 衝突していない中で最も良い複合体。まだ見つかっていなければ最初の複合体
+    pub fn best(&self) -> &Complex {
+        &self.best
+    }
+    pub fn best_score(&self) -> i32 {
+        self.best_score
+    }
+    pub fn evaluations(&self) -> u64 {
+        self.evaluations
+    }
+    pub fn first_step(&mut self) {
+        // 衝突している場合はどの候補でも受け入れる
+        let (value, score) = self.now.evaluate().unwrap_or((i32::MIN, -1));
+        self.evaluations += 1;
+        self.now_value = value;
+        self.now_score = score;
+        self.best = self.now.clone();
+        self.best_score = score;
+    }
+    pub fn one_step(&mut self) {
+        let mv = ChainMove::random(&self.now, self.rigid, &mut self.rng);
+        let Some(candidate) = mv.apply(&self.now) else {
+            return;
+        };
+        self.evaluations += 1;
+        let Ok((value, score)) = candidate.evaluate() else {
+            return;
+        };
+        let progress = self.iteration as f64 / self.max_iter.max(1) as f64;
+        let temperature = self.temperature * COOLING.powf(progress);
+        let accept = value >= self.now_value
+            || self
+                .rng
+                .gen_bool(((value - self.now_value) as f64 / temperature).exp());
+        if accept {
+            self.now = candidate;
+            self.now_value = value;
+            self.now_score = score;
+        }
+        if self.now_score > self.best_score {
+            self.best_score = self.now_score;
+            self.best = self.now.clone();
+        }
+    }
+    // 複合体はProteinで表せないので、observerには区切りと経過だけを知らせる
+    pub fn run(&mut self, observer: &mut dyn SearchObserver) {
+        if self.evaluations == 0 {
+            observer.on_step_start(0, "first step");
+            self.first_step();
+            observer.on_step_end(0, "first step", self.best_score);
+        }
+        for i in self.iteration..self.max_iter {
+            if self.budget.is_exhausted(self.evaluations, self.best_score) {
+                if i == 0 || i % REPORT_INTERVAL != 0 {
+                    observer.on_step_end(i, "dock", self.best_score);
+                    observer.on_progress(&self.progress(i));
+                }
+                break;
+            }
+            self.one_step();
+            self.iteration = i + 1;
+            if (i + 1) % REPORT_INTERVAL == 0 || i + 1 == self.max_iter {
+                observer.on_step_end(i + 1, "dock", self.best_score);
+                observer.on_progress(&self.progress(i + 1));
+            }
+        }
+    }
+    pub fn progress(&self, iteration: usize) -> Progress {
+        Progress {
+            iteration,
+            evaluations: self.evaluations,
+            best_score: self.best_score,
+            current_score: self.now_score,
+            diversity: 0.0,
+        }
+    }
+}
+
+// 鎖の形を変える動きと鎖全体の動きを合わせた近傍を全て調べ、禁止されていない最も良い複合体に移るタブーサーチ
+pub struct TabuDocking {
+    max_iter: usize,
+    // 一度離れた複合体に戻ることを禁止しておく反復の回数
+    tenure: usize,
+    now: Complex,
+    now_score: i32,
+    best: Complex,
+    best_score: i32,
+    evaluations: u64,
+    // 終わった反復の回数
+    iteration: usize,
+    budget: Budget,
+    // 最近tenure回の反復で離れた複合体と、それを入れた順
+    tabu: HashSet<Key>,
+    recent: VecDeque<Key>,
+}
+
+impl TabuDocking {
+    // 乱数は使わない
+    pub fn new(complex: Complex, max_iter: usize, tenure: usize, budget: Budget) -> TabuDocking {
+        TabuDocking {
+            max_iter,
+            tenure,
+            now: complex.clone(),
+            now_score: -1,
+            best: complex,
+            best_score: -1,
+            evaluations: 0,
+            iteration: 0,
+            budget,
+            tabu: HashSet::new(),
+            recent: VecDeque::new(),
+        }
+    }
+    pub fn best(&self) -> &Complex {
+        &self.best
+    }
+    pub fn best_score(&self) -> i32 {
+        self.best_score
+    }
+    pub fn evaluations(&self) -> u64 {
+        self.evaluations
+    }
+    pub fn first_step(&mut self) {
+        let (_, score) = self.now.evaluate().unwrap_or((i32::MIN, -1));
+        self.evaluations += 1;
+        self.now_score = score;
+        self.best = self.now.clone();
+        self.best_score = score;
+    }
+    // 離れる複合体を禁止し、tenure回より前に禁止したものは解く
+    fn push_tabu(&mut self, left: Key) {
+        if self.tabu.insert(left.clone()) {
+            self.recent.push_back(left);
+        }
+        while self.recent.len() > self.tenure {
+            if let Some(old) = self.recent.pop_front() {
+                self.tabu.remove(&old);
+            }
+        }
+    }
+    // 禁止されていてもベストを更新するなら移る
+    pub fn one_step(&mut self) {
+        let (candidates, evaluated) = neighbours(&self.now);
+        self.evaluations += evaluated;
+        let mut chosen: Option<Candidate> = None;
+        for (value, score, candidate) in candidates {
+            if score <= self.best_score && self.tabu.contains(&key(&candidate)) {
+                continue;
+            }
+            if chosen.as_ref().is_none_or(|c| value > c.0) {
+                chosen = Some((value, score, candidate));
+            }
+        }
+        let Some((_, score, candidate)) = chosen else {
+            return;
+        };
+        self.push_tabu(key(&self.now));
+        if score > self.best_score {
+            self.best_score = score;
+            self.best = candidate.clone();
+        }
+        self.now = candidate;
+        self.now_score = score;
+    }
+    pub fn run(&mut self, observer: &mut dyn SearchObserver) {
+        if self.evaluations == 0 {
+            observer.on_step_start(0, "first step");
+            self.first_step();
+            observer.on_step_end(0, "first step", self.best_score);
+        }
+        for i in self.iteration..self.max_iter {
+            if self.budget.is_exhausted(self.evaluations, self.best_score) {
+                if i == 0 || i % SEARCH_REPORT_INTERVAL != 0 {
+                    observer.on_step_end(i, "dock tabu", self.best_score);
+                    observer.on_progress(&self.progress(i));
+                }
+                break;
+            }
+            self.one_step();
+            self.iteration = i + 1;
+            if (i + 1) % SEARCH_REPORT_INTERVAL == 0 || i + 1 == self.max_iter {
+                observer.on_step_end(i + 1, "dock tabu", self.best_score);
+                observer.on_progress(&self.progress(i + 1));
+            }
+        }
+    }
+    pub fn progress(&self, iteration: usize) -> Progress {
+        Progress {
+            iteration,
+            evaluations: self.evaluations,
+            best_score: self.best_score,
+            current_score: self.now_score,
+            diversity: 0.0,
+        }
+    }
+}
+
+// 残した複合体それぞれの近傍を全て調べ、評価値の良いものから重複なくwidth個を残すビームサーチ
+pub struct BeamDocking {
+    max_iter: usize,
+    width: usize,
+    beam: Vec<Candidate>,
+    best: Complex,
+    best_score: i32,
+    evaluations: u64,
+    // 終わった反復の回数
+    iteration: usize,
+    budget: Budget,
+}
+
+impl BeamDocking {
+    // 乱数は使わない
+    pub fn new(complex: Complex, max_iter: usize, width: usize, budget: Budget) -> BeamDocking {
+        BeamDocking {
+            max_iter,
+            width: width.max(1),
+            beam: Vec::new(),
+            best: complex,
+            best_score: -1,
+            evaluations: 0,
+            iteration: 0,
+            budget,
+        }
+    }
+    pub fn best(&self) -> &Complex {
+        &self.best
+    }
+    pub fn best_score(&self) -> i32 {
+        self.best_score
+    }
+    pub fn evaluations(&self) -> u64 {
+        self.evaluations
+    }
+    pub fn first_step(&mut self) {
+        let (value, score) = self.best.evaluate().unwrap_or((i32::MIN, -1));
+        self.evaluations += 1;
+        self.beam = vec![(value, score, self.best.clone())];
+        self.best_score = score;
+    }
+    pub fn one_step(&mut self) {
+        let mut candidates = Vec::new();
+        for (_, _, complex) in self.beam.iter() {
+            let (found, evaluated) = neighbours(complex);
+            self.evaluations += evaluated;
+            candidates.extend(found);
+        }
+        // 並べ替えは安定なので、評価値が同じものは列挙した順に残る
+        candidates.sort_by_key(|c| Reverse(c.0));
+        let mut seen = HashSet::new();
+        let mut beam = Vec::new();
+        for candidate in candidates {
+            if beam.len() >= self.width {
+                break;
+            }
+            if seen.insert(key(&candidate.2)) {
+                beam.push(candidate);
+            }
+        }
+        if beam.is_empty() {
+            return;
+        }
+        for (_, score, complex) in beam.iter() {
+            if *score > self.best_score {
+                self.best_score = *score;
+                self.best = complex.clone();
+            }
+        }
+        self.beam = beam;
+    }
+    pub fn run(&mut self, observer: &mut dyn SearchObserver) {
+        if self.evaluations == 0 {
+            observer.on_step_start(0, "first step");
+            self.first_step();
+            observer.on_step_end(0, "first step", self.best_score);
+        }
+        for i in self.iteration..self.max_iter {
+            if self.budget.is_exhausted(self.evaluations, self.best_score) {
+                if i == 0 || i % SEARCH_REPORT_INTERVAL != 0 {
+                    observer.on_step_end(i, "dock beam", self.best_score);
+                    observer.on_progress(&self.progress(i));
+                }
+                break;
+            }
+            self.one_step();
+            self.iteration = i + 1;
+            if (i + 1) % SEARCH_REPORT_INTERVAL == 0 || i + 1 == self.max_iter {
+                observer.on_step_end(i + 1, "dock beam", self.best_score);
+                observer.on_progress(&self.progress(i + 1));
+            }
+        }
+    }
+    pub fn progress(&self, iteration: usize) -> Progress {
+        Progress {
+            iteration,
+            evaluations: self.evaluations,
+            best_score: self.best_score,
+            current_score: self.beam.first().map_or(-1, |b| b.1),
+            diversity: 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmark::parse_amino_str;
+
+    fn complex(num_direct: i32) -> Complex {
+        let sequences: Vec<_> = ["HPHHPH", "HHPHH"]
+            .iter()
+            .map(|s| parse_amino_str(s))
+            .collect();
+        Complex::new(&sequences, num_direct)
+    }
+
+    // どの鎖のアミノ酸も別の格子点にあり、スコアが数え直した結合の数と合う
+    fn assert_valid(complex: &Complex, score: i32) {
+        let positions: Vec<Point> = complex.positions().into_iter().flatten().collect();
+        let distinct: HashSet<&Point> = positions.iter().collect();
+        assert_eq!(distinct.len(), positions.len());
+        assert_eq!(complex.contacts().unwrap().score(), score);
+    }
+
+    #[test]
+    fn neighbours_skip_overlapping_complexes() {
+        for num_direct in [3, 5] {
+            let mut complex = complex(num_direct);
+            // 隣に並べておくと、鎖を近づける平行移動は衝突する
+            complex.chains[1].offset = (0, 1, 0);
+            let (candidates, evaluated) = neighbours(&complex);
+            assert_eq!(evaluated, ChainMove::neighbourhood(&complex).len() as u64);
+            assert!((candidates.len() as u64) < evaluated);
+            for (value, score, candidate) in candidates.iter() {
+                assert_valid(candidate, *score);
+                assert_eq!(candidate.evaluate().unwrap(), (*value, *score));
+            }
+        }
+    }
+
+    #[test]
+    fn tabu_keeps_chains_apart() {
+        for num_direct in [3, 5] {
+            let mut tabu = TabuDocking::new(complex(num_direct), 30, 10, Budget::default());
+            tabu.first_step();
+            for _ in 0..30 {
+                tabu.one_step();
+                assert_valid(&tabu.now, tabu.now_score);
+            }
+            assert_valid(tabu.best(), tabu.best_score());
+            assert!(tabu.best().contacts().unwrap().interface_score() > 0);
+        }
+    }
+
+    #[test]
+    fn beam_keeps_chains_apart() {
+        for num_direct in [3, 5] {
+            let mut beam = BeamDocking::new(complex(num_direct), 8, 5, Budget::default());
+            beam.first_step();
+            for _ in 0..8 {
+                beam.one_step();
+                assert!(!beam.beam.is_empty() && beam.beam.len() <= 5);
+                for (_, score, complex) in beam.beam.iter() {
+                    assert_valid(complex, *score);
+                }
+            }
+            assert_valid(beam.best(), beam.best_score());
+            assert!(beam.best().contacts().unwrap().interface_score() > 0);
+        }
+    }
+}
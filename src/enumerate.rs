@@ -0,0 +1,172 @@
+use crate::budget::Budget;
+use crate::protein::{turn, Amino, Direction, Protein};
+use std::collections::{HashMap, HashSet};
+
+type Point = (i32, i32, i32);
+
+const DIRECTIONS: [Direction; 5] = [
+    Direction::S,
+    Direction::L,
+    Direction::R,
+    Direction::U,
+    Direction::D,
+];
+
+// 向きの列を前から全て試す分枝限定法。最適解と、回転・鏡映で重ならない最適解の数を求める
+// 探索する構造の数は鎖の長さに対して指数的に増えるので、短い配列向け
+pub struct Enumerator {
+    pub best_ans: Protein,
+    pub best_score: i32,
+    pub num_direct: i32,
+    pub budget: Budget,
+    // 調べた途中の構造の数
+    pub nodes: u64,
+    // 最適解の正規化した座標列
+    pub optima: HashSet<Vec<Point>>,
+    // 打ち切らずに全て調べ終えたか
+    pub complete: bool,
+}
+
+impl Enumerator {
+    pub fn new(protein: &Protein, num_direct: i32, budget: Budget) -> Enumerator {
+        Enumerator {
+            best_ans: protein.clone(),
+            best_score: -1,
+            num_direct,
+            budget,
+            nodes: 0,
+            optima: HashSet::new(),
+            complete: false,
+        }
+    }
+
+    pub fn run(&mut self) {
+        let n = self.best_ans.aminos.len();
+        let is_h: Vec<bool> = self
+            .best_ans
+            .aminos
+            .iter()
+            .map(|a| a.amino == Amino::H)
+            .collect();
+        // i番目以降のアミノ酸を置いたときに増えるH-H結合の数の上限
+        // 新しく置いたHは、偶奇の異なる3つ以上前のHとしか結合できず、空いている隣の数も限られる
+        let cap = if self.num_direct == 5 { 6 } else { 4 };
+        let mut rest = vec![0; n + 1];
+        let mut earlier = [0, 0];
+        for i in 0..n {
+            if i >= 3 && is_h[i - 3] {
+                earlier[(i - 3) % 2] += 1;
+            }
+            if is_h[i] {
+                let free = if i == n - 1 { cap - 1 } else { cap - 2 };
+                rest[i] = earlier[1 - i % 2].min(free);
+            }
+        }
+        for i in (0..n).rev() {
+            rest[i] += rest[i + 1];
+        }
+        let mut search = Search {
+            is_h,
+            rest,
+            occupied: HashMap::from([((0, 0, 0), 0), ((1, 0, 0), 1)]),
+            direct: Vec::new(),
+            stopped: false,
+        };
+        let template = self.best_ans.clone();
+        search.extend(self, &template, (1, 0, 0), (1, 0, 0), 0);
+        self.complete = !search.stopped;
+    }
+}
+
+struct Search {
+    is_h: Vec<bool>,
+    rest: Vec<i32>,
+    occupied: HashMap<Point, usize>,
+    direct: Vec<Direction>,
+    stopped: bool,
+}
+
+impl Search {
+    fn extend(
+        &mut self,
+        enumerator: &mut Enumerator,
+        template: &Protein,
+        pos: Point,
+        prev: Point,
+        score: i32,
+    ) {
+        let i = self.direct.len() + 2;
+        if i == self.is_h.len() {
+            if score < enumerator.best_score {
+                return;
+            }
+            if score > enumerator.best_score {
+                enumerator.best_score = score;
+                enumerator.optima.clear();
+            }
+            let mut protein = template.clone();
+            protein.direct = self.direct.clone();
+            if enumerator.optima.is_empty() {
+                enumerator.best_ans = protein.clone();
+            }
+            enumerator.optima.insert(protein.canonical_key());
+            return;
+        }
+        // 最適解を全て数えるので、上限が今のベストと等しい枝は残す
+        if score + self.rest[i] < enumerator.best_score {
+            return;
+        }
+        enumerator.nodes += 1;
+        if enumerator.nodes.is_multiple_of(4096)
+            && enumerator
+                .budget
+                .is_exhausted(enumerator.nodes, enumerator.best_score)
+        {
+            self.stopped = true;
+        }
+        if self.stopped {
+            return;
+        }
+        // 2次元ではy軸での鏡映で重なる構造を除くため、最初に曲がるのは左だけにする
+        let straight = self.direct.iter().all(|&d| d == Direction::S);
+        let mut tried = Vec::new();
+        for &d in DIRECTIONS.iter().take(enumerator.num_direct as usize) {
+            if enumerator.num_direct == 3 && straight && d == Direction::R {
+                continue;
+            }
+            let step = turn(prev, d);
+            // 3次元の向きの表し方では、異なる向きが同じ結合になることがある
+            if tried.contains(&step) {
+                continue;
+            }
+            tried.push(step);
+            let next = (pos.0 + step.0, pos.1 + step.1, pos.2 + step.2);
+            if self.occupied.contains_key(&next) {
+                continue;
+            }
+            let mut gain = 0;
+            if self.is_h[i] {
+                for s in [
+                    (1, 0, 0),
+                    (-1, 0, 0),
+                    (0, 1, 0),
+                    (0, -1, 0),
+                    (0, 0, 1),
+                    (0, 0, -1),
+                ] {
+                    let q = (next.0 + s.0, next.1 + s.1, next.2 + s.2);
+                    if let Some(&j) = self.occupied.get(&q) {
+                        if j + 1 < i && self.is_h[j] {
+                            gain += 1;
+                        }
+                    }
+                }
+            }
+            self.occupied.insert(next, i);
+            self.direct.push(d);
+            self.extend(enumerator, template, next, step, score + gain);
+            self.direct.pop();
+            self.occupied.remove(&next);
+        }
+    }
+}
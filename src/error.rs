@@ -0,0 +1,108 @@
+use crate::validate::Defect;
+use plotters::drawing::DrawingAreaErrorKind;
+use std::fmt;
+use std::io;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// ライブラリの関数が返すエラー
+#[derive(Debug)]
+pub enum Error {
+    // residue番目のアミノ酸が、それより前のwith番目と同じ格子点に置かれた
+    Collision {
+        residue: usize,
+        with: usize,
+    },
+    // 複合体で、chain番目の鎖のresidue番目のアミノ酸が、with_chain番目の鎖のwith番目と同じ格子点に置かれた
+    ChainCollision {
+        chain: usize,
+        residue: usize,
+        with_chain: usize,
+        with: usize,
+    },
+    // residues個のアミノ酸にはresidues - 2個の向きが必要
+    LengthMismatch {
+        residues: usize,
+        directions: usize,
+    },
+    // その格子では使えない向きや、表せない移動
+    InvalidMove(String),
+    // 配列やファイルの中身が読めない
+    Parse(String),
+    // 座標で与えた構造の鎖が切れていたり衝突していたりする。その箇所を全て持つ
+    Defects(Vec<Defect>),
+    // ファイルの読み書きの失敗
+    Io {
+        path: String,
+        source: io::Error,
+    },
+    // 画像の描画や書き出しの失敗
+    Render(String),
+    // JSONやTOMLへの変換の失敗
+    Serialize(String),
+}
+
+impl Error {
+    pub fn io(path: &str, source: io::Error) -> Error {
+        Error::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Collision { residue, with } => {
+                write!(f, "residue {} collides with residue {}", residue, with)
+            }
+            Error::ChainCollision {
+                chain,
+                residue,
+                with_chain,
+                with,
+            } => write!(
+                f,
+                "residue {} of chain {} collides with residue {} of chain {}",
+                residue, chain, with, with_chain
+            ),
+            Error::LengthMismatch {
+                residues,
+                directions,
+            } => write!(
+                f,
+                "{} residues need {} directions, got {}",
+                residues,
+                residues.saturating_sub(2),
+                directions
+            ),
+            Error::InvalidMove(message)
+            | Error::Parse(message)
+            | Error::Render(message)
+            | Error::Serialize(message) => write!(f, "{}", message),
+            Error::Defects(defects) => {
+                for defect in defects.iter() {
+                    writeln!(f, "{}", defect)?;
+                }
+                write!(f, "invalid conformation ({} problems)", defects.len())
+            }
+            Error::Io { path, source } => write!(f, "{}: {}", path, source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl<E: std::error::Error + Send + Sync> From<DrawingAreaErrorKind<E>> for Error {
+    fn from(e: DrawingAreaErrorKind<E>) -> Error {
+        Error::Render(e.to_string())
+    }
+}
@@ -0,0 +1,313 @@
+use crate::beam::{distance, diversity};
+use crate::budget::Budget;
+use crate::checkpoint::{GeneticState, RngState, SolverState};
+use crate::conformation::value;
+use crate::init::Init;
+use crate::moves::{directions, swap_steps};
+use crate::observer::{Progress, SearchObserver};
+use crate::protein::{Direction, Protein};
+use crate::solve::{Solver, SolverSettings};
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use std::cmp::Reverse;
+
+// 突然変異で衝突しない変化が見つかるまで試す回数
+const MUTATION_ATTEMPTS: usize = 10;
+
+pub struct Genetic {
+    population_size: usize,
+    // 交叉・突然変異を行う確率
+    crossover_rate: f64,
+    mutation_rate: f64,
+    // トーナメント選択で比べる個体の数
+    tournament_size: usize,
+    // そのまま次の世代に残す上位の個体の数
+    elitism: usize,
+    // 向きが異なる箇所の割合がこれより小さい個体は同じニッチとみなし、良い方だけを残す
+    niche_radius: f64,
+    // 交叉で衝突したときに切る位置を変えて試す回数
+    crossover_attempts: usize,
+    population: Vec<Protein>,
+    // populationと同じ順の評価値(get_value)
+    values: Vec<i32>,
+    best_ans: Protein,
+    best_score: i32,
+    num_direct: i32,
+    evaluations: u64,
+    rng: ChaCha12Rng,
+    // 終わった世代の数
+    iteration: usize,
+    budget: Budget,
+    // 初期集団の個体の作り方
+    init: Init,
+}
+
+impl Genetic {
+    pub fn new(
+        template: &Protein,
+        num_direct: i32,
+        settings: &SolverSettings,
+        seed: u64,
+        budget: Budget,
+    ) -> Genetic {
+        Genetic {
+            population_size: settings.population,
+            crossover_rate: settings.crossover_rate,
+            mutation_rate: settings.mutation_rate,
+            tournament_size: settings.tournament_size,
+            elitism: settings.elitism,
+            niche_radius: settings.niche_radius,
+            crossover_attempts: settings.crossover_attempts,
+            population: Vec::new(),
+            values: Vec::new(),
+            best_ans: template.clone(),
+            best_score: 0,
+            num_direct,
+            evaluations: 0,
+            rng: ChaCha12Rng::seed_from_u64(seed),
+            iteration: 0,
+            budget,
+            init: settings.init_for(Solver::Genetic),
+        }
+    }
+    pub fn best_ans(&self) -> &Protein {
+        &self.best_ans
+    }
+    pub fn best_score(&self) -> i32 {
+        self.best_score
+    }
+    pub fn evaluations(&self) -> u64 {
+        self.evaluations
+    }
+    pub fn directions(&self) -> Vec<Direction> {
+        directions(self.num_direct)
+    }
+    pub fn is_exhausted(&self) -> bool {
+        self.budget.is_exhausted(self.evaluations, self.best_score)
+    }
+    // 衝突した場合はNone、そうでなければ(評価値, スコア)
+    fn evaluate(&mut self, protein: &mut Protein) -> Option<(i32, i32)> {
+        self.evaluations += 1;
+        let score = protein.score().ok()?;
+        Some((value(score, protein.calc_max_distance()), score))
+    }
+    pub fn first_step(&mut self) {
+        self.population.clear();
+        self.values.clear();
+        while self.population.len() < self.population_size {
+            let mut protein = self
+                .init
+                .build(&self.best_ans, self.num_direct, &mut self.rng);
+            if let Some((value, score)) = self.evaluate(&mut protein) {
+                if self.population.is_empty() || self.best_score < score {
+                    self.best_score = score;
+                    self.best_ans = protein.clone();
+                }
+                self.population.push(protein);
+                self.values.push(value);
+            }
+        }
+    }
+    // 評価値の良い順に見ていき、すでに残した個体とniche_radius以内の個体をはじく
+    fn cleared(&self) -> Vec<bool> {
+        let len = (self.best_ans.aminos.len() - 2).max(1) as f64;
+        let mut order: Vec<usize> = (0..self.population.len()).collect();
+        order.sort_by_key(|&i| Reverse(self.values[i]));
+        let mut winners: Vec<usize> = Vec::new();
+        let mut cleared = vec![false; self.population.len()];
+        for i in order {
+            let near = winners.iter().any(|&w| {
+                (distance(&self.population[i], &self.population[w]) as f64 / len)
+                    < self.niche_radius
+            });
+            if near {
+                cleared[i] = true;
+            } else {
+                winners.push(i);
+            }
+        }
+        cleared
+    }
+    fn tournament(&mut self, cleared: &[bool]) -> usize {
+        let mut winner = self.rng.gen_range(0..self.population.len());
+        for _ in 1..self.tournament_size {
+            let i = self.rng.gen_range(0..self.population.len());
+            if (!cleared[i], self.values[i]) > (!cleared[winner], self.values[winner]) {
+                winner = i;
+            }
+        }
+        winner
+    }
+    // aの前半とbの後半をつなぐ。衝突したら切り口の前後の向きを変えて直し、だめなら切る位置を変える
+    fn crossover(&mut self, a: &Protein, b: &Protein) -> Protein {
+        let len = a.direct.len();
+        if len < 2 {
+            return a.clone();
+        }
+        let directions = self.directions();
+        for _ in 0..self.crossover_attempts {
+            let cut = self.rng.gen_range(1..len);
+            let mut child = a.clone();
+            child.direct[cut..].copy_from_slice(&b.direct[cut..]);
+            self.evaluations += 1;
+            if child.score().is_ok() {
+                return child;
+            }
+            for k in cut - 1..(cut + 1).min(len) {
+                let original = child.direct[k];
+                for &d in directions.iter() {
+                    if d == original {
+                        continue;
+                    }
+                    child.direct[k] = d;
+                    self.evaluations += 1;
+                    if child.score().is_ok() {
+                        return child;
+                    }
+                }
+                child.direct[k] = original;
+            }
+        }
+        a.clone()
+    }
+    // 格子上の局所的な動きで個体を変える。衝突しないものが見つからなければそのまま
+    fn mutate(&mut self, protein: &mut Protein) {
+        // 向きが1つもない(アミノ酸が2つ以下の)ときは変えられるところがない
+        if protein.direct.is_empty() {
+            return;
+        }
+        let directions = self.directions();
+        for _ in 0..MUTATION_ATTEMPTS {
+            let mut mutant = protein.clone();
+            if self.rng.gen_bool(0.5) {
+                // 1箇所の向きを変える(それより後ろ全体が回転する)
+                let k = self.rng.gen_range(0..mutant.direct.len());
+                mutant.direct[k] = *directions.choose(&mut self.rng).unwrap();
+                if mutant.direct[k] == protein.direct[k] {
+                    continue;
+                }
+            } else if !self.local_move(&mut mutant) {
+                continue;
+            }
+            self.evaluations += 1;
+            if mutant.score().is_ok() {
+                *protein = mutant;
+                return;
+            }
+        }
+    }
+    // 角の移動かクランクシャフトをランダムな位置で行う
+    fn local_move(&mut self, protein: &mut Protein) -> bool {
+        let len = protein.direct.len();
+        let gap = self.rng.gen_range(1..=2);
+        if len < gap + 1 {
+            return false;
+        }
+        let k = self.rng.gen_range(1..=len - gap);
+        swap_steps(protein, k, gap, self.num_direct)
+    }
+    // 1世代分の選択・交叉・突然変異を行う。budgetを使い切って途中で打ち切った場合はfalseを返す
+    pub fn one_generation(&mut self, observer: &mut dyn SearchObserver) -> bool {
+        let cleared = self.cleared();
+        // 別々のニッチにいる上位の個体をそのまま残す
+        let mut order: Vec<usize> = (0..self.population.len()).collect();
+        order.sort_by_key(|&i| Reverse((!cleared[i], self.values[i])));
+        let mut population = Vec::new();
+        let mut values = Vec::new();
+        for &i in order.iter().take(self.elitism) {
+            population.push(self.population[i].clone());
+            values.push(self.values[i]);
+        }
+        while population.len() < self.population_size {
+            if self.is_exhausted() {
+                return false;
+            }
+            let a = self.tournament(&cleared);
+            let b = self.tournament(&cleared);
+            let (a, b) = (self.population[a].clone(), self.population[b].clone());
+            let mut child = if self.rng.gen_bool(self.crossover_rate) {
+                self.crossover(&a, &b)
+            } else {
+                a
+            };
+            if self.rng.gen_bool(self.mutation_rate) {
+                self.mutate(&mut child);
+            }
+            let Some((value, score)) = self.evaluate(&mut child) else {
+                continue;
+            };
+            if self.best_score < score {
+                self.best_score = score;
+                self.best_ans = child.clone();
+                observer.on_new_best(&child, score);
+            }
+            observer.on_candidate_accepted(&child, score);
+            population.push(child);
+            values.push(value);
+        }
+        self.population = population;
+        self.values = values;
+        true
+    }
+    // 自己回避歩行の集団から始めて、generations世代まで進める
+    pub fn run(&mut self, generations: usize, observer: &mut dyn SearchObserver) {
+        if self.evaluations == 0 {
+            observer.on_step_start(0, "initial population");
+            self.first_step();
+            observer.on_step_end(0, "initial population", self.best_score);
+            observer.on_checkpoint(&self.state());
+        }
+        for i in self.iteration..generations {
+            if self.is_exhausted() {
+                if i == 0 {
+                    observer.on_progress(&self.progress(0));
+                }
+                break;
+            }
+            observer.on_step_start(i, "generation");
+            let completed = self.one_generation(observer);
+            observer.on_step_end(i, "generation", self.best_score);
+            observer.on_progress(&self.progress(i + 1));
+            if completed {
+                self.iteration = i + 1;
+                observer.on_checkpoint(&self.state());
+            }
+        }
+        observer.on_finished(&self.best_ans, self.best_score);
+    }
+    pub fn progress(&self, iteration: usize) -> Progress {
+        Progress {
+            iteration,
+            evaluations: self.evaluations,
+            best_score: self.best_score,
+            current_score: self
+                .population
+                .iter()
+                .map(|protein| protein.predict)
+                .max()
+                .unwrap_or(0),
+            diversity: diversity(&self.population),
+        }
+    }
+    pub fn state(&self) -> SolverState {
+        SolverState::Genetic(GeneticState {
+            iteration: self.iteration,
+            population: self.population.clone(),
+            values: self.values.clone(),
+            best_ans: self.best_ans.clone(),
+            best_score: self.best_score,
+            evaluations: self.evaluations,
+            rng: RngState::new(&self.rng),
+        })
+    }
+    pub fn restore(&mut self, state: GeneticState) {
+        self.iteration = state.iteration;
+        self.population = state.population;
+        self.values = state.values;
+        self.best_ans = state.best_ans;
+        self.best_score = state.best_score;
+        self.evaluations = state.evaluations;
+        self.rng = state.rng.to_rng();
+    }
+}
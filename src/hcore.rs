@@ -0,0 +1,930 @@
+use crate::bounds::max_edges;
+use crate::budget::Budget;
+use crate::conformation::{manhattan, transforms, Conformation};
+use crate::observer::{Progress, SearchObserver};
+use crate::protein::{Amino, Direction, Protein};
+use crate::solve::SolverSettings;
+use std::collections::{HashMap, HashSet};
+
+type Point = (i32, i32, i32);
+
+// H-コアを使った厳密解法(CPSPと同じ考え方)
+// 1. Hの数と同じ個数の格子点の集合(H-コア)を、隣接する組の数が多い順に列挙する
+// 2. 配列をそのコアに通せるか(Hはコアの点、Pはコアの外)をバックトラックで調べる
+// H-H結合の数は(コア内の隣接する組の数) - (鎖上で隣り合うHの組の数)なので、
+// 上の段階から順に調べて最初に通せたものが最適解になる
+pub struct HCore {
+    // 1つの段階で列挙するコアの数の上限
+    max_cores: usize,
+    // 1つのコアに配列を通すときに調べるノードの数の上限
+    max_nodes: u64,
+    // 調べるコアの段階の数の上限
+    max_levels: usize,
+    best_ans: Protein,
+    best_score: i32,
+    num_direct: i32,
+    // 調べたノード(コアと配列を通す途中の状態)の数
+    evaluations: u64,
+    budget: Budget,
+    // まだ否定できていない最も良いスコア
+    upper_bound: Option<i32>,
+    // best_scoreが最適であることを示せたか
+    proven: bool,
+}
+
+fn neighbours(p: Point, dim: usize) -> impl Iterator<Item = Point> {
+    let d: [Point; 6] = [
+        (1, 0, 0),
+        (-1, 0, 0),
+        (0, 1, 0),
+        (0, -1, 0),
+        (0, 0, 1),
+        (0, 0, -1),
+    ];
+    d.into_iter()
+        .take(dim * 2)
+        .map(move |(dx, dy, dz)| (p.0 + dx, p.1 + dy, p.2 + dz))
+}
+
+fn parity(p: Point) -> usize {
+    (p.0 + p.1 + p.2).rem_euclid(2) as usize
+}
+
+// 平行移動と対称操作で重なるコアが同じになるように正規化した点の列
+fn canonical(points: &[Point], dim: usize) -> Vec<Point> {
+    transforms(dim)
+        .iter()
+        .map(|t| {
+            let mut moved: Vec<Point> = points.iter().map(|&p| t(p)).collect();
+            let min = moved.iter().fold((i32::MAX, i32::MAX, i32::MAX), |m, p| {
+                (m.0.min(p.0), m.1.min(p.1), m.2.min(p.2))
+            });
+            for p in moved.iter_mut() {
+                *p = (p.0 - min.0, p.1 - min.1, p.2 - min.2);
+            }
+            moved.sort();
+            moved
+        })
+        .min()
+        .unwrap()
+}
+
+// p×qの長方形の中のm点の間で隣接する組の数の上限
+// 点を含む行の数r・列の数cだけ2mより少なくなり、r×cはm以上になる
+fn rectangle_edges(m: usize, p: usize, q: usize) -> usize {
+    if m == 0 {
+        return 0;
+    }
+    let lines = (1..=p.min(m))
+        .filter(|&r| m.div_ceil(r) <= q)
+        .map(|r| r + m.div_ceil(r))
+        .min()
+        .unwrap_or(2 * m);
+    2 * m - lines
+}
+
+// 箱の中のn点の隣接する組の数の上限
+// 各軸に平行な直線のうち点を含むものの数だけ、組の数はdim×nより少なくなる
+// その直線は点の数÷軸の長さ以上あり、ほかの軸で点を含む面の数以上ある
+// 点のない面が続けてmax_gap枚までなら、長さdの軸で点を含む面は(d+max_gap)/(max_gap+1)枚以上
+fn line_bound(dims: Point, n: usize, dim: usize, max_gap: usize) -> usize {
+    let d = [dims.0 as usize, dims.1 as usize, dims.2 as usize];
+    let lines: usize = (0..dim)
+        .map(|axis| {
+            let span = (0..dim)
+                .filter(|&k| k != axis)
+                .map(|k| (d[k] + max_gap).div_ceil(max_gap + 1))
+                .max()
+                .unwrap_or(1);
+            n.div_ceil(d[axis]).max(span)
+        })
+        .sum();
+    (dim * n).saturating_sub(lines)
+}
+
+// 箱を横切る面で点のないものがあるコアは、その面の両側の2つに分かれ、両側の間には組がない
+// そのようなコアの組の数は、2つに分けたそれぞれの上限の和を超えない
+fn split_bound(n: usize, dim: usize) -> usize {
+    (1..n)
+        .map(|k| max_edges(k, dim) + max_edges(n - k, dim))
+        .max()
+        .unwrap_or(0)
+}
+
+// a×b×cの箱の中でn点のコアを分枝限定法で列挙する
+// 1つ目の軸で箱を層に分け、層ごとの点の数の並びを決めてから、点を辞書順に入れるかどうか決めていく
+// 各点はそれより前の隣接点とだけ組を作ると数える
+// 最初と最後以外の層には、max_gap枚まで続けて点のない層があってもよい
+struct CoreSearch {
+    dims: Point,
+    cells: Vec<Point>,
+    // 辞書順で前にある隣接点の番号(前の層の点は最初に入れる)
+    lower: Vec<Vec<usize>>,
+    state: Vec<Option<bool>>,
+    n: usize,
+    target: usize,
+    dim: usize,
+    max_gap: usize,
+    // 1つの層の点の数
+    layer_len: usize,
+    // inner[m]: 1つの層のm点の間の組の数の上限
+    inner: Vec<usize>,
+    // table[l][r][last]: 残りr点をちょうどl枚の層に置き、直前の層がlast点のときに増やせる組の数の上限
+    table: Vec<Vec<Vec<Option<usize>>>>,
+    // 層ごとの点の数と、それより後ろの層で増やせる組の数の上限
+    sizes: Vec<usize>,
+    rest: Vec<usize>,
+    // 層ごとに入れた点の数、層の中の組の数、前の層との組の数
+    counts: Vec<usize>,
+    within: Vec<usize>,
+    inter: Vec<usize>,
+    max_cores: usize,
+    found: HashSet<Vec<Point>>,
+    complete: bool,
+    nodes: u64,
+    budget: Budget,
+    // 探索を始める前までの評価回数
+    evaluations: u64,
+}
+
+impl CoreSearch {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        dims: Point,
+        n: usize,
+        target: usize,
+        dim: usize,
+        max_gap: usize,
+        max_cores: usize,
+        budget: &Budget,
+        evaluations: u64,
+    ) -> CoreSearch {
+        let layers = dims.0 as usize;
+        let layer_len = (dims.1 * dims.2) as usize;
+        let cap = layer_len.min(n);
+        let inner: Vec<usize> = (0..=cap)
+            .map(|m| rectangle_edges(m, dims.1 as usize, dims.2 as usize))
+            .collect();
+        let mut table = vec![vec![vec![None; cap + 1]; n + 1]; layers + 1];
+        table[0][0] = vec![Some(0); cap + 1];
+        for l in 1..=layers {
+            // 最後の層には点がある
+            let first = if max_gap > 0 && l > 1 { 0 } else { 1 };
+            for r in 1..=n {
+                for last in 0..=cap {
+                    table[l][r][last] = (first..=cap.min(r))
+                        .filter_map(|s| {
+                            table[l - 1][r - s][s].map(|rest| inner[s] + last.min(s) + rest)
+                        })
+                        .max();
+                }
+            }
+        }
+        CoreSearch {
+            dims,
+            state: Vec::new(),
+            cells: Vec::new(),
+            lower: Vec::new(),
+            n,
+            target,
+            dim,
+            max_gap,
+            layer_len,
+            inner,
+            table,
+            sizes: Vec::new(),
+            rest: Vec::new(),
+            counts: vec![0; layers],
+            within: vec![0; layers],
+            inter: vec![0; layers],
+            max_cores,
+            found: HashSet::new(),
+            complete: true,
+            nodes: 0,
+            budget: budget.clone(),
+            evaluations,
+        }
+    }
+    // 箱の中のn点の隣接する組の数の上限
+    fn bound(&self) -> usize {
+        let layers = self.dims.0 as usize;
+        self.table[layers][self.n][0].unwrap_or(0)
+    }
+    // 組の数の上限がtarget以上になる層の点の数の並びを全て試す
+    // 箱の点とその隣接は、上限で落ちなかった箱でだけ作る
+    fn run(&mut self) {
+        if self.bound() < self.target {
+            return;
+        }
+        let dims = self.dims;
+        for x in 0..dims.0 {
+            for y in 0..dims.1 {
+                for z in 0..dims.2 {
+                    self.cells.push((x, y, z));
+                }
+            }
+        }
+        let index: HashMap<Point, usize> = self
+            .cells
+            .iter()
+            .enumerate()
+            .map(|(i, &p)| (p, i))
+            .collect();
+        self.lower = self
+            .cells
+            .iter()
+            .enumerate()
+            .map(|(i, &p)| {
+                neighbours(p, self.dim)
+                    .filter_map(|q| index.get(&q).copied())
+                    .filter(|&j| j < i)
+                    .collect()
+            })
+            .collect();
+        self.state = vec![None; self.cells.len()];
+        self.layers(0, 0);
+    }
+    fn layers(&mut self, placed: usize, edges: usize) {
+        let layers = self.dims.0 as usize;
+        let l = self.sizes.len();
+        if l == layers {
+            let mut rest = vec![0; layers];
+            for x in (0..layers - 1).rev() {
+                let (a, b) = (self.sizes[x], self.sizes[x + 1]);
+                rest[x] = rest[x + 1] + self.inner[b] + a.min(b);
+            }
+            self.rest = rest;
+            self.search(0, 0, 0);
+            return;
+        }
+        let last = self.sizes.last().copied().unwrap_or(0);
+        let r = self.n - placed;
+        // 箱の両端の層には点がある
+        let empty = self.sizes.iter().rev().take_while(|&&s| s == 0).count();
+        let first = if l > 0 && l + 1 < layers && empty < self.max_gap {
+            0
+        } else {
+            1
+        };
+        for s in first..=self.inner.len().saturating_sub(1).min(r) {
+            let gain = self.inner[s] + last.min(s);
+            let Some(rest) = self.table[layers - l - 1][r - s][s] else {
+                continue;
+            };
+            if edges + gain + rest < self.target {
+                continue;
+            }
+            self.sizes.push(s);
+            self.layers(placed + s, edges + gain);
+            self.sizes.pop();
+            if !self.complete {
+                return;
+            }
+        }
+    }
+    // まだ決めていない点に残りの点を入れたときに増やせる組の数の上限
+    fn potential(&self, k: usize, count: usize) -> usize {
+        let mut potential: Vec<usize> = (k..self.cells.len())
+            .map(|j| {
+                self.lower[j]
+                    .iter()
+                    .filter(|&&l| self.state[l] != Some(false))
+                    .count()
+            })
+            .collect();
+        potential.sort_unstable_by(|a, b| b.cmp(a));
+        potential.iter().take(self.n - count).sum()
+    }
+    // 層ごとの点の数から見た、残りで増やせる組の数の上限
+    fn layer_potential(&self, x: usize) -> usize {
+        let r = self.sizes[x] - self.counts[x];
+        let within = self.inner[self.sizes[x]]
+            .saturating_sub(self.within[x])
+            .min(2 * r);
+        let inter = if x == 0 {
+            0
+        } else {
+            (self.sizes[x - 1].min(self.sizes[x]) - self.inter[x]).min(r)
+        };
+        within + inter + self.rest[x]
+    }
+    fn search(&mut self, k: usize, count: usize, edges: usize) {
+        self.nodes += 1;
+        // 打ち切った場合はcompleteを下ろす
+        if !self.complete {
+            return;
+        }
+        if self.found.len() >= self.max_cores
+            || (self.nodes.is_multiple_of(4096)
+                && self
+                    .budget
+                    .is_exhausted(self.evaluations + self.nodes, i32::MIN))
+        {
+            self.complete = false;
+            return;
+        }
+        if count == self.n {
+            if edges == self.target {
+                self.accept();
+            }
+            return;
+        }
+        let x = k / self.layer_len;
+        let left = (x + 1) * self.layer_len - k;
+        let need = self.sizes[x] - self.counts[x];
+        if need > left
+            || edges + self.layer_potential(x) < self.target
+            || edges + self.potential(k, count) < self.target
+        {
+            return;
+        }
+        if need > 0 {
+            let mut within = 0;
+            let mut inter = 0;
+            for &l in self.lower[k].iter() {
+                if self.state[l] == Some(true) {
+                    if l + self.layer_len == k {
+                        inter += 1;
+                    } else {
+                        within += 1;
+                    }
+                }
+            }
+            self.state[k] = Some(true);
+            self.counts[x] += 1;
+            self.within[x] += within;
+            self.inter[x] += inter;
+            self.search(k + 1, count + 1, edges + within + inter);
+            self.counts[x] -= 1;
+            self.within[x] -= within;
+            self.inter[x] -= inter;
+        }
+        if need < left {
+            self.state[k] = Some(false);
+            self.search(k + 1, count, edges);
+        }
+        self.state[k] = None;
+    }
+    fn accept(&mut self) {
+        let points: Vec<Point> = self
+            .cells
+            .iter()
+            .zip(self.state.iter())
+            .filter(|(_, &s)| s == Some(true))
+            .map(|(&p, _)| p)
+            .collect();
+        // 箱いっぱいに広がっていないものは小さい箱で数える
+        let max = points
+            .iter()
+            .fold((0, 0, 0), |m, p| (m.0.max(p.0), m.1.max(p.1), m.2.max(p.2)));
+        if max != (self.dims.0 - 1, self.dims.1 - 1, self.dims.2 - 1) {
+            return;
+        }
+        self.found.insert(canonical(&points, self.dim));
+    }
+}
+
+// 配列をコアに通す。HはコアのPoint、Pはコアの外に置く
+// restrictedなら、最初の結合が(1, 0, 0)で、Directionで表せる向きの列になるものだけを探す
+struct Threading<'a> {
+    hp: &'a [bool],
+    core: HashSet<Point>,
+    dim: usize,
+    restricted: bool,
+    // 最初のHから後ろへ、次に最初のHより前を逆順に置いていく
+    order: Vec<usize>,
+    pos: Vec<Point>,
+    occupied: HashSet<Point>,
+    free_core: HashSet<Point>,
+    nodes: u64,
+    max_nodes: u64,
+    aborted: bool,
+    budget: &'a Budget,
+    // 探索を始める前までの評価回数
+    evaluations: u64,
+}
+
+impl<'a> Threading<'a> {
+    fn new(
+        hp: &'a [bool],
+        core: &[Point],
+        dim: usize,
+        restricted: bool,
+        max_nodes: u64,
+        budget: &'a Budget,
+        evaluations: u64,
+    ) -> Threading<'a> {
+        let first = hp.iter().position(|&h| h).unwrap();
+        let mut order: Vec<usize> = (first..hp.len()).collect();
+        order.extend((0..first).rev());
+        Threading {
+            hp,
+            core: core.iter().copied().collect(),
+            dim,
+            restricted,
+            order,
+            pos: vec![(0, 0, 0); hp.len()],
+            occupied: HashSet::new(),
+            free_core: core.iter().copied().collect(),
+            nodes: 0,
+            max_nodes,
+            aborted: false,
+            budget,
+            evaluations,
+        }
+    }
+    // 通せたら各アミノ酸の座標を返す。上限に達して打ち切った場合はabortedが立つ
+    fn run(&mut self) -> Option<Vec<Point>> {
+        let first = self.order[0];
+        let counts = |offset: usize| {
+            (0..self.hp.len())
+                .filter(|&i| self.hp[i] && (i + offset).is_multiple_of(2))
+                .count()
+        };
+        let even_core = self.core.iter().filter(|&&p| parity(p) == 0).count();
+        let mut starts: Vec<Point> = self.core.iter().copied().collect();
+        starts.sort();
+        for start in starts {
+            // 格子は2色に塗り分けられるので、Hの番号の偶奇とコアの点の色の数が合わなければ通せない
+            let offset = (parity(start) + first) % 2;
+            if counts(offset) != even_core {
+                continue;
+            }
+            self.place(first, start);
+            let found = self.search(1);
+            self.remove(first, start);
+            if found {
+                return Some(self.pos.clone());
+            }
+            if self.aborted {
+                return None;
+            }
+        }
+        None
+    }
+    fn place(&mut self, i: usize, p: Point) {
+        self.pos[i] = p;
+        self.occupied.insert(p);
+        self.free_core.remove(&p);
+    }
+    fn remove(&mut self, _i: usize, p: Point) {
+        self.occupied.remove(&p);
+        if self.core.contains(&p) {
+            self.free_core.insert(p);
+        }
+    }
+    fn step(&self, from: usize, to: usize) -> Point {
+        let (a, b) = (self.pos[from], self.pos[to]);
+        (b.0 - a.0, b.1 - a.1, b.2 - a.2)
+    }
+    // ±yの結合の直後に±zの結合は表せない
+    fn representable(prev: Point, next: Point) -> bool {
+        !(prev.1 != 0 && next.2 != 0)
+    }
+    fn search(&mut self, t: usize) -> bool {
+        if t == self.order.len() {
+            return true;
+        }
+        self.nodes += 1;
+        if self.nodes >= self.max_nodes
+            || (self.nodes.is_multiple_of(4096)
+                && self
+                    .budget
+                    .is_exhausted(self.evaluations + self.nodes, i32::MIN))
+        {
+            self.aborted = true;
+            return false;
+        }
+        let first = self.order[0];
+        let i = self.order[t];
+        let forward = i > first;
+        let anchor = if forward { i - 1 } else { i + 1 };
+        let candidates: Vec<Point> = neighbours(self.pos[anchor], self.dim).collect();
+        for q in candidates {
+            if self.occupied.contains(&q) || self.core.contains(&q) != self.hp[i] {
+                continue;
+            }
+            self.place(i, q);
+            if self.feasible(i, forward) && self.search(t + 1) {
+                return true;
+            }
+            self.remove(i, q);
+            if self.aborted {
+                return false;
+            }
+        }
+        false
+    }
+    fn feasible(&self, i: usize, forward: bool) -> bool {
+        let first = self.order[0];
+        if forward {
+            // 次のHまでの残りの結合の数で、まだ空いているコアの点に届かなければならない
+            if let Some(j) = (i + 1..self.hp.len()).find(|&j| self.hp[j]) {
+                let steps = (j - i) as i32;
+                if !self
+                    .free_core
+                    .iter()
+                    .any(|&q| manhattan(self.pos[i], q) <= steps)
+                {
+                    return false;
+                }
+            } else if !self.free_core.is_empty() {
+                return false;
+            }
+            if self.restricted {
+                if i == 1 && self.step(0, 1) != (1, 0, 0) {
+                    return false;
+                }
+                if i >= first + 2
+                    && !Self::representable(self.step(i - 2, i - 1), self.step(i - 1, i))
+                {
+                    return false;
+                }
+            }
+        } else if self.restricted {
+            if i == 0 && self.step(0, 1) != (1, 0, 0) {
+                return false;
+            }
+            if !Self::representable(self.step(i, i + 1), self.step(i + 1, i + 2)) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl HCore {
+    // 乱数は使わない
+    pub fn new(
+        template: &Protein,
+        num_direct: i32,
+        settings: &SolverSettings,
+        budget: Budget,
+    ) -> HCore {
+        // コアに通せなくても答えが構造になるように、H-H結合のない真っ直ぐな鎖から始める
+        let mut straight = template.clone();
+        straight.direct = vec![Direction::S; template.aminos.len().saturating_sub(2)];
+        straight.score().expect("a straight chain is self-avoiding");
+        HCore {
+            max_cores: settings.max_cores,
+            max_nodes: settings.max_nodes,
+            max_levels: settings.core_levels,
+            best_ans: straight,
+            best_score: 0,
+            num_direct,
+            evaluations: 0,
+            budget,
+            upper_bound: None,
+            proven: false,
+        }
+    }
+    pub fn best_ans(&self) -> &Protein {
+        &self.best_ans
+    }
+    pub fn best_score(&self) -> i32 {
+        self.best_score
+    }
+    pub fn evaluations(&self) -> u64 {
+        self.evaluations
+    }
+    // まだ否定できていない最も良いスコア。runの前はNone
+    pub fn upper_bound(&self) -> Option<i32> {
+        self.upper_bound
+    }
+    // best_scoreが最適であることを示せたか
+    pub fn proven(&self) -> bool {
+        self.proven
+    }
+    fn dim(&self) -> usize {
+        if self.num_direct == 5 {
+            3
+        } else {
+            2
+        }
+    }
+    // 座標の列を、最初の結合が(1, 0, 0)になるように回してDirectionの列に直す。表せなければNone
+    fn to_protein(&self, pos: &[Point]) -> Option<Protein> {
+        let aminos: Vec<Amino> = self.best_ans.aminos.iter().map(|a| a.amino).collect();
+        Conformation::from_coordinates(&aminos, pos, self.num_direct)
+            .ok()
+            .map(|c| c.to_protein())
+    }
+    // n点でtarget組の隣接を持つコアを列挙する。上限で打ち切った場合は2つ目がfalse
+    // 箱を横切る面に点のないコアは、targetがsplit_bound以下のときだけ、続けてmax_gap枚までのものを探す
+    // (鎖上で続くHの間がそれより空いていなければ、点のない面をまたげない)
+    fn cores(&mut self, n: usize, target: usize, max_gap: usize) -> (Vec<Vec<Point>>, bool) {
+        let dim = self.dim();
+        let max_gap = if target <= split_bound(n, dim) {
+            max_gap
+        } else {
+            0
+        };
+        let mut found = HashSet::new();
+        let mut complete = true;
+        for a in 1..=n as i32 {
+            if dim == 2 && a > 1 {
+                break;
+            }
+            for b in a..=n as i32 {
+                for c in b..=n as i32 {
+                    if ((a * b * c) as usize) < n || line_bound((b, c, a), n, dim, max_gap) < target
+                    {
+                        continue;
+                    }
+                    if self.budget.is_exhausted(self.evaluations, i32::MIN) {
+                        return (found.into_iter().collect(), false);
+                    }
+                    let mut search = CoreSearch::new(
+                        (b, c, a),
+                        n,
+                        target,
+                        dim,
+                        max_gap,
+                        self.max_cores - found.len().min(self.max_cores),
+                        &self.budget,
+                        self.evaluations,
+                    );
+                    search.run();
+                    self.evaluations += search.nodes;
+                    complete &= search.complete;
+                    found.extend(search.found);
+                    if found.len() >= self.max_cores {
+                        return (found.into_iter().collect(), false);
+                    }
+                }
+            }
+        }
+        let mut cores: Vec<Vec<Point>> = found.into_iter().collect();
+        cores.sort();
+        (cores, complete)
+    }
+    // コアに配列を通す。通せたらSome、通せないことを示せたらNone、打ち切ったらErr
+    fn thread(&mut self, hp: &[bool], core: &[Point]) -> Result<Option<Protein>, ()> {
+        let dim = self.dim();
+        let mut aborted = false;
+        // まずは表せるかを気にせずに探し、通せなければ向きを変えても通せない
+        let mut threading = Threading::new(
+            hp,
+            core,
+            dim,
+            false,
+            self.max_nodes,
+            &self.budget,
+            self.evaluations,
+        );
+        let found = threading.run();
+        self.evaluations += threading.nodes;
+        match found {
+            Some(pos) => {
+                if let Some(protein) = self.to_protein(&pos) {
+                    return Ok(Some(protein));
+                }
+            }
+            None if threading.aborted => aborted = true,
+            None => return Ok(None),
+        }
+        // Directionで表せる向きの列を、コアの向きを変えながら探す
+        let mut seen = HashSet::new();
+        for t in transforms(dim) {
+            let mut moved: Vec<Point> = core.iter().map(|&p| t(p)).collect();
+            moved.sort();
+            if !seen.insert(moved.clone()) {
+                continue;
+            }
+            let mut threading = Threading::new(
+                hp,
+                &moved,
+                dim,
+                true,
+                self.max_nodes,
+                &self.budget,
+                self.evaluations,
+            );
+            let found = threading.run();
+            self.evaluations += threading.nodes;
+            aborted |= threading.aborted;
+            if let Some(pos) = found {
+                let origin = pos[0];
+                let pos: Vec<Point> = pos
+                    .iter()
+                    .map(|p| (p.0 - origin.0, p.1 - origin.1, p.2 - origin.2))
+                    .collect();
+                if let Some(protein) = self.to_protein(&pos) {
+                    return Ok(Some(protein));
+                }
+            }
+        }
+        if aborted {
+            Err(())
+        } else {
+            Ok(None)
+        }
+    }
+    pub fn run(&mut self, observer: &mut dyn SearchObserver) {
+        let hp: Vec<bool> = self
+            .best_ans
+            .aminos
+            .iter()
+            .map(|a| a.amino == Amino::H)
+            .collect();
+        let n = hp.iter().filter(|&&h| h).count();
+        // 鎖上で隣り合うHの組はコアの中で必ず隣接するが、H-H結合には数えない
+        let bonded = hp.windows(2).filter(|w| w[0] && w[1]).count();
+        // 鎖上でi番目とj番目に続くHは格子上でj-i以内にあるので、間に挟める点のない面はj-i-1枚まで
+        let h: Vec<usize> = (0..hp.len()).filter(|&i| hp[i]).collect();
+        let max_gap = h.windows(2).map(|w| w[1] - w[0] - 1).max().unwrap_or(0);
+        if n == 0 {
+            self.upper_bound = Some(0);
+            self.proven = true;
+            observer.on_finished(&self.best_ans, self.best_score);
+            return;
+        }
+        // 層に分けて考えた組の数の上限から始める
+        let dim = self.dim();
+        let mut target = max_edges(n, dim);
+        // 否定できなかった段階があれば、そこが上限になる
+        let mut unrefuted: Option<usize> = None;
+        // 箱を横切る点のない面があるコアを後回しにした段階と、その段階で通せなかったコア
+        let mut deferred: Vec<(usize, HashSet<Vec<Point>>)> = Vec::new();
+        let mut level = 0;
+        // まず点のない面がないコアだけで段階を下りていき、解を早く見つける
+        while level < self.max_levels {
+            if target < bonded || self.budget.is_exhausted(self.evaluations, self.best_score) {
+                break;
+            }
+            let score = (target - bonded) as i32;
+            if score <= self.best_score {
+                break;
+            }
+            let gapped = max_gap > 0 && target <= split_bound(n, dim);
+            let (cores, complete) = self.cores(n, target, 0);
+            // コアが1つもない段階は数えない
+            if cores.is_empty() && complete && !gapped {
+                target -= 1;
+                continue;
+            }
+            let step = format!("cores with {} contacts", score);
+            observer.on_step_start(level, &step);
+            let mut tried = HashSet::new();
+            let refuted = self.thread_all(&hp, &cores, score, &mut tried, observer) && complete;
+            observer.on_step_end(level, &step, self.best_score);
+            observer.on_progress(&self.progress(level + 1));
+            level += 1;
+            if self.best_score == score {
+                break;
+            }
+            if refuted && gapped {
+                deferred.push((target, tried));
+            } else if !refuted && unrefuted.is_none() {
+                unrefuted = Some(target);
+            }
+            target -= 1;
+        }
+        // 後回しにした段階を、上から点のない面があるコアも含めて調べ直す
+        for (deferred_target, mut tried) in deferred {
+            let score = (deferred_target - bonded) as i32;
+            if score <= self.best_score {
+                break;
+            }
+            if self.budget.is_exhausted(self.evaluations, self.best_score) {
+                unrefuted = unrefuted.max(Some(deferred_target));
+                break;
+            }
+            let step = format!("cores with {} contacts and gaps", score);
+            observer.on_step_start(level, &step);
+            let (cores, complete) = self.cores(n, deferred_target, max_gap);
+            let refuted = self.thread_all(&hp, &cores, score, &mut tried, observer) && complete;
+            observer.on_step_end(level, &step, self.best_score);
+            observer.on_progress(&self.progress(level + 1));
+            level += 1;
+            if !refuted {
+                unrefuted = unrefuted.max(Some(deferred_target));
+            }
+            if self.best_score == score {
+                break;
+            }
+        }
+        let bound = unrefuted.unwrap_or(target).saturating_sub(bonded) as i32;
+        self.upper_bound = Some(bound.max(self.best_score));
+        self.proven = self.best_score >= bound;
+        observer.on_finished(&self.best_ans, self.best_score);
+    }
+    // コアに順に配列を通し、通せたらscoreの解をベストにする。どのコアにも通せないことを示せたらtrue
+    // triedのコアは通せないとわかっているので飛ばし、新たに通せないとわかったものを加える
+    fn thread_all(
+        &mut self,
+        hp: &[bool],
+        cores: &[Vec<Point>],
+        score: i32,
+        tried: &mut HashSet<Vec<Point>>,
+        observer: &mut dyn SearchObserver,
+    ) -> bool {
+        let mut refuted = true;
+        for core in cores.iter() {
+            if tried.contains(core) {
+                continue;
+            }
+            if self.budget.is_exhausted(self.evaluations, i32::MIN) {
+                return false;
+            }
+            match self.thread(hp, core) {
+                Ok(Some(mut protein)) => {
+                    // コアの組の数から求めたスコアと、実際に数えたものは一致する
+                    debug_assert_eq!(protein.score().ok(), Some(score));
+                    self.best_score = score;
+                    self.best_ans = protein.clone();
+                    observer.on_new_best(&protein, score);
+                    return false;
+                }
+                Ok(None) => {
+                    tried.insert(core.clone());
+                }
+                Err(()) => refuted = false,
+            }
+        }
+        refuted
+    }
+    pub fn progress(&self, iteration: usize) -> Progress {
+        Progress {
+            iteration,
+            evaluations: self.evaluations,
+            best_score: self.best_score,
+            current_score: self.best_score,
+            diversity: 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmark::{parse_amino_str, protein_from_aminos};
+    use crate::enumerate::Enumerator;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha12Rng;
+
+    // 短いランダムな配列で、全ての構造を調べた最適解と比べる
+    fn compare(dim: usize, lengths: std::ops::RangeInclusive<usize>, samples: usize) {
+        let num_direct = if dim == 2 { 3 } else { 5 };
+        let mut rng = ChaCha12Rng::seed_from_u64(dim as u64);
+        for _ in 0..samples {
+            let len = rng.gen_range(lengths.clone());
+            let aminos: Vec<Amino> = (0..len)
+                .map(|_| {
+                    if rng.gen_bool(0.5) {
+                        Amino::H
+                    } else {
+                        Amino::P
+                    }
+                })
+                .collect();
+            let protein = protein_from_aminos(&aminos);
+            let mut enumerator = Enumerator::new(&protein, num_direct, Budget::default());
+            enumerator.run();
+            assert!(enumerator.complete);
+            let optimum = enumerator.best_score;
+            let mut hcore = HCore::new(
+                &protein,
+                num_direct,
+                &SolverSettings::default(),
+                Budget::default(),
+            );
+            hcore.run(&mut ());
+            assert!(hcore.best_score() <= optimum, "{:?}", aminos);
+            assert!(hcore.upper_bound().unwrap() >= optimum, "{:?}", aminos);
+            if hcore.proven() {
+                assert_eq!(hcore.best_score(), optimum, "{:?}", aminos);
+            }
+            assert_eq!(
+                hcore.best_ans().clone().score().unwrap(),
+                hcore.best_score(),
+                "{:?}",
+                aminos
+            );
+        }
+    }
+
+    #[test]
+    fn matches_enumeration_in_2d() {
+        compare(2, 6..=12, 40);
+    }
+
+    #[test]
+    fn matches_enumeration_in_3d() {
+        compare(3, 6..=9, 30);
+    }
+
+    // Hがない配列や、コアに通せる構造がない配列でも、答えは配列と同じ長さの構造になる
+    #[test]
+    fn answers_a_conformation_without_cores() {
+        for sequence in ["PPPPPP", "HPH", "HP"] {
+            let protein = protein_from_aminos(&parse_amino_str(sequence));
+            let mut hcore = HCore::new(&protein, 3, &SolverSettings::default(), Budget::default());
+            hcore.run(&mut ());
+            assert!(hcore.proven(), "{}", sequence);
+            assert_eq!(hcore.best_score(), 0, "{}", sequence);
+            assert_eq!(hcore.best_ans().direct.len(), sequence.len() - 2);
+            assert_eq!(hcore.best_ans().clone().score().unwrap(), 0);
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use crate::error::Error;
+use crate::moves::grow;
+use crate::protein::{relative_direction, Protein};
+use rand::Rng;
+use std::str::FromStr;
+
+// 探索を始める解の作り方
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Init {
+    // 行き止まりでは後戻りしながら、ランダムな向きで伸ばした自己回避歩行
+    Random,
+    // 新しくできるH-H結合が多い向きを優先して伸ばした自己回避歩行
+    Greedy,
+    // 正方形・立方体に近い箱を端から順に蛇行して埋める経路
+    Compact,
+}
+
+impl FromStr for Init {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "random" => Ok(Init::Random),
+            "greedy" => Ok(Init::Greedy),
+            "compact" => Ok(Init::Compact),
+            _ => Err(format!("unknown init: {}", s)),
+        }
+    }
+}
+
+impl Init {
+    // templateと同じ配列の、衝突しない初期解を作る
+    pub fn build<R: Rng>(&self, template: &Protein, num_direct: i32, rng: &mut R) -> Protein {
+        match self {
+            Init::Random | Init::Greedy => loop {
+                let greedy = *self == Init::Greedy;
+                if let Some(protein) = grow(template, &[], num_direct, rng, greedy) {
+                    return protein;
+                }
+            },
+            // 向きで表せない経路になった場合はランダムな自己回避歩行で始める
+            Init::Compact => compact(template, num_direct, rng)
+                .unwrap_or_else(|_| Init::Random.build(template, num_direct, rng)),
+        }
+    }
+}
+
+// 箱の中を蛇行する経路。行はx軸に沿って往復し、層(z)が変わるときは直前の層を逆にたどる
+// 3次元の向きの表し方では±yの直後に±zへ曲がれないので、層を変える直前は必ずx方向に進む
+fn compact<R: Rng>(template: &Protein, num_direct: i32, rng: &mut R) -> Result<Protein, Error> {
+    let n = template.aminos.len();
+    let side = if num_direct == 5 {
+        (n as f64).cbrt().ceil() as i32
+    } else {
+        (n as f64).sqrt().ceil() as i32
+    };
+    // やり直しごとに少し違う形になるように、行の長さを1つ伸ばすことがある
+    let width = (side + rng.gen_range(0..=1)).max(2);
+    let height = if num_direct == 5 {
+        side
+    } else {
+        (n as i32 + width - 1) / width
+    };
+    let mut layer = Vec::new();
+    for y in 0..height {
+        for i in 0..width {
+            let x = if y % 2 == 0 { i } else { width - 1 - i };
+            layer.push((x, y));
+        }
+    }
+    let mut pos = Vec::new();
+    let mut z = 0;
+    while pos.len() < n {
+        if z % 2 == 0 {
+            pos.extend(layer.iter().map(|&(x, y)| (x, y, z)));
+        } else {
+            pos.extend(layer.iter().rev().map(|&(x, y)| (x, y, z)));
+        }
+        z += 1;
+    }
+    pos.truncate(n);
+    let steps: Vec<(i32, i32, i32)> = pos
+        .windows(2)
+        .map(|w| (w[1].0 - w[0].0, w[1].1 - w[0].1, w[1].2 - w[0].2))
+        .collect();
+    let mut protein = template.clone();
+    protein.direct = steps
+        .windows(2)
+        .map(|w| {
+            relative_direction(w[0], w[1], num_direct).ok_or_else(|| {
+                Error::InvalidMove(format!("cannot turn from {:?} to {:?}", w[0], w[1]))
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(protein)
+}
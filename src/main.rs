@@ -1,222 +1,650 @@
-use plotters::prelude::*;
-use rand::Rng;
-use std::collections::HashMap;
-// mod aco;
-mod animation;
-mod beam;
-mod lib;
-// use aco::ACO;
-use animation::animation;
-use beam::next_permutation;
-use beam::Beam;
-use clap::Parser;
-use lib::{rotate_left, rotate_right, Amino, AminoAcid, Direction, Protein};
-use std::cmp::Ordering;
-use std::hash::{Hash, Hasher};
-
-#[derive(Debug, PartialEq, PartialOrd)]
-struct OrdF32(f32);
-
-impl Eq for OrdF32 {}
-
-impl Ord for OrdF32 {
-    fn cmp(&self, other: &Self) -> Ordering {
-        // NaNの扱いに注意しつつ、PartialOrdの結果を利用
-        self.partial_cmp(other).unwrap_or(Ordering::Equal)
+mod cli;
+mod overrides;
+use clap::{CommandFactory, FromArgMatches};
+use cli::{
+    BenchArgs, Cli, Command, ConformationArgs, DockArgs, EnumerateArgs, EvalArgs, FoldArgs,
+    ImageArgs, ListArgs, ProteinArgs, RenderArgs,
+};
+use protein_structure::animation::{turntable, Animation, TraceRecorder};
+use protein_structure::benchmark::{self, known_optimum, parse_sequence, setup, PROTEIN_DATA};
+use protein_structure::bounds::Bounds;
+use protein_structure::budget::Budget;
+use protein_structure::checkpoint::{Checkpoint, Checkpointer};
+use protein_structure::complex::Complex;
+use protein_structure::config::{RunConfig, MAX_SEED};
+use protein_structure::dock::{BeamDocking, DockSolver, Docking, TabuDocking};
+use protein_structure::enumerate::Enumerator;
+use protein_structure::observer::{ConsoleLogger, CsvTrace, Observers};
+use protein_structure::pdb::{chain_id, write_pdb};
+use protein_structure::record::{direction_char, sequence_string, FoldRecord};
+use protein_structure::render::{render, render_views, RenderOptions};
+use protein_structure::solve::{Folding, Solver};
+use protein_structure::trajectory::{plot_convergence, write_csv, Trajectory};
+use protein_structure::validate::{self, contacts, radius_of_gyration, Loaded, Point, Source};
+use protein_structure::{Amino, Conformation, Error, Protein};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use std::fmt::Display;
+use std::time::Duration;
+
+fn fold(args: &FoldArgs) {
+    let dim = args.protein.dim;
+    let protein = &load_protein(&args.protein);
+    let optimum = args
+        .protein
+        .benchmark_id()
+        .and_then(|id| known_optimum(id, dim));
+    let options = args.image.options();
+    set_threads(args.solver.threads);
+    // 再開するときはチェックポイントと同じタンパク質・次元・シードで探索する
+    let resumed = args
+        .resume
+        .as_deref()
+        .map(|path| exit_on_error(Checkpoint::load(path)));
+    let sequence = sequence_string(protein);
+    if let Some(resumed) = &resumed {
+        let mismatch = match &resumed.sequence {
+            Some(s) => *s != sequence,
+            None => resumed.id != args.protein.id,
+        };
+        if mismatch || resumed.dim != dim {
+            match &resumed.sequence {
+                Some(s) => eprintln!("checkpoint is for --sequence {} --dim {}", s, resumed.dim),
+                None => eprintln!(
+                    "checkpoint is for --id {} --dim {}",
+                    resumed.id, resumed.dim
+                ),
+            }
+            std::process::exit(2);
+        }
+        if args.solver.seed.is_some_and(|seed| seed != resumed.seed) {
+            eprintln!("checkpoint was created with --seed {}", resumed.seed);
+            std::process::exit(2);
+        }
     }
-}
-#[derive(Debug, PartialEq, PartialOrd)]
-struct FloatKey(f32);
+    // シードを指定しなかった場合も、同じ結果を再現できるように表示しておく
+    let seed = match &resumed {
+        Some(resumed) => resumed.seed,
+        None => args.solver.seed.unwrap_or_else(random_seed),
+    };
+    // 実際に使う設定を、そのまま--configに渡せる形で表示する
+    let config = overrides::from_fold(args, seed);
+    println!("== config ==\n{}", exit_on_error(config.to_toml()));
+    println!("seed: {}", seed);
+    let checkpointer = args
+        .checkpoint
+        .as_deref()
+        .or(args.resume.as_deref())
+        .map(|path| {
+            let checkpoint = resumed
+                .clone()
+                .unwrap_or_else(|| Checkpoint::new(seed, args.protein.id, sequence.clone(), dim));
+            Checkpointer::new(
+                checkpoint,
+                path,
+                Duration::from_secs_f64(args.checkpoint_interval),
+            )
+        });
+    let resume_state = |label: &str, restart: usize| {
+        resumed
+            .as_ref()
+            .and_then(|c| c.states.get(&Checkpoint::key(label, restart)).cloned())
+    };
 
-impl Eq for FloatKey {}
+    let budget = exit_on_error(config.budget(optimum));
+    install_ctrlc(&budget);
 
-impl Hash for FloatKey {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        // ビットパターンをハッシュ値に変換
-        self.0.to_bits().hash(state);
+    let mut trace = args.vis.then(|| {
+        let trace_options = RenderOptions {
+            frame_delay: args.trace_delay,
+            format: None,
+            ..options
+        };
+        let animation = exit_on_error(Animation::new(&args.trace, dim, &trace_options));
+        TraceRecorder::new(animation, args.step as usize)
+    });
+    let mut csv_trace = args
+        .log_csv
+        .as_deref()
+        .map(|path| exit_on_error(CsvTrace::new(path)));
+
+    let solvers = if args.compare {
+        vec![Solver::Beam, Solver::Anneal, Solver::Genetic, Solver::Tabu]
+    } else {
+        vec![args.solver.solver]
+    };
+    // 既知の最適解がない配列でも、どれだけ改善の余地があるかわかるように上限を出す
+    let mut bounds = Bounds::new(protein, dim);
+    let settings = exit_on_error(config.solver.settings());
+    let mut best_ans = protein.clone();
+    let mut best_score = -1;
+    let mut trajectories = Vec::new();
+    for solver in solvers {
+        let label = format!("{:?}", solver).to_lowercase();
+        let budget = budget.for_solver();
+        let mut checkpointer = checkpointer.as_ref().map(|c| c.with_label(&label));
+        let folding = Folding::new(
+            solver,
+            protein,
+            &settings,
+            dim,
+            &budget,
+            args.solver.restarts,
+            seed,
+        );
+        let resume = |restart| resume_state(&label, restart);
+        let (summary, runs) = if args.vis {
+            // GIFの記録はスレッドをまたげないので、やり直しを順番に実行する
+            let mut observers = Observers::default();
+            observers.push(ConsoleLogger::default());
+            if let Some(trace) = trace.as_mut() {
+                observers.push(trace);
+            }
+            if let Some(csv_trace) = csv_trace.as_mut() {
+                observers.push(csv_trace);
+            }
+            if let Some(checkpointer) = checkpointer.as_mut() {
+                observers.push(checkpointer);
+            }
+            folding.run_sequential(&mut observers, |_| Trajectory::new(&label), resume)
+        } else {
+            let (summary, observers) = folding.run_parallel(
+                |restart| {
+                    let console = ConsoleLogger {
+                        prefix: format!("[{} {}] ", label, restart),
+                    };
+                    let trajectory = Trajectory::new(&format!("{}#{}", label, restart));
+                    (
+                        console,
+                        (csv_trace.clone(), (checkpointer.clone(), trajectory)),
+                    )
+                },
+                resume,
+            );
+            let runs = observers
+                .into_iter()
+                .map(|(_, (_, (_, trajectory)))| trajectory)
+                .collect();
+            (summary, runs)
+        };
+        trajectories.extend(runs);
+        if let Some(bound) = summary.best().upper_bound {
+            if bound == summary.best().best_score {
+                println!("{}: {} is optimal", label, bound);
+            } else {
+                println!(
+                    "{}: {} (upper bound: {})",
+                    label,
+                    summary.best().best_score,
+                    bound
+                );
+            }
+        }
+        println!("== {} ==", label);
+        summary.report();
+        // hcoreが示した上限は、最後にまとめて表示する上限に使う
+        bounds = bounds.with_search(summary.results.iter().filter_map(|r| r.upper_bound).min());
+        if budget.stop_requested() {
+            println!("stopped by user ({:.1}s)", budget.elapsed().as_secs_f64());
+        } else if budget.target_reached() {
+            println!("target reached ({:.1}s)", budget.elapsed().as_secs_f64());
+        }
+        let best = summary.best();
+        if best.best_score > best_score {
+            best_ans = best.best_ans.clone();
+            best_score = best.best_score;
+        }
+    }
+    // 途中の書き出しに失敗しても探索は最後まで続けるので、ここで知らせる
+    if let Some(e) = trace.as_mut().and_then(|t| t.error.take()) {
+        eprintln!("{}", e);
+    }
+    if let Some(e) = checkpointer.as_ref().and_then(|c| c.take_error()) {
+        eprintln!("{}", e);
     }
+    if let Some(e) = csv_trace.as_ref().and_then(|c| c.take_error()) {
+        eprintln!("{}", e);
+    }
+    let trajectories: Vec<&Trajectory> = trajectories.iter().collect();
+    if let Some(path) = &args.trajectory {
+        exit_on_error(write_csv(&trajectories, path));
+    }
+    if let Some(path) = &args.convergence {
+        let out = exit_on_error(plot_convergence(&trajectories, optimum, path, &options));
+        println!("saved: {}", out.display());
+    }
+    match optimum {
+        Some(optimum) => println!("best: {} (known optimum: {})", best_score, optimum),
+        None => println!("best: {}", best_score),
+    }
+    println!("{}", bounds.report(best_score));
+    if let Some(path) = &args.save {
+        let record = FoldRecord {
+            config: Some(config),
+            ..exit_on_error(FoldRecord::new(&mut best_ans, dim))
+        };
+        exit_on_error(record.save(path));
+        println!("saved: {}", path);
+    }
+    draw(&args.image, &mut best_ans, dim);
 }
-static PROTEIN_DATA: [&str; 22] = [
-    "H4",
-    "(HP)2PH2PHP2HPH2P2HPH",
-    "H2(P2H)7H",
-    "P2HP2(H2P4)3H2",
-    "P3H2P2H2P5H7P2H2P4H2P2HP2",
-    "P2H(P2H2)2P5H10P6(H2P2)2HP2H5",
-    "H2(PH)3PH4PH(P3H)2P4H(P3H)2PHPH4(HP)3H2",
-    "P2H3PH8P3H10PHP3H12P4H6PH2PHP",
-    "H12(PH)2(P2H2)2P2HP2H2PPH2P2HP2(H2P2)2(HP)2H12",
-    "H4P4H12P6(H12P3)3HP2(H2P2)2HPH",
-    "P3H2P2H4P2H3(PH2)2PH4P8H6P2H6P9HPH2PH11P2H3PH2PHP2HPH3P6H3",
-    "P6HPH2P5H3PH5PH2P4H2P2H2PH5PH10PH2PH7p11H7P2HPH3P6HPH2",
-    "HPH2P2H4PH3P2H2P2HPH2PHPH2P2H2P3HP8H2",
-    "H4PH2PH5P2HP2H2P2HP6HP2HP3HP2H2P2H3PH",
-    "PHPH2PH6P2HPHP2HPH2(PH)2P3H(P2H2)2P2HPHP2HP",
-    "PHPH2P2HPH3P2H2PH2P3H5P2HPH2(PH)2P4HP2(HP)2",
-    "P2HP3HPH4P2H4PH2PH3P2(HP)2HP2HP6H2PH2PH",
-    "H3P3H2PH(PH2)3PHP7HPHP2HP3HP2H6PH",
-    "PHP4HPH3PHPH4PH2PH2P3HPHP3H3(P2H2)2P3H",
-    "PH2PH3PH4P2H3P6HPH2P2H2PHP3H2(PH)2PH2P3",
-    "(PH)2P4(HP)2HP2HPH6P2H3PHP2HPH2P2HPH3P4H",
-    "PH2P6H2P3H3PHP2HPH2(P2H)2P2H2P2H7P2H2",
-];
-
-fn parse_amino_str(input: &str) -> Vec<Amino> {
-    let mut result = Vec::new();
-    let mut i = 0;
-    let chars: Vec<char> = input.chars().collect();
-    while i < chars.len() {
-        match chars[i] {
-            'H' => result.push(Amino::H),
-            'P' => result.push(Amino::P),
-            '(' => {
-                let mut j = i + 1;
-                while j < chars.len() && chars[j] != ')' {
-                    j += 1;
-                }
-                let sequence = &chars[i + 1..j];
-                let mut inner_result = parse_amino_str(&sequence.iter().collect::<String>());
-                i = j + 1;
-                let mut multiplier = 0;
-                while i < chars.len() && chars[i].is_numeric() {
-                    multiplier = multiplier * 10 + chars[i].to_digit(10).unwrap() as i32;
-                    i += 1;
-                }
-                for _ in 0..multiplier {
-                    result.append(&mut inner_result.clone());
-                }
-                continue;
-            }
-            c if c.is_ascii_digit() => {
-                let mut multiplier = 0;
-                while i < chars.len() && chars[i].is_numeric() {
-                    multiplier = multiplier * 10 + chars[i].to_digit(10).unwrap() as i32;
-                    i += 1;
-                }
-                if let Some(&last) = result.last() {
-                    for _ in 0..multiplier - 1 {
-                        result.push(last.clone());
-                    }
-                }
-                continue;
+
+fn bench(args: &BenchArgs) {
+    set_threads(args.solver.threads);
+    let proteins = setup();
+    let ids: Vec<usize> = if args.ids.is_empty() {
+        (0..proteins.len())
+            .filter(|&id| known_optimum(id, args.dim).is_some())
+            .collect()
+    } else {
+        args.ids.clone()
+    };
+    if let Some(id) = ids.iter().find(|&&id| id >= proteins.len()) {
+        eprintln!("unknown benchmark id: {} (see `list`)", id);
+        std::process::exit(2);
+    }
+    let seed = args.solver.seed.unwrap_or_else(random_seed);
+    let config = overrides::from_bench(args, &ids, seed);
+    println!("== config ==\n{}", exit_on_error(config.to_toml()));
+    println!("seed: {}", seed);
+    // Ctrl-Cで止めたときは、その配列の結果を出して残りは解かない
+    let stop = Budget::default();
+    install_ctrlc(&stop);
+    let solver = args.solver.solver;
+    let settings = exit_on_error(config.solver.settings());
+    println!(
+        "{:>3} {:>4} {:>5} {:>8} {:>6} {:>8}",
+        "id", "len", "best", "optimum", "bound", "time[s]"
+    );
+    let mut total = 0;
+    let mut total_optimum = 0;
+    for id in ids {
+        let protein = &proteins[id];
+        let optimum = known_optimum(id, args.dim);
+        let budget = stop.for_sequence(exit_on_error(config.budget(optimum)));
+        let (summary, _) = Folding::new(
+            solver,
+            protein,
+            &settings,
+            args.dim,
+            &budget,
+            args.solver.restarts,
+            seed,
+        )
+        .run_parallel(|_| (), |_| None);
+        let best_score = summary.best().best_score;
+        let bound = Bounds::new(protein, args.dim)
+            .with_search(summary.results.iter().filter_map(|r| r.upper_bound).min())
+            .best();
+        println!(
+            "{:>3} {:>4} {:>5} {:>8} {:>6} {:>8.1}",
+            id,
+            protein.aminos.len(),
+            best_score,
+            optimum.map_or("-".to_string(), |o| o.to_string()),
+            bound,
+            budget.elapsed().as_secs_f64()
+        );
+        if let Some(optimum) = optimum {
+            total += best_score;
+            total_optimum += optimum;
+        }
+        if budget.stop_requested() {
+            println!("stopped by user");
+            break;
+        }
+    }
+    if total_optimum > 0 {
+        println!(
+            "total: {} / {} ({:.1}%)",
+            total,
+            total_optimum,
+            100.0 * total as f64 / total_optimum as f64
+        );
+    }
+}
+
+fn eval(args: &EvalArgs) {
+    let Loaded {
+        template,
+        pos,
+        dim,
+        id,
+    } = load_conformation(&args.conformation);
+    let aminos: Vec<Amino> = template.aminos.iter().map(|a| a.amino).collect();
+    let contacts = contacts(&aminos, &pos);
+    let num_direct = if dim == 2 { 3 } else { 5 };
+    let score = match Conformation::from_coordinates(&aminos, &pos, num_direct) {
+        Ok(conformation) => {
+            let mut protein = conformation.to_protein();
+            let score = conformation.score();
+            debug_assert_eq!(score, contacts.len() as i32);
+            let directions: String = protein.direct.iter().map(|&d| direction_char(d)).collect();
+            println!("directions: {}", directions);
+            println!("value: {}", exit_on_error(protein.get_value()));
+            println!("max distance: {:.3}", protein.calc_max_distance());
+            if let Some(path) = &args.save {
+                exit_on_error(FoldRecord::new(&mut protein, dim).and_then(|r| r.save(path)));
+                println!("saved: {}", path);
             }
-            _ => {}
+            score
+        }
+        Err(_) => {
+            println!("directions: none (the 3D encoding cannot turn to ±z right after ±y)");
+            contacts.len() as i32
         }
-        i += 1;
+    };
+    println!("score: {}", score);
+    println!("energy: {}", -score);
+    let contacts: Vec<String> = contacts
+        .iter()
+        .map(|(i, j)| format!("{}-{}", i, j))
+        .collect();
+    println!("contacts: {}", contacts.join(" "));
+    println!("radius of gyration: {:.3}", radius_of_gyration(&pos));
+    if let Some(optimum) = id.and_then(|id| known_optimum(id, dim)) {
+        println!("known optimum: {}", optimum);
     }
-    result
+    println!("{}", Bounds::new(&template, dim).report(score));
 }
 
-static SAMPLE_PROTEIN_POINTS: [i32; 22] = [
-    4, 9, 9, 8, 14, 23, 21, 36, 42, 53, 50, 48, 32, 34, 34, 33, 32, 32, 32, 31, 34, 33,
-];
-fn setup() -> Vec<Protein> {
-    let mut sample_proteins = Vec::new();
-    for i in 0..PROTEIN_DATA.len() {
-        let amino_str = PROTEIN_DATA[i];
-        let aminos = parse_amino_str(amino_str);
-        let mut amino_acids = Vec::new();
-        for j in 0..aminos.len() {
-            amino_acids.push(AminoAcid {
-                amino: aminos[j],
-                pos: (0, 0, 0),
-            });
-        }
-        sample_proteins.push(Protein {
-            size: aminos.len() as i32,
-            aminos: amino_acids,
-            direct: Vec::new(),
-            predict: 0,
-        });
+fn render_command(args: &RenderArgs) {
+    let Loaded {
+        template, pos, dim, ..
+    } = load_conformation(&args.conformation);
+    let aminos: Vec<Amino> = template.aminos.iter().map(|a| a.amino).collect();
+    let num_direct = if dim == 2 { 3 } else { 5 };
+    let mut protein = match Conformation::from_coordinates(&aminos, &pos, num_direct) {
+        Ok(conformation) => conformation.to_protein(),
+        Err(e) => {
+            eprintln!("{}, so this conformation cannot be drawn", e);
+            std::process::exit(1);
+        }
+    };
+    draw(&args.image, &mut protein, dim);
+}
+
+fn enumerate(args: &EnumerateArgs) {
+    let dim = args.protein.dim;
+    let protein = load_protein(&args.protein);
+    let budget = Budget::new(args.time_limit.map(Duration::from_secs_f64), None, None);
+    install_ctrlc(&budget);
+    let mut enumerator = Enumerator::new(&protein, if dim == 2 { 3 } else { 5 }, budget);
+    enumerator.run();
+    if enumerator.best_score < 0 {
+        println!("no conformation found ({} nodes)", enumerator.nodes);
+        return;
+    }
+    if enumerator.complete {
+        println!(
+            "optimum: {} ({} distinct conformations, {} nodes)",
+            enumerator.best_score,
+            enumerator.optima.len(),
+            enumerator.nodes
+        );
+    } else {
+        println!(
+            "best: {} (stopped after {} nodes)",
+            enumerator.best_score, enumerator.nodes
+        );
+    }
+    if let Some(optimum) = args
+        .protein
+        .benchmark_id()
+        .and_then(|id| known_optimum(id, dim))
+    {
+        println!("known optimum: {}", optimum);
+    }
+    println!(
+        "{}",
+        Bounds::new(&protein, dim)
+            .with_search(enumerator.complete.then_some(enumerator.best_score))
+            .report(enumerator.best_score)
+    );
+    if let Some(path) = &args.save {
+        exit_on_error(FoldRecord::new(&mut enumerator.best_ans, dim).and_then(|r| r.save(path)));
+        println!("saved: {}", path);
+    }
+}
+
+fn list(args: &ListArgs) {
+    println!(
+        "{:>3} {:>3} {:>4} {:>3} {:>8}  sequence",
+        "id", "dim", "len", "H", "optimum"
+    );
+    for (id, protein) in setup().iter().enumerate() {
+        // 1~11番は2次元、12番以降は3次元の論文の値
+        let dim = if id >= 12 { 3 } else { 2 };
+        let optimum = known_optimum(id, dim);
+        if args.dim.is_some_and(|d| d != dim || optimum.is_none()) {
+            continue;
+        }
+        let h = protein
+            .aminos
+            .iter()
+            .filter(|a| a.amino == Amino::H)
+            .count();
+        println!(
+            "{:>3} {:>3} {:>4} {:>3} {:>8}  {}",
+            id,
+            if optimum.is_some() {
+                dim.to_string()
+            } else {
+                "-".to_string()
+            },
+            protein.aminos.len(),
+            h,
+            optimum.map_or("-".to_string(), |o| o.to_string()),
+            PROTEIN_DATA[id]
+        );
+    }
+}
+
+fn dock(args: &DockArgs) {
+    let dim = args.dim;
+    let sequences: Vec<Vec<Amino>> = args
+        .sequences
+        .iter()
+        .map(|s| {
+            let protein = exit_on_error(parse_sequence(s));
+            protein.aminos.iter().map(|a| a.amino).collect()
+        })
+        .collect();
+    let complex = Complex::new(&sequences, if dim == 2 { 3 } else { 5 });
+    let seed = args.seed.unwrap_or_else(random_seed);
+    let budget = exit_on_error(overrides::from_budget(&args.budget).budget(None));
+    install_ctrlc(&budget);
+    let iterations = args
+        .iterations
+        .unwrap_or_else(|| args.solver.default_iterations());
+    let logger = &mut ConsoleLogger::default();
+    let best = match args.solver {
+        DockSolver::Anneal => {
+            let mut docking = Docking::new(
+                complex,
+                args.temperature,
+                iterations,
+                args.rigid,
+                ChaCha12Rng::seed_from_u64(seed),
+                budget.clone(),
+            );
+            docking.run(logger);
+            docking.best().clone()
+        }
+        DockSolver::Tabu => {
+            let mut docking =
+                TabuDocking::new(complex, iterations, args.tabu_tenure, budget.clone());
+            docking.run(logger);
+            docking.best().clone()
+        }
+        DockSolver::Beam => {
+            let mut docking =
+                BeamDocking::new(complex, iterations, args.beam_width, budget.clone());
+            docking.run(logger);
+            docking.best().clone()
+        }
+    };
+    if budget.stop_requested() {
+        println!("stopped by user ({:.1}s)", budget.elapsed().as_secs_f64());
+    } else if budget.target_reached() {
+        println!("target reached ({:.1}s)", budget.elapsed().as_secs_f64());
+    }
+    // ベストは衝突していない構造しか残らない
+    let contacts = exit_on_error(best.contacts());
+    let positions = best.positions();
+    for (c, chain) in best.chains.iter().enumerate() {
+        let directions: String = chain.direct.iter().map(|&d| direction_char(d)).collect();
+        println!(
+            "chain {}: {} residues, {} contacts, directions {}, start {:?}",
+            chain_id(c),
+            chain.aminos.len(),
+            contacts.intra[c].len(),
+            directions,
+            positions[c][0]
+        );
+    }
+    for (&(a, b), pairs) in contacts.interface.iter() {
+        let pairs: Vec<String> = pairs
+            .iter()
+            .map(|(i, j)| format!("{}{}-{}{}", chain_id(a), i, chain_id(b), j))
+            .collect();
+        println!(
+            "interface {}-{}: {} contacts ({})",
+            chain_id(a),
+            chain_id(b),
+            pairs.len(),
+            pairs.join(" ")
+        );
+    }
+    println!(
+        "best: {} (intra {}, interface {}, seed {})",
+        contacts.score(),
+        contacts.intra_score(),
+        contacts.interface_score(),
+        seed
+    );
+    if let Some(path) = &args.pdb {
+        let chains: Vec<(&[Amino], &[Point])> = best
+            .chains
+            .iter()
+            .zip(positions.iter())
+            .map(|(chain, pos)| (chain.aminos.as_slice(), pos.as_slice()))
+            .collect();
+        exit_on_error(write_pdb(path, &chains));
+        println!("saved: {}", path);
     }
-    for i in 0..sample_proteins.len() {
-        sample_proteins[i].aminos[0].pos = (0, 0, 0);
-        sample_proteins[i].aminos[1].pos = (1, 0, 0);
-    }
-    sample_proteins[0].direct = vec![Direction::L, Direction::L];
-    sample_proteins[1].direct = vec![
-        Direction::L,
-        Direction::S,
-        Direction::L,
-        Direction::L,
-        Direction::R,
-        Direction::R,
-        Direction::L,
-        Direction::R,
-        Direction::L,
-        Direction::L,
-        Direction::S,
-        Direction::L,
-        Direction::R,
-        Direction::R,
-        Direction::L,
-        Direction::L,
-        Direction::S,
-        Direction::L,
-    ];
-    sample_proteins
 }
 
-#[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
-struct Args {
-    #[arg(short, long)]
-    vis: bool,
+// 表示した設定をそのまま--configに渡せるように、TOMLの整数で書ける範囲から選ぶ
+fn random_seed() -> u64 {
+    rand::thread_rng().gen_range(0..=MAX_SEED)
+}
 
-    #[arg(short, long, default_value_t = 5)]
-    step: u8,
+fn set_threads(threads: usize) {
+    if threads > 0 {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .unwrap();
+    }
+}
 
-    #[arg(short, long, default_value_t = 2)]
-    dim: u8,
+// 1回目のCtrl-Cでは探索を止めてそれまでのベストを書き出す。2回目で即座に終了する
+fn install_ctrlc(budget: &Budget) {
+    let handler_budget = budget.clone();
+    ctrlc::set_handler(move || {
+        if handler_budget.stop_requested() {
+            std::process::exit(130);
+        }
+        eprintln!("stopping... (press Ctrl-C again to quit immediately)");
+        handler_budget.request_stop();
+    })
+    .unwrap();
+}
 
-    #[arg(short, long, default_value_t = 10)]
-    id: u8,
+fn load_protein(args: &ProteinArgs) -> Protein {
+    exit_on_error(benchmark::load_protein(
+        args.sequence.as_deref(),
+        args.id as usize,
+    ))
 }
 
-fn main() {
-    let args = Args::parse();
-    print!("{:?}", args);
-    let mut sample_proteins = setup();
-    let mut protein = &mut sample_proteins[args.id as usize];
-
-    if args.vis {
-        let mut beam = Beam {
-            beam_width: 200,
-            nodes: vec![protein.clone()],
-            best_score: 0,
-            best_ans: protein.clone(),
-            num_direct: if args.dim == 2 { 3 } else { 5 },
-        };
-        beam.vis_one_step(args.step, args.dim);
+// 鎖が切れていたり衝突していたりする場合は、その箇所を全て表示して終了する
+fn load_conformation(args: &ConformationArgs) -> Loaded {
+    let source = match (&args.file, &args.coords, &args.directions) {
+        (Some(path), _, _) => Source::Record(path),
+        (None, Some(path), _) => Source::Coordinates(path),
+        (None, None, Some(directions)) if args.absolute => Source::Absolute(directions),
+        (None, None, Some(directions)) => Source::Directions(directions),
+        (None, None, None) => unreachable!(),
+    };
+    let protein = &args.protein;
+    match validate::load_conformation(
+        &source,
+        protein.sequence.as_deref(),
+        protein.id as usize,
+        protein.dim,
+    ) {
+        Ok(loaded) => loaded,
+        Err(e @ Error::Defects(_)) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        Err(e) => exit_on_error(Err(e)),
+    }
+}
+
+fn draw(image: &ImageArgs, protein: &mut Protein, dim: u8) {
+    let options = image.options();
+    let out = exit_on_error(if image.views && dim == 3 {
+        render_views(protein, &image.out, &options)
     } else {
-        let mut best_ans = protein.clone();
-        let mut best_score = 0;
-        for _ in 0..4 {
-            let mut beam = Beam {
-                beam_width: 200,
-                nodes: vec![protein.clone()],
-                best_score: 0,
-                best_ans: protein.clone(),
-                num_direct: if args.dim == 2 { 3 } else { 5 },
-            };
-
-            beam.first_step();
-            println!("first step completed");
-
-            for i in 0..10 {
-                beam.one_step();
-                println!("one step completed");
-                println!("beam {}: {}", i, beam.best_score);
-                beam.local_one_step();
-                println!("local step completed");
-                println!("beam {}: {}", i, beam.best_score);
+        render(protein, dim, &image.out, &options)
+    });
+    println!("saved: {}", out.display());
+    if let Some(path) = &image.turntable {
+        if dim == 3 {
+            exit_on_error(turntable(protein, path, 36, &options));
+        }
+    }
+}
+
+fn main() {
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let sub = matches.subcommand().map(|(_, sub)| sub).unwrap();
+    match cli.command {
+        Command::Fold(mut args) => {
+            if let Some(path) = args.config.clone() {
+                exit_on_error(
+                    RunConfig::load(&path)
+                        .map_err(|e| e.to_string())
+                        .and_then(|c| overrides::apply_fold(&c, &mut args, sub)),
+                );
             }
-            if beam.best_score > best_score {
-                best_ans = beam.best_ans.clone();
-                best_score = beam.best_score;
+            fold(&args)
+        }
+        Command::Bench(mut args) => {
+            if let Some(path) = args.config.clone() {
+                exit_on_error(
+                    RunConfig::load(&path)
+                        .map_err(|e| e.to_string())
+                        .and_then(|c| overrides::apply_bench(&c, &mut args, sub)),
+                );
             }
-            animation(&mut beam.best_ans, args.dim);
+            bench(&args)
         }
-        animation(&mut best_ans, args.dim);
+        Command::Eval(args) => eval(&args),
+        Command::Render(args) => render_command(&args),
+        Command::Enumerate(args) => enumerate(&args),
+        Command::List(args) => list(&args),
+        Command::Dock(args) => dock(&args),
     }
 }
+
+fn exit_on_error<T, E: Display>(result: Result<T, E>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    })
+}
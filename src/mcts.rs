@@ -0,0 +1,394 @@
+use crate::budget::Budget;
+use crate::checkpoint::{MctsState, RngState, SolverState};
+use crate::moves::{directions, grow};
+use crate::observer::{Progress, SearchObserver};
+use crate::protein::{turn, Direction, Protein};
+use crate::solve::SolverSettings;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+// 途中まで作った鎖の座標を、最初の結合(x軸)を保つ回転・鏡映で正規化したもの
+type Key = Vec<(i32, i32, i32)>;
+
+// プレイアウトで途中から最後まで鎖を伸ばす方法
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rollout {
+    Random,
+    // 新しくできるH-H結合が多い向きを優先する
+    Greedy,
+}
+
+impl FromStr for Rollout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "random" => Ok(Rollout::Random),
+            "greedy" => Ok(Rollout::Greedy),
+            _ => Err(format!("unknown rollout: {}", s)),
+        }
+    }
+}
+
+// 探索木のノード。向きの列directまで決めた状態を表す
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Node {
+    pub direct: Vec<Direction>,
+    // 衝突せずに進める向きと、その先のノード(まだ展開していなければNone)
+    pub moves: Vec<Direction>,
+    pub children: Vec<Option<usize>>,
+    pub visits: u32,
+    // プレイアウトで得たスコアの合計
+    pub total_score: f64,
+    pub best_score: i32,
+    // 行き止まりで最後まで伸ばせないことがわかったノード
+    pub dead: bool,
+}
+
+pub struct Mcts {
+    // 1つのアミノ酸の位置を決めるまでに行うプレイアウトの回数
+    playouts: usize,
+    // UCTの探索項の係数
+    exploration: f64,
+    rollout: Rollout,
+    nodes: Vec<Node>,
+    // y、zの鏡映で重なる途中の構造は同じノードにまとめる
+    table: HashMap<Key, usize>,
+    // ここまでの向きは確定している
+    root: usize,
+    best_ans: Protein,
+    best_score: i32,
+    num_direct: i32,
+    // 完成した構造を評価した回数
+    evaluations: u64,
+    rng: ChaCha12Rng,
+    // 位置を確定したアミノ酸の数
+    iteration: usize,
+    budget: Budget,
+}
+
+// 向きの列から各アミノ酸の座標を求める
+fn positions(direct: &[Direction]) -> Vec<(i32, i32, i32)> {
+    let mut pos = vec![(0, 0, 0), (1, 0, 0)];
+    let mut step = (1, 0, 0);
+    for &d in direct {
+        step = turn(step, d);
+        let last = pos[pos.len() - 1];
+        pos.push((last.0 + step.0, last.1 + step.1, last.2 + step.2));
+    }
+    pos
+}
+
+// x軸を動かさない鏡映(y、zの符号の反転)4通りのうち、座標の列が辞書順で最小になるもの
+// yとzの入れ替えは使わない。3次元の向きの表し方では±yの直後に±zへ曲がれないが±zの直後には±yへ曲がれるので、
+// 入れ替えた状態どうしでは次に選べる向きが違い、同じノードにまとめられない
+fn canonical(pos: &[(i32, i32, i32)]) -> Key {
+    let mut best: Option<Key> = None;
+    for signs in 0..4 {
+        let sy = if signs & 1 == 1 { -1 } else { 1 };
+        let sz = if signs & 2 == 2 { -1 } else { 1 };
+        let key: Key = pos.iter().map(|&(x, y, z)| (x, y * sy, z * sz)).collect();
+        if best.as_ref().is_none_or(|b| key < *b) {
+            best = Some(key);
+        }
+    }
+    best.unwrap()
+}
+
+impl Mcts {
+    pub fn new(
+        template: &Protein,
+        num_direct: i32,
+        settings: &SolverSettings,
+        seed: u64,
+        budget: Budget,
+    ) -> Mcts {
+        Mcts {
+            playouts: settings.playouts,
+            exploration: settings.exploration,
+            rollout: settings.rollout,
+            nodes: Vec::new(),
+            table: HashMap::new(),
+            root: 0,
+            best_ans: template.clone(),
+            // まだ完成した構造がないことを表す。最初に完成したプレイアウトで必ず更新される
+            best_score: -1,
+            num_direct,
+            evaluations: 0,
+            rng: ChaCha12Rng::seed_from_u64(seed),
+            iteration: 0,
+            budget,
+        }
+    }
+    pub fn best_ans(&self) -> &Protein {
+        &self.best_ans
+    }
+    pub fn best_score(&self) -> i32 {
+        self.best_score
+    }
+    pub fn evaluations(&self) -> u64 {
+        self.evaluations
+    }
+    fn len(&self) -> usize {
+        self.best_ans.aminos.len() - 2
+    }
+    // directの状態のノードを返す。y、zの鏡映で重なるノードがすでにあればそれを使う
+    fn node(&mut self, direct: Vec<Direction>) -> usize {
+        let pos = positions(&direct);
+        let key = canonical(&pos);
+        if let Some(&index) = self.table.get(&key) {
+            return index;
+        }
+        let occupied: HashSet<(i32, i32, i32)> = pos.iter().copied().collect();
+        let last = pos[pos.len() - 1];
+        let prev = pos[pos.len() - 2];
+        let moves: Vec<Direction> = directions(self.num_direct)
+            .into_iter()
+            .filter(|&d| {
+                let step = turn((last.0 - prev.0, last.1 - prev.1, last.2 - prev.2), d);
+                !occupied.contains(&(last.0 + step.0, last.1 + step.1, last.2 + step.2))
+            })
+            .collect();
+        let dead = moves.is_empty() && direct.len() < self.len();
+        self.nodes.push(Node {
+            children: vec![None; moves.len()],
+            direct,
+            moves,
+            visits: 0,
+            total_score: 0.0,
+            best_score: 0,
+            dead,
+        });
+        self.table.insert(key, self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+    pub fn first_step(&mut self) {
+        self.nodes.clear();
+        self.table.clear();
+        self.root = self.node(Vec::new());
+    }
+    // 展開済みの子の中からUCTの値が最大のものを選ぶ。全て行き止まりならNone
+    fn select(&self, index: usize) -> Option<usize> {
+        let node = &self.nodes[index];
+        let scale = self.best_score.max(1) as f64;
+        let ln_visits = (node.visits.max(1) as f64).ln();
+        let mut best: Option<(f64, usize)> = None;
+        for &child in node.children.iter().flatten() {
+            let c = &self.nodes[child];
+            if c.dead {
+                continue;
+            }
+            let visits = c.visits.max(1) as f64;
+            let uct =
+                c.total_score / visits / scale + self.exploration * (ln_visits / visits).sqrt();
+            if best.is_none_or(|(value, _)| uct > value) {
+                best = Some((uct, child));
+            }
+        }
+        best.map(|(_, child)| child)
+    }
+    // 根から木をたどって1つ展開し、そこから最後まで鎖を伸ばした結果を木に反映する
+    pub fn playout(&mut self, observer: &mut dyn SearchObserver) {
+        let len = self.len();
+        let mut path = vec![self.root];
+        let mut index = self.root;
+        while self.nodes[index].direct.len() < len {
+            let untried: Vec<usize> = (0..self.nodes[index].moves.len())
+                .filter(|&k| self.nodes[index].children[k].is_none())
+                .collect();
+            if !untried.is_empty() {
+                let k = untried[self.rng.gen_range(0..untried.len())];
+                let mut direct = self.nodes[index].direct.clone();
+                direct.push(self.nodes[index].moves[k]);
+                let child = self.node(direct);
+                self.nodes[index].children[k] = Some(child);
+                path.push(child);
+                index = child;
+                break;
+            }
+            match self.select(index) {
+                Some(child) => {
+                    path.push(child);
+                    index = child;
+                }
+                None => {
+                    // 子が全て行き止まりなら、このノードも行き止まり
+                    self.nodes[index].dead = true;
+                    break;
+                }
+            }
+        }
+
+        let score = if self.nodes[index].dead {
+            0
+        } else {
+            let greedy = self.rollout == Rollout::Greedy;
+            let prefix = self.nodes[index].direct.clone();
+            match grow(
+                &self.best_ans,
+                &prefix,
+                self.num_direct,
+                &mut self.rng,
+                greedy,
+            ) {
+                Some(mut protein) => {
+                    // growが返すのは自己回避歩行だけ
+                    let score = protein.score().expect("grown walks are self-avoiding");
+                    self.evaluations += 1;
+                    if self.best_score < score {
+                        self.best_score = score;
+                        self.best_ans = protein.clone();
+                        observer.on_new_best(&protein, score);
+                    }
+                    score
+                }
+                None => 0,
+            }
+        };
+        for &i in path.iter() {
+            let node = &mut self.nodes[i];
+            node.visits += 1;
+            node.total_score += score as f64;
+            node.best_score = node.best_score.max(score);
+        }
+    }
+    // 予算が尽きるなどして完成したプレイアウトが1つもないときに、根から最後まで鎖を伸ばして答えにする
+    // 自己回避歩行は必ず存在するので、行き止まりなら伸ばし直す
+    fn fallback(&mut self, observer: &mut dyn SearchObserver) {
+        let greedy = self.rollout == Rollout::Greedy;
+        let mut protein = loop {
+            if let Some(protein) = grow(&self.best_ans, &[], self.num_direct, &mut self.rng, greedy)
+            {
+                break protein;
+            }
+        };
+        self.best_score = protein.score().expect("grown walks are self-avoiding");
+        self.evaluations += 1;
+        self.best_ans = protein.clone();
+        observer.on_new_best(&protein, self.best_score);
+    }
+    // プレイアウトで最も良いスコアが出た子に進み、次のアミノ酸の位置を確定する
+    // 進めなかった場合はfalse
+    fn commit(&mut self) -> bool {
+        let root = &self.nodes[self.root];
+        let next = root
+            .children
+            .iter()
+            .flatten()
+            .copied()
+            .filter(|&child| !self.nodes[child].dead)
+            .max_by_key(|&child| (self.nodes[child].best_score, self.nodes[child].visits));
+        let Some(next) = next else {
+            return false;
+        };
+        self.root = next;
+        self.prune();
+        true
+    }
+    // 根から届かなくなったノードを捨てて番号を詰める
+    fn prune(&mut self) {
+        let mut remap: HashMap<usize, usize> = HashMap::new();
+        let mut order = vec![self.root];
+        remap.insert(self.root, 0);
+        let mut i = 0;
+        while i < order.len() {
+            for &child in self.nodes[order[i]].children.iter().flatten() {
+                if let std::collections::hash_map::Entry::Vacant(e) = remap.entry(child) {
+                    e.insert(order.len());
+                    order.push(child);
+                }
+            }
+            i += 1;
+        }
+        let mut nodes: Vec<Node> = order.iter().map(|&i| self.nodes[i].clone()).collect();
+        for node in nodes.iter_mut() {
+            for child in node.children.iter_mut().flatten() {
+                *child = remap[child];
+            }
+        }
+        self.nodes = nodes;
+        self.table = self
+            .table
+            .drain()
+            .filter_map(|(key, index)| remap.get(&index).map(|&index| (key, index)))
+            .collect();
+        self.root = 0;
+    }
+    // アミノ酸の位置を1つずつ決めていく。1つ決めるごとにplayouts回のプレイアウトを行う
+    pub fn run(&mut self, observer: &mut dyn SearchObserver) {
+        let len = self.len();
+        if self.nodes.is_empty() {
+            observer.on_step_start(0, "first step");
+            self.first_step();
+            observer.on_step_end(0, "first step", self.best_score);
+        }
+        for i in self.iteration..len {
+            if self.budget.is_exhausted(self.evaluations, self.best_score) {
+                if i == 0 {
+                    observer.on_progress(&self.progress(0));
+                }
+                break;
+            }
+            observer.on_step_start(i, "mcts");
+            let mut completed = true;
+            for _ in 0..self.playouts {
+                if self.budget.is_exhausted(self.evaluations, self.best_score) {
+                    completed = false;
+                    break;
+                }
+                self.playout(observer);
+            }
+            let committed = completed && self.commit();
+            observer.on_step_end(i, "mcts", self.best_score);
+            observer.on_progress(&self.progress(i + 1));
+            if !committed {
+                break;
+            }
+            self.iteration = i + 1;
+            observer.on_checkpoint(&self.state());
+        }
+        if self.best_score < 0 {
+            self.fallback(observer);
+        }
+        observer.on_finished(&self.best_ans, self.best_score);
+    }
+    pub fn progress(&self, iteration: usize) -> Progress {
+        Progress {
+            iteration,
+            evaluations: self.evaluations,
+            best_score: self.best_score,
+            current_score: self.nodes.get(self.root).map_or(0, |node| node.best_score),
+            diversity: 0.0,
+        }
+    }
+    pub fn state(&self) -> SolverState {
+        SolverState::Mcts(MctsState {
+            iteration: self.iteration,
+            nodes: self.nodes.clone(),
+            root: self.root,
+            best_ans: self.best_ans.clone(),
+            best_score: self.best_score,
+            evaluations: self.evaluations,
+            rng: RngState::new(&self.rng),
+        })
+    }
+    pub fn restore(&mut self, state: MctsState) {
+        self.iteration = state.iteration;
+        self.nodes = state.nodes;
+        self.root = state.root;
+        self.best_ans = state.best_ans;
+        self.best_score = state.best_score;
+        self.evaluations = state.evaluations;
+        self.rng = state.rng.to_rng();
+        // 表は保存していないので、ノードの向きの列から作り直す
+        self.table = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (canonical(&positions(&node.direct)), index))
+            .collect();
+    }
+}
@@ -0,0 +1,230 @@
+use crate::packed::PackedMoves;
+use crate::protein::{relative_direction, turn, Amino, Direction, Protein};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::HashMap;
+
+// 2次元なら[S, L, R]、3次元なら[S, L, R, U, D]
+pub fn directions(num_direct: i32) -> Vec<Direction> {
+    let mut directions = vec![Direction::S, Direction::L, Direction::R];
+    if num_direct == 5 {
+        directions.push(Direction::U);
+        directions.push(Direction::D);
+    }
+    directions
+}
+
+// 向きの列prefixの続きを1つずつ伸ばして自己回避歩行を完成させる。行き止まりでは1つ前に戻る
+// greedyなら新しくできるH-H結合が多い向きから試す。戻りすぎた場合や完成できない場合はNone
+pub fn grow<R: Rng>(
+    template: &Protein,
+    prefix: &[Direction],
+    num_direct: i32,
+    rng: &mut R,
+    greedy: bool,
+) -> Option<Protein> {
+    let len = template.aminos.len() - 2;
+    let directions = directions(num_direct);
+    let mut pos = vec![(0, 0, 0), (1, 0, 0)];
+    let mut occupied: HashMap<(i32, i32, i32), usize> =
+        HashMap::from([((0, 0, 0), 0), ((1, 0, 0), 1)]);
+    for &d in prefix {
+        let next = next_position(&pos, d);
+        if occupied.contains_key(&next) {
+            return None;
+        }
+        occupied.insert(next, pos.len());
+        pos.push(next);
+    }
+    // 次に試す向きの候補。末尾から取り出す
+    let mut options = |pos: &[(i32, i32, i32)], occupied: &HashMap<(i32, i32, i32), usize>| {
+        let mut options = directions.clone();
+        options.shuffle(rng);
+        if greedy {
+            options.sort_by_cached_key(|&d| {
+                new_contacts(template, pos, occupied, next_position(pos, d))
+            });
+        }
+        options
+    };
+    let mut direct = prefix.to_vec();
+    let mut choices = vec![options(&pos, &occupied)];
+    let mut backtracks = 0;
+    while direct.len() < len {
+        let depth = direct.len() - prefix.len();
+        match choices[depth].pop() {
+            Some(d) => {
+                let next = next_position(&pos, d);
+                if occupied.contains_key(&next) {
+                    continue;
+                }
+                occupied.insert(next, pos.len());
+                pos.push(next);
+                direct.push(d);
+                choices.push(options(&pos, &occupied));
+            }
+            None => {
+                // 戻りすぎる場合は袋小路に入り込んでいるので諦める
+                backtracks += 1;
+                if depth == 0 || backtracks > 10 * len {
+                    return None;
+                }
+                choices.pop();
+                direct.pop();
+                occupied.remove(&pos.pop().unwrap());
+            }
+        }
+    }
+    let mut protein = template.clone();
+    protein.direct = direct;
+    Some(protein)
+}
+
+// 座標の列posの最後の結合からdの方向に曲がった次の位置
+pub fn next_position(pos: &[(i32, i32, i32)], d: Direction) -> (i32, i32, i32) {
+    let last = pos[pos.len() - 1];
+    let prev = pos[pos.len() - 2];
+    let step = turn((last.0 - prev.0, last.1 - prev.1, last.2 - prev.2), d);
+    (last.0 + step.0, last.1 + step.1, last.2 + step.2)
+}
+
+// 次のアミノ酸をnextに置いたときに新しくできるH-H結合の数
+pub fn new_contacts(
+    template: &Protein,
+    pos: &[(i32, i32, i32)],
+    occupied: &HashMap<(i32, i32, i32), usize>,
+    next: (i32, i32, i32),
+) -> usize {
+    let index = pos.len();
+    if occupied.contains_key(&next)
+        || template
+            .aminos
+            .get(index)
+            .is_none_or(|a| a.amino != Amino::H)
+    {
+        return 0;
+    }
+    let neighbours = [
+        (1, 0, 0),
+        (-1, 0, 0),
+        (0, 1, 0),
+        (0, -1, 0),
+        (0, 0, 1),
+        (0, 0, -1),
+    ];
+    neighbours
+        .iter()
+        .filter_map(|&(dx, dy, dz)| occupied.get(&(next.0 + dx, next.1 + dy, next.2 + dz)))
+        .filter(|&&j| j + 1 < index && template.aminos[j].amino == Amino::H)
+        .count()
+}
+
+// 結合の向きを絶対座標で見たときのk番目とk+gap番目を入れ替える(gapが1なら角の移動、2ならクランクシャフト)
+// 両端のアミノ酸の位置は変わらず、その間のアミノ酸だけが動く。向きで表せない場合はfalse
+pub fn swap_steps(protein: &mut Protein, k: usize, gap: usize, num_direct: i32) -> bool {
+    swap_directions(&mut protein.direct, k, gap, num_direct)
+}
+
+pub fn swap_directions(direct: &mut [Direction], k: usize, gap: usize, num_direct: i32) -> bool {
+    let len = direct.len();
+    // 最初の結合は(1, 0, 0)に固定されているので動かさない
+    if k == 0 || k + gap > len {
+        return false;
+    }
+    let mut steps = vec![(1, 0, 0)];
+    for &d in direct.iter() {
+        steps.push(turn(*steps.last().unwrap(), d));
+    }
+    if steps[k] == steps[k + gap] {
+        return false;
+    }
+    steps.swap(k, k + gap);
+    for i in k - 1..(k + gap + 1).min(len) {
+        match relative_direction(steps[i], steps[i + 1], num_direct) {
+            Some(d) => direct[i] = d,
+            None => return false,
+        }
+    }
+    true
+}
+
+// 現在の解を少しだけ変える格子上の動き
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Move {
+    // index番目の向きをdirectionに変える(それより後ろ全体が回転する)
+    Pivot { index: usize, direction: Direction },
+    // swap_stepsによる局所的な動き
+    Swap { index: usize, gap: usize },
+}
+
+impl Move {
+    // 動かした解を返す。向きで表せない場合はNone(衝突は調べない)
+    pub fn apply(&self, protein: &Protein, num_direct: i32) -> Option<Protein> {
+        let mut moved = protein.clone();
+        moved.direct = self.apply_directions(&protein.direct, num_direct)?;
+        Some(moved)
+    }
+    // 向きの列に対するapply
+    pub fn apply_directions(
+        &self,
+        direct: &[Direction],
+        num_direct: i32,
+    ) -> Option<Vec<Direction>> {
+        let mut moved = direct.to_vec();
+        match *self {
+            Move::Pivot { index, direction } => {
+                if moved[index] == direction {
+                    return None;
+                }
+                moved[index] = direction;
+            }
+            Move::Swap { index, gap } => {
+                if !swap_directions(&mut moved, index, gap, num_direct) {
+                    return None;
+                }
+            }
+        }
+        Some(moved)
+    }
+    // 詰めた向きの列に対するapply
+    pub fn apply_packed(&self, moves: &PackedMoves, num_direct: i32) -> Option<PackedMoves> {
+        match *self {
+            Move::Pivot { index, direction } => {
+                if moves.get(index) == direction {
+                    return None;
+                }
+                let mut moved = moves.clone();
+                moved.set(index, direction);
+                Some(moved)
+            }
+            Move::Swap { .. } => {
+                let direct = self.apply_directions(&moves.to_directions(), num_direct)?;
+                Some(PackedMoves::from_directions(&direct, num_direct))
+            }
+        }
+    }
+}
+
+// proteinから1回の動きで移れる候補を全て列挙する
+pub fn neighbourhood(protein: &Protein, num_direct: i32) -> Vec<Move> {
+    direction_neighbourhood(&protein.direct, num_direct)
+}
+
+// 向きの列に対するneighbourhood
+pub fn direction_neighbourhood(direct: &[Direction], num_direct: i32) -> Vec<Move> {
+    let len = direct.len();
+    let mut moves = Vec::new();
+    for (index, &current) in direct.iter().enumerate() {
+        for direction in directions(num_direct) {
+            if current != direction {
+                moves.push(Move::Pivot { index, direction });
+            }
+        }
+    }
+    for gap in 1..=2 {
+        for index in 1..=len.saturating_sub(gap) {
+            moves.push(Move::Swap { index, gap });
+        }
+    }
+    moves
+}
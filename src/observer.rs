@@ -0,0 +1,307 @@
+use crate::checkpoint::SolverState;
+use crate::error::Error;
+use crate::protein::Protein;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, Mutex};
+
+// ステップが終わるごとの探索の状態
+#[derive(Clone, Copy, Debug)]
+pub struct Progress {
+    // やり直しをまたいで通しで数えた繰り返しの回数
+    pub iteration: usize,
+    // 構造を評価した回数
+    pub evaluations: u64,
+    pub best_score: i32,
+    // 現在の解(ビームの場合はビーム内で最も良いもの)のスコア
+    pub current_score: i32,
+    // ビーム内の解の多様性(0~1)。解を1つしか持たない探索では0
+    pub diversity: f64,
+}
+
+// 探索の進み具合を受け取るためのフック。必要なメソッドだけ実装すればよい
+pub trait SearchObserver {
+    fn on_restart(&mut self, _restart: usize) {}
+    fn on_step_start(&mut self, _iteration: usize, _step: &str) {}
+    fn on_step_end(&mut self, _iteration: usize, _step: &str, _best_score: i32) {}
+    // ビームなどの次の世代に採用された候補
+    fn on_candidate_accepted(&mut self, _candidate: &Protein, _score: i32) {}
+    // on_candidate_acceptedを受け取るか。falseなら探索アルゴリズムは候補の座標を求めずに済ませてよい
+    fn wants_candidates(&self) -> bool {
+        false
+    }
+    fn on_new_best(&mut self, _best: &Protein, _score: i32) {}
+    fn on_progress(&mut self, _progress: &Progress) {}
+    // 再開できる区切りごとの探索の状態
+    fn on_checkpoint(&mut self, _state: &SolverState) {}
+    fn on_finished(&mut self, _best: &Protein, _score: i32) {}
+}
+
+// 何もしないオブザーバー
+impl SearchObserver for () {}
+
+// 借用したままでも渡せるようにする。探索後に中身を取り出したい場合に使う
+impl<T: SearchObserver + ?Sized> SearchObserver for &mut T {
+    fn on_restart(&mut self, restart: usize) {
+        (**self).on_restart(restart);
+    }
+    fn on_step_start(&mut self, iteration: usize, step: &str) {
+        (**self).on_step_start(iteration, step);
+    }
+    fn on_step_end(&mut self, iteration: usize, step: &str, best_score: i32) {
+        (**self).on_step_end(iteration, step, best_score);
+    }
+    fn on_candidate_accepted(&mut self, candidate: &Protein, score: i32) {
+        (**self).on_candidate_accepted(candidate, score);
+    }
+    fn wants_candidates(&self) -> bool {
+        (**self).wants_candidates()
+    }
+    fn on_new_best(&mut self, best: &Protein, score: i32) {
+        (**self).on_new_best(best, score);
+    }
+    fn on_progress(&mut self, progress: &Progress) {
+        (**self).on_progress(progress);
+    }
+    fn on_checkpoint(&mut self, state: &SolverState) {
+        (**self).on_checkpoint(state);
+    }
+    fn on_finished(&mut self, best: &Protein, score: i32) {
+        (**self).on_finished(best, score);
+    }
+}
+
+// 2つのオブザーバーに同じイベントを流す。スレッドをまたいで渡したい場合はObserversの代わりにこちらを使う
+impl<A: SearchObserver, B: SearchObserver> SearchObserver for (A, B) {
+    fn on_restart(&mut self, restart: usize) {
+        self.0.on_restart(restart);
+        self.1.on_restart(restart);
+    }
+    fn on_step_start(&mut self, iteration: usize, step: &str) {
+        self.0.on_step_start(iteration, step);
+        self.1.on_step_start(iteration, step);
+    }
+    fn on_step_end(&mut self, iteration: usize, step: &str, best_score: i32) {
+        self.0.on_step_end(iteration, step, best_score);
+        self.1.on_step_end(iteration, step, best_score);
+    }
+    fn on_candidate_accepted(&mut self, candidate: &Protein, score: i32) {
+        self.0.on_candidate_accepted(candidate, score);
+        self.1.on_candidate_accepted(candidate, score);
+    }
+    fn wants_candidates(&self) -> bool {
+        self.0.wants_candidates() || self.1.wants_candidates()
+    }
+    fn on_new_best(&mut self, best: &Protein, score: i32) {
+        self.0.on_new_best(best, score);
+        self.1.on_new_best(best, score);
+    }
+    fn on_progress(&mut self, progress: &Progress) {
+        self.0.on_progress(progress);
+        self.1.on_progress(progress);
+    }
+    fn on_checkpoint(&mut self, state: &SolverState) {
+        self.0.on_checkpoint(state);
+        self.1.on_checkpoint(state);
+    }
+    fn on_finished(&mut self, best: &Protein, score: i32) {
+        self.0.on_finished(best, score);
+        self.1.on_finished(best, score);
+    }
+}
+
+impl<T: SearchObserver> SearchObserver for Option<T> {
+    fn on_restart(&mut self, restart: usize) {
+        if let Some(observer) = self {
+            observer.on_restart(restart);
+        }
+    }
+    fn on_step_start(&mut self, iteration: usize, step: &str) {
+        if let Some(observer) = self {
+            observer.on_step_start(iteration, step);
+        }
+    }
+    fn on_step_end(&mut self, iteration: usize, step: &str, best_score: i32) {
+        if let Some(observer) = self {
+            observer.on_step_end(iteration, step, best_score);
+        }
+    }
+    fn on_candidate_accepted(&mut self, candidate: &Protein, score: i32) {
+        if let Some(observer) = self {
+            observer.on_candidate_accepted(candidate, score);
+        }
+    }
+    fn wants_candidates(&self) -> bool {
+        self.as_ref()
+            .is_some_and(|observer| observer.wants_candidates())
+    }
+    fn on_new_best(&mut self, best: &Protein, score: i32) {
+        if let Some(observer) = self {
+            observer.on_new_best(best, score);
+        }
+    }
+    fn on_progress(&mut self, progress: &Progress) {
+        if let Some(observer) = self {
+            observer.on_progress(progress);
+        }
+    }
+    fn on_checkpoint(&mut self, state: &SolverState) {
+        if let Some(observer) = self {
+            observer.on_checkpoint(state);
+        }
+    }
+    fn on_finished(&mut self, best: &Protein, score: i32) {
+        if let Some(observer) = self {
+            observer.on_finished(best, score);
+        }
+    }
+}
+
+// 複数のオブザーバーにまとめてイベントを流す
+#[derive(Default)]
+pub struct Observers<'a> {
+    pub observers: Vec<Box<dyn SearchObserver + 'a>>,
+}
+
+impl<'a> Observers<'a> {
+    pub fn push(&mut self, observer: impl SearchObserver + 'a) {
+        self.observers.push(Box::new(observer));
+    }
+}
+
+impl SearchObserver for Observers<'_> {
+    fn on_restart(&mut self, restart: usize) {
+        for observer in self.observers.iter_mut() {
+            observer.on_restart(restart);
+        }
+    }
+    fn on_step_start(&mut self, iteration: usize, step: &str) {
+        for observer in self.observers.iter_mut() {
+            observer.on_step_start(iteration, step);
+        }
+    }
+    fn on_step_end(&mut self, iteration: usize, step: &str, best_score: i32) {
+        for observer in self.observers.iter_mut() {
+            observer.on_step_end(iteration, step, best_score);
+        }
+    }
+    fn on_candidate_accepted(&mut self, candidate: &Protein, score: i32) {
+        for observer in self.observers.iter_mut() {
+            observer.on_candidate_accepted(candidate, score);
+        }
+    }
+    fn wants_candidates(&self) -> bool {
+        self.observers
+            .iter()
+            .any(|observer| observer.wants_candidates())
+    }
+    fn on_new_best(&mut self, best: &Protein, score: i32) {
+        for observer in self.observers.iter_mut() {
+            observer.on_new_best(best, score);
+        }
+    }
+    fn on_progress(&mut self, progress: &Progress) {
+        for observer in self.observers.iter_mut() {
+            observer.on_progress(progress);
+        }
+    }
+    fn on_checkpoint(&mut self, state: &SolverState) {
+        for observer in self.observers.iter_mut() {
+            observer.on_checkpoint(state);
+        }
+    }
+    fn on_finished(&mut self, best: &Protein, score: i32) {
+        for observer in self.observers.iter_mut() {
+            observer.on_finished(best, score);
+        }
+    }
+}
+
+// 標準出力に途中経過を表示する。並列に実行するときはやり直しの番号を行頭に表示する
+#[derive(Default, Clone)]
+pub struct ConsoleLogger {
+    pub prefix: String,
+}
+
+impl SearchObserver for ConsoleLogger {
+    fn on_restart(&mut self, restart: usize) {
+        println!("{}restart {}", self.prefix, restart);
+    }
+    fn on_step_end(&mut self, iteration: usize, step: &str, best_score: i32) {
+        println!("{}{} {}: {}", self.prefix, step, iteration, best_score);
+    }
+    fn on_finished(&mut self, _best: &Protein, score: i32) {
+        println!("{}finished: {}", self.prefix, score);
+    }
+}
+
+// イベントを1行ずつCSVに書き出す。cloneしたものは同じファイルに書き込むので、並列のやり直しごとに配れる
+// 書き込みに失敗しても探索は続け、最後の失敗をtake_errorで受け取る
+#[derive(Clone)]
+pub struct CsvTrace {
+    writer: Arc<Mutex<BufWriter<File>>>,
+    error: Arc<Mutex<Option<Error>>>,
+    path: String,
+    restart: usize,
+    iteration: usize,
+    step: String,
+}
+
+impl CsvTrace {
+    pub fn new(path: &str) -> Result<CsvTrace, Error> {
+        let error = |e| Error::io(path, e);
+        let mut writer = BufWriter::new(File::create(path).map_err(error)?);
+        writeln!(writer, "restart,iteration,step,event,score").map_err(error)?;
+        Ok(CsvTrace {
+            writer: Arc::new(Mutex::new(writer)),
+            error: Arc::new(Mutex::new(None)),
+            path: path.to_string(),
+            restart: 0,
+            iteration: 0,
+            step: String::new(),
+        })
+    }
+    pub fn take_error(&self) -> Option<Error> {
+        self.error.lock().unwrap().take()
+    }
+    fn write(&mut self, event: &str, score: i32) {
+        let result = writeln!(
+            self.writer.lock().unwrap(),
+            "{},{},{},{},{}",
+            self.restart,
+            self.iteration,
+            self.step,
+            event,
+            score
+        );
+        self.check(result);
+    }
+    fn check(&self, result: std::io::Result<()>) {
+        if let Err(e) = result {
+            *self.error.lock().unwrap() = Some(Error::io(&self.path, e));
+        }
+    }
+}
+
+impl SearchObserver for CsvTrace {
+    fn on_restart(&mut self, restart: usize) {
+        self.restart = restart;
+        self.iteration = 0;
+        self.step.clear();
+    }
+    fn on_step_start(&mut self, iteration: usize, step: &str) {
+        self.iteration = iteration;
+        self.step = step.to_string();
+    }
+    fn on_step_end(&mut self, _iteration: usize, _step: &str, best_score: i32) {
+        self.write("step_end", best_score);
+    }
+    fn on_new_best(&mut self, _best: &Protein, score: i32) {
+        self.write("new_best", score);
+    }
+    fn on_finished(&mut self, _best: &Protein, score: i32) {
+        self.write("finished", score);
+        let result = self.writer.lock().unwrap().flush();
+        self.check(result);
+    }
+}
@@ -0,0 +1,537 @@
+use crate::cli::{BenchArgs, BudgetArgs, FoldArgs, ImageArgs, ProteinArgs, SolverArgs};
+use clap::parser::ValueSource;
+use clap::ArgMatches;
+use protein_structure::config::{
+    at_least_one, parse_init, positive, probability, seconds, LatticeConfig, ObjectiveConfig,
+    OutputConfig, RunConfig, SequenceConfig, SolverConfig, MAX_SEED,
+};
+use std::fmt::Debug;
+use std::str::FromStr;
+
+// コマンドラインの引数と設定ファイル(RunConfig)の間の変換
+// foldの実行で実際に使う設定
+pub fn from_fold(args: &FoldArgs, seed: u64) -> RunConfig {
+    let mut solver = solver_config(&args.solver, &args.budget);
+    solver.compare = Some(args.compare);
+    let image = &args.image;
+    RunConfig {
+        seed: Some(seed),
+        sequence: sequence_config(&args.protein),
+        lattice: LatticeConfig {
+            dim: Some(args.protein.dim),
+        },
+        objective: objective_config(&args.budget),
+        solver,
+        output: OutputConfig {
+            out: Some(image.out.clone()),
+            format: image.format.as_ref().map(name),
+            width: Some(image.width),
+            height: Some(image.height),
+            frame_delay: Some(image.frame_delay),
+            labels: Some(image.labels),
+            yaw: Some(image.yaw),
+            pitch: Some(image.pitch),
+            scale: Some(image.scale),
+            views: Some(image.views),
+            turntable: image.turntable.clone(),
+            vis: Some(args.vis),
+            step: Some(args.step),
+            trace: Some(args.trace.clone()),
+            trace_delay: Some(args.trace_delay),
+            log_csv: args.log_csv.clone(),
+            trajectory: args.trajectory.clone(),
+            convergence: args.convergence.clone(),
+            save: args.save.clone(),
+            checkpoint: args.checkpoint.clone(),
+            checkpoint_interval: Some(args.checkpoint_interval),
+        },
+    }
+}
+
+// benchの実行で実際に使う設定
+pub fn from_bench(args: &BenchArgs, ids: &[usize], seed: u64) -> RunConfig {
+    RunConfig {
+        seed: Some(seed),
+        sequence: SequenceConfig {
+            ids: Some(ids.to_vec()),
+            ..SequenceConfig::default()
+        },
+        lattice: LatticeConfig {
+            dim: Some(args.dim),
+        },
+        objective: objective_config(&args.budget),
+        solver: solver_config(&args.solver, &args.budget),
+        output: OutputConfig::default(),
+    }
+}
+
+// dockのように打ち切りの条件だけを使うときの設定
+pub fn from_budget(args: &BudgetArgs) -> RunConfig {
+    RunConfig {
+        objective: objective_config(args),
+        solver: SolverConfig {
+            time_limit: args.time_limit,
+            max_evaluations: args.max_evaluations,
+            ..SolverConfig::default()
+        },
+        ..RunConfig::default()
+    }
+}
+
+// matchesでコマンドラインから指定されていない項目を、設定ファイルの値で置き換える
+pub fn apply_fold(
+    config: &RunConfig,
+    args: &mut FoldArgs,
+    matches: &ArgMatches,
+) -> Result<(), String> {
+    let overlay = Overlay { matches };
+    overlay.set("seed", &mut args.solver.seed, check_seed(config.seed)?);
+    apply_protein(config, &overlay, &mut args.protein)?;
+    apply_solver(&config.solver, &overlay, &mut args.solver, &mut args.budget)?;
+    apply_objective(&config.objective, &overlay, &mut args.budget);
+    overlay.set("compare", &mut args.compare, config.solver.compare);
+    apply_output(&config.output, &overlay, &mut args.image)?;
+    let output = &config.output;
+    overlay.set("vis", &mut args.vis, output.vis);
+    overlay.set("step", &mut args.step, output.step);
+    overlay.set("trace", &mut args.trace, output.trace.clone());
+    overlay.set("trace_delay", &mut args.trace_delay, output.trace_delay);
+    overlay.set("log_csv", &mut args.log_csv, some(&output.log_csv));
+    overlay.set("trajectory", &mut args.trajectory, some(&output.trajectory));
+    overlay.set(
+        "convergence",
+        &mut args.convergence,
+        some(&output.convergence),
+    );
+    overlay.set("save", &mut args.save, some(&output.save));
+    overlay.set("checkpoint", &mut args.checkpoint, some(&output.checkpoint));
+    overlay.set(
+        "checkpoint_interval",
+        &mut args.checkpoint_interval,
+        output.checkpoint_interval.map(seconds).transpose()?,
+    );
+    Ok(())
+}
+
+// benchでは配列の指定と出力の設定は使わない
+pub fn apply_bench(
+    config: &RunConfig,
+    args: &mut BenchArgs,
+    matches: &ArgMatches,
+) -> Result<(), String> {
+    let overlay = Overlay { matches };
+    overlay.set("seed", &mut args.solver.seed, check_seed(config.seed)?);
+    overlay.set("dim", &mut args.dim, check_dim(config.lattice.dim)?);
+    overlay.set("ids", &mut args.ids, config.sequence.ids.clone());
+    apply_solver(&config.solver, &overlay, &mut args.solver, &mut args.budget)?;
+    apply_objective(&config.objective, &overlay, &mut args.budget);
+    Ok(())
+}
+
+fn apply_protein(
+    config: &RunConfig,
+    overlay: &Overlay,
+    args: &mut ProteinArgs,
+) -> Result<(), String> {
+    overlay.set("dim", &mut args.dim, check_dim(config.lattice.dim)?);
+    overlay.set("id", &mut args.id, config.sequence.id);
+    // コマンドラインで--idを指定したときは設定ファイルの配列を使わない
+    if !overlay.given("id") {
+        overlay.set(
+            "sequence",
+            &mut args.sequence,
+            some(&config.sequence.sequence),
+        );
+    }
+    Ok(())
+}
+
+fn sequence_config(args: &ProteinArgs) -> SequenceConfig {
+    SequenceConfig {
+        id: args.sequence.is_none().then_some(args.id),
+        sequence: args.sequence.clone(),
+        ids: None,
+    }
+}
+
+fn objective_config(args: &BudgetArgs) -> ObjectiveConfig {
+    ObjectiveConfig {
+        target: args.target,
+        stop_at_optimum: Some(args.stop_at_optimum),
+    }
+}
+
+fn apply_objective(config: &ObjectiveConfig, overlay: &Overlay, args: &mut BudgetArgs) {
+    overlay.set("target", &mut args.target, config.target.map(Some));
+    overlay.set(
+        "stop_at_optimum",
+        &mut args.stop_at_optimum,
+        config.stop_at_optimum,
+    );
+}
+
+fn solver_config(args: &SolverArgs, budget: &BudgetArgs) -> SolverConfig {
+    SolverConfig {
+        name: Some(name(&args.solver)),
+        compare: None,
+        restarts: Some(args.restarts),
+        iterations: args.iterations,
+        time_limit: budget.time_limit,
+        max_evaluations: budget.max_evaluations,
+        threads: Some(args.threads),
+        init: Some(
+            args.init
+                .iter()
+                .map(|(solver, init)| match solver {
+                    Some(solver) => format!("{}={}", name(solver), name(init)),
+                    None => name(init),
+                })
+                .collect(),
+        ),
+        temperature: Some(args.temperature),
+        beam_width: Some(args.beam_width),
+        selection: Some(name(&args.selection)),
+        sharpness: Some(args.sharpness),
+        diversity_weight: Some(args.diversity_weight),
+        window: Some(args.window),
+        stride: Some(args.stride),
+        refine: Some(name(&args.refine)),
+        refine_samples: Some(args.refine_samples),
+        population: Some(args.population),
+        crossover_rate: Some(args.crossover_rate),
+        mutation_rate: Some(args.mutation_rate),
+        tournament_size: Some(args.tournament_size),
+        elitism: Some(args.elitism),
+        niche_radius: Some(args.niche_radius),
+        crossover_attempts: Some(args.crossover_attempts),
+        tabu_tenure: Some(args.tabu_tenure),
+        stagnation: Some(args.stagnation),
+        perturbation: Some(args.perturbation),
+        playouts: Some(args.playouts),
+        exploration: Some(args.exploration),
+        rollout: Some(name(&args.rollout)),
+        max_cores: Some(args.max_cores),
+        max_nodes: Some(args.max_nodes),
+        core_levels: Some(args.core_levels),
+    }
+}
+
+fn apply_solver(
+    config: &SolverConfig,
+    overlay: &Overlay,
+    args: &mut SolverArgs,
+    budget: &mut BudgetArgs,
+) -> Result<(), String> {
+    let solver = match &config.name {
+        Some(name) => Some(name.parse()?),
+        None => None,
+    };
+    overlay.set("solver", &mut args.solver, solver);
+    overlay.set(
+        "restarts",
+        &mut args.restarts,
+        config.restarts.map(at_least_one).transpose()?,
+    );
+    overlay.set(
+        "iterations",
+        &mut args.iterations,
+        config.iterations.map(Some),
+    );
+    overlay.set(
+        "time_limit",
+        &mut budget.time_limit,
+        config.time_limit.map(seconds).transpose()?.map(Some),
+    );
+    overlay.set(
+        "max_evaluations",
+        &mut budget.max_evaluations,
+        config.max_evaluations.map(Some),
+    );
+    overlay.set("threads", &mut args.threads, config.threads);
+    let init = match &config.init {
+        Some(init) => Some(
+            init.iter()
+                .map(|s| parse_init(s))
+                .collect::<Result<_, _>>()?,
+        ),
+        None => None,
+    };
+    overlay.set("init", &mut args.init, init);
+    overlay.set(
+        "temperature",
+        &mut args.temperature,
+        config.temperature.map(positive).transpose()?,
+    );
+    overlay.set(
+        "beam_width",
+        &mut args.beam_width,
+        config.beam_width.map(at_least_one).transpose()?,
+    );
+    overlay.set("selection", &mut args.selection, parse(&config.selection)?);
+    overlay.set("sharpness", &mut args.sharpness, config.sharpness);
+    overlay.set(
+        "diversity_weight",
+        &mut args.diversity_weight,
+        config.diversity_weight,
+    );
+    overlay.set("window", &mut args.window, config.window);
+    overlay.set("stride", &mut args.stride, config.stride);
+    overlay.set("refine", &mut args.refine, parse(&config.refine)?);
+    overlay.set(
+        "refine_samples",
+        &mut args.refine_samples,
+        config.refine_samples,
+    );
+    overlay.set(
+        "population",
+        &mut args.population,
+        config.population.map(at_least_one).transpose()?,
+    );
+    overlay.set(
+        "crossover_rate",
+        &mut args.crossover_rate,
+        config.crossover_rate.map(probability).transpose()?,
+    );
+    overlay.set(
+        "mutation_rate",
+        &mut args.mutation_rate,
+        config.mutation_rate.map(probability).transpose()?,
+    );
+    overlay.set(
+        "tournament_size",
+        &mut args.tournament_size,
+        config.tournament_size,
+    );
+    overlay.set("elitism", &mut args.elitism, config.elitism);
+    overlay.set("niche_radius", &mut args.niche_radius, config.niche_radius);
+    overlay.set(
+        "crossover_attempts",
+        &mut args.crossover_attempts,
+        config.crossover_attempts,
+    );
+    overlay.set("tabu_tenure", &mut args.tabu_tenure, config.tabu_tenure);
+    overlay.set("stagnation", &mut args.stagnation, config.stagnation);
+    overlay.set("perturbation", &mut args.perturbation, config.perturbation);
+    overlay.set(
+        "playouts",
+        &mut args.playouts,
+        config.playouts.map(at_least_one).transpose()?,
+    );
+    overlay.set("exploration", &mut args.exploration, config.exploration);
+    overlay.set("rollout", &mut args.rollout, parse(&config.rollout)?);
+    overlay.set("max_cores", &mut args.max_cores, config.max_cores);
+    overlay.set("max_nodes", &mut args.max_nodes, config.max_nodes);
+    overlay.set("core_levels", &mut args.core_levels, config.core_levels);
+    Ok(())
+}
+
+fn apply_output(
+    config: &OutputConfig,
+    overlay: &Overlay,
+    args: &mut ImageArgs,
+) -> Result<(), String> {
+    overlay.set("out", &mut args.out, config.out.clone());
+    overlay.set("format", &mut args.format, parse(&config.format)?.map(Some));
+    overlay.set("width", &mut args.width, config.width);
+    overlay.set("height", &mut args.height, config.height);
+    overlay.set("frame_delay", &mut args.frame_delay, config.frame_delay);
+    overlay.set("labels", &mut args.labels, config.labels);
+    overlay.set("yaw", &mut args.yaw, config.yaw);
+    overlay.set("pitch", &mut args.pitch, config.pitch);
+    overlay.set("scale", &mut args.scale, config.scale);
+    overlay.set("views", &mut args.views, config.views);
+    overlay.set("turntable", &mut args.turntable, some(&config.turntable));
+    Ok(())
+}
+
+struct Overlay<'a> {
+    matches: &'a ArgMatches,
+}
+
+impl Overlay<'_> {
+    fn given(&self, id: &str) -> bool {
+        self.matches.value_source(id) == Some(ValueSource::CommandLine)
+    }
+    // 設定ファイルに値があり、コマンドラインで指定されていなければ置き換える
+    fn set<T>(&self, id: &str, target: &mut T, value: Option<T>) {
+        if let Some(value) = value {
+            if !self.given(id) {
+                *target = value;
+            }
+        }
+    }
+}
+
+// 各列挙型のFromStrはDebugの名前を小文字にしたものを受け付ける
+fn name<T: Debug>(value: &T) -> String {
+    format!("{:?}", value).to_lowercase()
+}
+
+fn parse<T: FromStr<Err = String>>(value: &Option<String>) -> Result<Option<T>, String> {
+    value.as_deref().map(T::from_str).transpose()
+}
+
+// Option<T>の項目用。設定ファイルにあればSome(値)で置き換える
+fn some<T: Clone>(value: &Option<T>) -> Option<Option<T>> {
+    value.clone().map(Some)
+}
+
+fn check_dim(dim: Option<u8>) -> Result<Option<u8>, String> {
+    match dim {
+        Some(dim) if dim != 2 && dim != 3 => Err(format!("dim must be 2 or 3, got {}", dim)),
+        _ => Ok(dim),
+    }
+}
+
+fn check_seed(seed: Option<u64>) -> Result<Option<Option<u64>>, String> {
+    match seed {
+        Some(seed) if seed > MAX_SEED => Err(format!("seed must be at most {}", MAX_SEED)),
+        _ => Ok(seed.map(Some)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{Cli, Command};
+    use clap::{CommandFactory, FromArgMatches};
+    use protein_structure::solve::Solver;
+
+    // コマンドラインの引数を読み、TOMLの設定ファイルを重ねる
+    fn fold(args: &[&str], toml: &str) -> Result<FoldArgs, String> {
+        let argv = ["protein_structure", "fold"].iter().chain(args);
+        let matches = Cli::command().try_get_matches_from(argv).unwrap();
+        let Command::Fold(mut fold) = Cli::from_arg_matches(&matches).unwrap().command else {
+            unreachable!()
+        };
+        let config: RunConfig = toml::from_str(toml).unwrap();
+        apply_fold(&config, &mut fold, matches.subcommand().unwrap().1)?;
+        Ok(*fold)
+    }
+
+    fn bench(args: &[&str], toml: &str) -> Result<BenchArgs, String> {
+        let argv = ["protein_structure", "bench"].iter().chain(args);
+        let matches = Cli::command().try_get_matches_from(argv).unwrap();
+        let Command::Bench(mut bench) = Cli::from_arg_matches(&matches).unwrap().command else {
+            unreachable!()
+        };
+        let config: RunConfig = toml::from_str(toml).unwrap();
+        apply_bench(&config, &mut bench, matches.subcommand().unwrap().1)?;
+        Ok(*bench)
+    }
+
+    const CONFIG: &str = r#"
+        seed = 42
+
+        [sequence]
+        sequence = "HPHPPH"
+
+        [lattice]
+        dim = 3
+
+        [solver]
+        name = "tabu"
+        restarts = 7
+        temperature = 2.5
+        time_limit = 3.0
+
+        [output]
+        save = "config.json"
+    "#;
+
+    #[test]
+    fn empty_config_keeps_the_defaults() {
+        let args = fold(&[], "").unwrap();
+        assert_eq!(args.solver.solver, Solver::Beam);
+        assert_eq!(args.solver.restarts, 4);
+        assert_eq!(args.solver.temperature, 5.0);
+        assert_eq!(args.solver.seed, None);
+        assert_eq!(args.budget.time_limit, None);
+        assert_eq!(args.protein.dim, 2);
+        assert_eq!(args.protein.id, 10);
+        assert_eq!(args.protein.sequence, None);
+        assert_eq!(args.save, None);
+    }
+
+    #[test]
+    fn config_overrides_the_defaults() {
+        let args = fold(&[], CONFIG).unwrap();
+        assert_eq!(args.solver.seed, Some(42));
+        assert_eq!(args.protein.sequence.as_deref(), Some("HPHPPH"));
+        assert_eq!(args.protein.dim, 3);
+        assert_eq!(args.solver.solver, Solver::Tabu);
+        assert_eq!(args.solver.restarts, 7);
+        assert_eq!(args.solver.temperature, 2.5);
+        assert_eq!(args.budget.time_limit, Some(3.0));
+        assert_eq!(args.save.as_deref(), Some("config.json"));
+    }
+
+    #[test]
+    fn command_line_overrides_the_config() {
+        let args = fold(
+            &[
+                "--restarts",
+                "2",
+                "--dim",
+                "2",
+                "--save",
+                "cli.json",
+                "--seed",
+                "1",
+            ],
+            CONFIG,
+        )
+        .unwrap();
+        assert_eq!(args.solver.restarts, 2);
+        assert_eq!(args.protein.dim, 2);
+        assert_eq!(args.save.as_deref(), Some("cli.json"));
+        assert_eq!(args.solver.seed, Some(1));
+        // コマンドラインで指定していない項目は設定ファイルのまま
+        assert_eq!(args.solver.temperature, 2.5);
+        assert_eq!(args.solver.solver, Solver::Tabu);
+    }
+
+    // 既定値と同じ値でも、コマンドラインで指定したものが優先される
+    #[test]
+    fn explicit_default_beats_the_config() {
+        let args = fold(&["--restarts", "4", "--solver", "beam"], CONFIG).unwrap();
+        assert_eq!(args.solver.restarts, 4);
+        assert_eq!(args.solver.solver, Solver::Beam);
+    }
+
+    #[test]
+    fn command_line_id_ignores_the_config_sequence() {
+        let args = fold(&["--id", "3"], CONFIG).unwrap();
+        assert_eq!(args.protein.id, 3);
+        assert_eq!(args.protein.sequence, None);
+    }
+
+    #[test]
+    fn invalid_config_values_are_rejected() {
+        for toml in [
+            "[solver]\nrestarts = 0",
+            "[solver]\ntime_limit = -1.0",
+            "[solver]\nmutation_rate = 1.5",
+            "[solver]\ntemperature = 0.0",
+            "[solver]\nname = \"unknown\"",
+            "[lattice]\ndim = 4",
+        ] {
+            assert!(fold(&[], toml).is_err(), "{}", toml);
+        }
+        // コマンドラインで置き換える項目でも、設定ファイルの誤りは誤りとして報告する
+        assert!(fold(&["--restarts", "3"], "[solver]\nrestarts = 0").is_err());
+    }
+
+    #[test]
+    fn bench_uses_the_same_precedence() {
+        let toml = "[sequence]\nids = [1, 2]\n[lattice]\ndim = 3\n[solver]\nrestarts = 5";
+        let args = bench(&[], toml).unwrap();
+        assert_eq!(args.ids, vec![1, 2]);
+        assert_eq!(args.dim, 3);
+        assert_eq!(args.solver.restarts, 5);
+        let args = bench(&["--dim", "2", "--restarts", "1"], toml).unwrap();
+        assert_eq!(args.ids, vec![1, 2]);
+        assert_eq!(args.dim, 2);
+        assert_eq!(args.solver.restarts, 1);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::protein::{Direction, Protein};
+use serde::{Deserialize, Serialize};
+
+const DIRECTIONS: [Direction; 5] = [
+    Direction::S,
+    Direction::L,
+    Direction::R,
+    Direction::U,
+    Direction::D,
+];
+
+// 向きの列を、2次元なら1つ2ビット、3次元なら3ビットでu64に詰めたもの
+// 各ワードの上位ビットから詰めるので、同じ長さなら大小は向きの列の辞書順と同じになる
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct PackedMoves {
+    words: Vec<u64>,
+    len: usize,
+    bits: u32,
+}
+
+impl PackedMoves {
+    pub fn from_directions(direct: &[Direction], num_direct: i32) -> PackedMoves {
+        let bits = if num_direct == 5 { 3 } else { 2 };
+        let per_word = 64 / bits as usize;
+        let mut packed = PackedMoves {
+            words: vec![0; direct.len().div_ceil(per_word)],
+            len: direct.len(),
+            bits,
+        };
+        for (i, &d) in direct.iter().enumerate() {
+            packed.set(i, d);
+        }
+        packed
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    fn per_word(&self) -> usize {
+        64 / self.bits as usize
+    }
+    // i番目の向きが入っているワードと、その中でのシフト量
+    fn locate(&self, i: usize) -> (usize, u32) {
+        let per_word = self.per_word();
+        let shift = 64 - self.bits * (i % per_word + 1) as u32;
+        (i / per_word, shift)
+    }
+    pub fn get(&self, i: usize) -> Direction {
+        let (word, shift) = self.locate(i);
+        let code = (self.words[word] >> shift) & ((1 << self.bits) - 1);
+        DIRECTIONS[code as usize]
+    }
+    pub fn set(&mut self, i: usize, d: Direction) {
+        let (word, shift) = self.locate(i);
+        let mask = ((1 << self.bits) - 1) << shift;
+        let code = d as u64 - 1;
+        self.words[word] = (self.words[word] & !mask) | (code << shift);
+    }
+    pub fn iter(&self) -> impl Iterator<Item = Direction> + '_ {
+        (0..self.len).map(|i| self.get(i))
+    }
+    pub fn to_directions(&self) -> Vec<Direction> {
+        self.iter().collect()
+    }
+    // templateの配列にこの向きの列を付けた構造(座標はまだ求めていない)
+    pub fn to_protein(&self, template: &Protein) -> Protein {
+        let mut protein = template.clone();
+        protein.direct = self.to_directions();
+        protein
+    }
+    // 向きが異なる箇所の数。ワードごとに排他的論理和を取り、0でない区画を数える
+    pub fn distance(&self, other: &PackedMoves) -> usize {
+        // 各区画の最下位ビットだけが立ったマスク
+        let mut low = 0u64;
+        for k in 0..self.per_word() {
+            low |= 1 << (self.bits * k as u32 + 64 % self.bits);
+        }
+        self.words
+            .iter()
+            .zip(other.words.iter())
+            .map(|(&a, &b)| {
+                let x = a ^ b;
+                let mut any = x;
+                for s in 1..self.bits {
+                    any |= x >> s;
+                }
+                (any & low).count_ones() as usize
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha12Rng;
+
+    fn random_directions(rng: &mut ChaCha12Rng, len: usize, num_direct: i32) -> Vec<Direction> {
+        (0..len)
+            .map(|_| DIRECTIONS[rng.gen_range(0..num_direct as usize)])
+            .collect()
+    }
+
+    // ワードの境目(2次元は32個、3次元は21個ごと)をまたぐ長さも試す
+    #[test]
+    fn pack_and_unpack() {
+        let mut rng = ChaCha12Rng::seed_from_u64(1);
+        for num_direct in [3, 5] {
+            for len in 0..70 {
+                let direct = random_directions(&mut rng, len, num_direct);
+                let mut packed = PackedMoves::from_directions(&direct, num_direct);
+                assert_eq!(packed.len(), len);
+                assert_eq!(packed.to_directions(), direct);
+                if len > 0 {
+                    let i = rng.gen_range(0..len);
+                    let d = DIRECTIONS[rng.gen_range(0..num_direct as usize)];
+                    packed.set(i, d);
+                    let mut expected = direct.clone();
+                    expected[i] = d;
+                    assert_eq!(packed.to_directions(), expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn distance_counts_differences() {
+        let mut rng = ChaCha12Rng::seed_from_u64(2);
+        for num_direct in [3, 5] {
+            for len in [1, 20, 21, 22, 32, 33, 64, 65] {
+                let a = random_directions(&mut rng, len, num_direct);
+                let b = random_directions(&mut rng, len, num_direct);
+                let expected = a.iter().zip(b.iter()).filter(|(x, y)| x != y).count();
+                let pa = PackedMoves::from_directions(&a, num_direct);
+                let pb = PackedMoves::from_directions(&b, num_direct);
+                assert_eq!(pa.distance(&pb), expected);
+                assert_eq!(pb.distance(&pa), expected);
+                assert_eq!(pa.distance(&pa), 0);
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use crate::conformation::Point;
+use crate::error::Error;
+use crate::protein::Amino;
+use std::fs;
+
+// 隣り合うCα原子の間の距離 [Å]。格子の1目盛りをこの長さにする
+const BOND_LENGTH: f64 = 3.8;
+// 鎖に付けるチェーンID。これより鎖が多ければ最初から使い回す
+const CHAIN_IDS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+pub fn chain_id(chain: usize) -> char {
+    CHAIN_IDS.as_bytes()[chain % CHAIN_IDS.len()] as char
+}
+
+// 分子ビューアで疎水性と親水性が見分けられるように、HはLEU、PはSERとして書く
+fn residue_name(amino: Amino) -> &'static str {
+    match amino {
+        Amino::H => "LEU",
+        Amino::P => "SER",
+    }
+}
+
+// 鎖ごとの(配列, 座標)を、アミノ酸1つにつきCα原子1つのPDB形式にする
+// 鎖ごとに別のチェーンIDを付けてTERで区切り、鎖の中の結合をCONECTで書く
+pub fn to_pdb(chains: &[(&[Amino], &[Point])]) -> String {
+    let mut pdb = String::new();
+    let mut serial = 0;
+    let mut bonds = Vec::new();
+    for (c, &(aminos, pos)) in chains.iter().enumerate() {
+        let id = chain_id(c);
+        for (i, (&amino, &(x, y, z))) in aminos.iter().zip(pos.iter()).enumerate() {
+            serial += 1;
+            if i > 0 {
+                bonds.push((serial - 1, serial));
+            }
+            pdb.push_str(&format!(
+                "ATOM  {:>5}  CA  {:>3} {}{:>4}    {:>8.3}{:>8.3}{:>8.3}{:>6.2}{:>6.2}           C\n",
+                serial,
+                residue_name(amino),
+                id,
+                i + 1,
+                x as f64 * BOND_LENGTH,
+                y as f64 * BOND_LENGTH,
+                z as f64 * BOND_LENGTH,
+                1.0,
+                0.0
+            ));
+        }
+        if let Some(&amino) = aminos.last() {
+            serial += 1;
+            pdb.push_str(&format!(
+                "TER   {:>5}      {:>3} {}{:>4}\n",
+                serial,
+                residue_name(amino),
+                id,
+                aminos.len()
+            ));
+        }
+    }
+    for (a, b) in bonds {
+        pdb.push_str(&format!("CONECT{:>5}{:>5}\n", a, b));
+    }
+    pdb.push_str("END\n");
+    pdb
+}
+
+pub fn write_pdb(path: &str, chains: &[(&[Amino], &[Point])]) -> Result<(), Error> {
+    fs::write(path, to_pdb(chains)).map_err(|e| Error::io(path, e))
+}
@@ -0,0 +1,137 @@
+use crate::conformation::{above_cube, canonical_key, max_distance, value, walk, Conformation};
+use crate::error::Error;
+use serde::{Deserialize, Serialize};
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Amino {
+    H = 1,
+    P = 2,
+}
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Direction {
+    S = 1,
+    L = 2,
+    R = 3,
+    U = 4,
+    D = 5,
+}
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct AminoAcid {
+    pub amino: Amino,
+    pub pos: (i32, i32, i32),
+}
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Protein {
+    pub size: i32,
+    pub aminos: Vec<AminoAcid>,
+    pub direct: Vec<Direction>,
+    pub predict: i32,
+}
+pub fn rotate_left((x, y, z): (i32, i32, i32)) -> (i32, i32, i32) {
+    if z == 0 {
+        (y, -x, z)
+    } else {
+        (x, -z, y)
+    }
+}
+pub fn rotate_right((x, y, z): (i32, i32, i32)) -> (i32, i32, i32) {
+    if z == 0 {
+        (-y, x, z)
+    } else {
+        (x, z, -y)
+    }
+}
+pub fn rotate_up((x, y, z): (i32, i32, i32)) -> (i32, i32, i32) {
+    if y == 0 {
+        (-z, y, x)
+    } else {
+        (-y, x, z)
+    }
+}
+pub fn rotate_down((x, y, z): (i32, i32, i32)) -> (i32, i32, i32) {
+    if y == 0 {
+        (z, y, -x)
+    } else {
+        (y, -x, z)
+    }
+}
+// 1つ前の結合の向きprevからdの方向に曲がったときの結合の向き
+pub fn turn(prev: (i32, i32, i32), d: Direction) -> (i32, i32, i32) {
+    match d {
+        Direction::S => prev,
+        Direction::L => rotate_left(prev),
+        Direction::R => rotate_right(prev),
+        Direction::U => rotate_up(prev),
+        Direction::D => rotate_down(prev),
+    }
+}
+// turnの逆。prevからnextへ曲がる方向がなければNone
+pub fn relative_direction(
+    prev: (i32, i32, i32),
+    next: (i32, i32, i32),
+    num_direct: i32,
+) -> Option<Direction> {
+    let directions = [
+        Direction::S,
+        Direction::L,
+        Direction::R,
+        Direction::U,
+        Direction::D,
+    ];
+    directions[..num_direct as usize]
+        .iter()
+        .copied()
+        .find(|&d| turn(prev, d) == next)
+}
+
+impl Protein {
+    // 探索で比べる評価値。構造にならなければscoreと同じエラーを返す
+    pub fn get_value(&mut self) -> Result<i32, Error> {
+        let score = self.score()?;
+        let max_distance = self.calc_max_distance();
+        // let (above_cube, area) = self.get_above_cube();
+        // score as f32 - area / 5.0 - above_cube / 5.0
+        Ok(value(score, max_distance))
+    }
+    // 座標を求めてH-H結合の数を返す。衝突していれば最初に重なったアミノ酸を返す
+    pub fn score(&mut self) -> Result<i32, Error> {
+        Ok(self.sync()?.score())
+    }
+    // 隣接しているH同士の組(鎖上で隣り合うものは除く)を返す
+    pub fn hh_contacts(&mut self) -> Result<Vec<(usize, usize)>, Error> {
+        Ok(self.sync()?.hh_contacts())
+    }
+    // 向きの列から作った構造
+    pub fn conformation(&self) -> Result<Conformation, Error> {
+        let aminos: Vec<Amino> = self.aminos.iter().map(|a| a.amino).collect();
+        Conformation::from_moves(&aminos, &self.direct)
+    }
+    // 構造を作り、各アミノ酸のposとpredictをそれに合わせる
+    fn sync(&mut self) -> Result<Conformation, Error> {
+        match self.conformation() {
+            Ok(conformation) => {
+                for (a, &p) in self.aminos.iter_mut().zip(conformation.positions()) {
+                    a.pos = p;
+                }
+                self.predict = conformation.score();
+                Ok(conformation)
+            }
+            Err(e) => {
+                if let Error::Collision { .. } = e {
+                    self.predict = -1;
+                }
+                Err(e)
+            }
+        }
+    }
+    // 衝突していても座標を求めて正規化する
+    pub fn canonical_key(&self) -> Vec<(i32, i32, i32)> {
+        canonical_key(&walk(&self.direct, self.aminos.len()))
+    }
+    pub fn get_above_cube(&self) -> (f32, f32) {
+        let aminos: Vec<Amino> = self.aminos.iter().map(|a| a.amino).collect();
+        above_cube(&aminos, &walk(&self.direct, self.aminos.len()))
+    }
+    pub fn calc_max_distance(&self) -> f32 {
+        max_distance(&walk(&self.direct, self.aminos.len()))
+    }
+}
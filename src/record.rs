@@ -0,0 +1,96 @@
+use crate::benchmark::parse_sequence;
+use crate::config::RunConfig;
+use crate::error::Error;
+use crate::protein::{Amino, Direction, Protein};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+// 書き出した構造。配列と向きを文字列で持つので、手で書いたり他のプログラムから作ったりもできる
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FoldRecord {
+    pub sequence: String,
+    pub dim: u8,
+    pub directions: String,
+    pub score: i32,
+    // foldで書き出したときの設定
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<RunConfig>,
+}
+
+impl FoldRecord {
+    // 衝突している構造は書き出せない
+    pub fn new(protein: &mut Protein, dim: u8) -> Result<FoldRecord, Error> {
+        Ok(FoldRecord {
+            sequence: sequence_string(protein),
+            dim,
+            directions: protein.direct.iter().map(|&d| direction_char(d)).collect(),
+            score: protein.score()?,
+            config: None,
+        })
+    }
+    pub fn save(&self, path: &str) -> Result<(), Error> {
+        let text = serde_json::to_string_pretty(self)
+            .map_err(|e| Error::Serialize(format!("{}: {}", path, e)))?;
+        fs::write(path, text).map_err(|e| Error::io(path, e))
+    }
+    pub fn load(path: &str) -> Result<FoldRecord, Error> {
+        let text = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+        serde_json::from_str(&text).map_err(|e| Error::Parse(format!("{}: {}", path, e)))
+    }
+    pub fn to_protein(&self) -> Result<Protein, Error> {
+        if self.dim != 2 && self.dim != 3 {
+            return Err(Error::Parse(format!(
+                "dim must be 2 or 3, got {}",
+                self.dim
+            )));
+        }
+        build(&parse_sequence(&self.sequence)?, &self.directions, self.dim)
+    }
+}
+
+pub fn sequence_string(protein: &Protein) -> String {
+    protein
+        .aminos
+        .iter()
+        .map(|a| if a.amino == Amino::H { 'H' } else { 'P' })
+        .collect()
+}
+
+pub fn direction_char(d: Direction) -> char {
+    match d {
+        Direction::S => 'S',
+        Direction::L => 'L',
+        Direction::R => 'R',
+        Direction::U => 'U',
+        Direction::D => 'D',
+    }
+}
+
+// templateの配列にdirectionsの向きを付けた構造。衝突しているかどうかは調べない
+pub fn build(template: &Protein, directions: &str, dim: u8) -> Result<Protein, Error> {
+    let mut direct = Vec::new();
+    for c in directions.chars() {
+        direct.push(match c.to_ascii_uppercase() {
+            'S' => Direction::S,
+            'L' => Direction::L,
+            'R' => Direction::R,
+            'U' if dim == 3 => Direction::U,
+            'D' if dim == 3 => Direction::D,
+            _ => {
+                return Err(Error::InvalidMove(format!(
+                    "unexpected direction for {}D: {:?}",
+                    dim, c
+                )))
+            }
+        });
+    }
+    if direct.len() + 2 != template.aminos.len() {
+        return Err(Error::LengthMismatch {
+            residues: template.aminos.len(),
+            directions: direct.len(),
+        });
+    }
+    let mut protein = template.clone();
+    protein.direct = direct;
+    Ok(protein)
+}
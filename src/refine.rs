@@ -0,0 +1,335 @@
+use crate::protein::{relative_direction, Direction};
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+type Point = (i32, i32, i32);
+
+const STEPS: [Point; 6] = [
+    (1, 0, 0),
+    (-1, 0, 0),
+    (0, 1, 0),
+    (0, -1, 0),
+    (0, 0, 1),
+    (0, 0, -1),
+];
+
+// ビームサーチの局所的な組み替えで、窓の中の経路の候補を作る方法
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Refine {
+    // 両端を結ぶ自己回避な経路を全て試す
+    Exhaustive,
+    // 両端を結ぶ自己回避な経路からランダムにいくつか選んで試す
+    Sample,
+    // 鎖の他の部分も避けながら、両端を結ぶ経路を深さ優先で探す
+    Walk,
+}
+
+impl FromStr for Refine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "exhaustive" => Ok(Refine::Exhaustive),
+            "sample" => Ok(Refine::Sample),
+            "walk" => Ok(Refine::Walk),
+            _ => Err(format!("unknown refine: {}", s)),
+        }
+    }
+}
+
+// 原点から始まる長さwindowの自己回避な経路を、終点ごとにまとめた表
+// 窓の位置やビームのノードによらず同じなので、一度だけ作って使い回す
+pub struct PathTable {
+    pub paths: HashMap<Point, Vec<Vec<Point>>>,
+}
+
+impl PathTable {
+    pub fn new(window: usize, num_direct: i32) -> PathTable {
+        let mut paths = HashMap::new();
+        let mut path = Vec::new();
+        let mut visited = HashSet::from([(0, 0, 0)]);
+        Self::extend(
+            window,
+            num_direct,
+            (0, 0, 0),
+            &mut path,
+            &mut visited,
+            &mut paths,
+        );
+        PathTable { paths }
+    }
+    fn extend(
+        window: usize,
+        num_direct: i32,
+        pos: Point,
+        path: &mut Vec<Point>,
+        visited: &mut HashSet<Point>,
+        paths: &mut HashMap<Point, Vec<Vec<Point>>>,
+    ) {
+        if path.len() == window {
+            paths.entry(pos).or_default().push(path.clone());
+            return;
+        }
+        for &step in STEPS.iter().take(if num_direct == 5 { 6 } else { 4 }) {
+            let next = (pos.0 + step.0, pos.1 + step.1, pos.2 + step.2);
+            // 一度通った点(原点を含む)には戻らない
+            if !visited.insert(next) {
+                continue;
+            }
+            path.push(step);
+            Self::extend(window, num_direct, next, path, visited, paths);
+            path.pop();
+            visited.remove(&next);
+        }
+    }
+    pub fn get(&self, offset: Point) -> &[Vec<Point>] {
+        self.paths
+            .get(&offset)
+            .map_or(&[], |paths| paths.as_slice())
+    }
+}
+
+// start番目からの結合をpathに置き換えた向きの列。元と同じか、向きで表せない場合はNone
+pub fn replace(
+    direct: &[Direction],
+    steps: &[Point],
+    start: usize,
+    path: &[Point],
+    num_direct: i32,
+) -> Option<Vec<Direction>> {
+    if steps[start..start + path.len()] == *path {
+        return None;
+    }
+    let mut steps = steps.to_vec();
+    steps[start..start + path.len()].copy_from_slice(path);
+    let mut moved = direct.to_vec();
+    for i in start - 1..(start + path.len()).min(moved.len()) {
+        moved[i] = relative_direction(steps[i], steps[i + 1], num_direct)?;
+    }
+    Some(moved)
+}
+
+// fromからlen歩でtoに着き、occupiedを通らず、向きで表せる経路を深さ優先で全て探す
+// prevは窓の直前の結合、nextは窓の直後の結合
+pub fn walks(
+    from: Point,
+    to: Point,
+    len: usize,
+    occupied: &HashSet<Point>,
+    prev: Point,
+    next: Option<Point>,
+    num_direct: i32,
+) -> Vec<Vec<Point>> {
+    let mut walker = Walker {
+        to,
+        len,
+        occupied,
+        next,
+        num_direct,
+        path: Vec::new(),
+        visited: HashSet::new(),
+        result: Vec::new(),
+    };
+    walker.search(from, prev);
+    walker.result
+}
+
+struct Walker<'a> {
+    to: Point,
+    len: usize,
+    occupied: &'a HashSet<Point>,
+    next: Option<Point>,
+    num_direct: i32,
+    path: Vec<Point>,
+    visited: HashSet<Point>,
+    result: Vec<Vec<Point>>,
+}
+
+impl Walker<'_> {
+    fn search(&mut self, pos: Point, prev: Point) {
+        if self.path.len() == self.len {
+            let fits = self
+                .next
+                .is_none_or(|next| relative_direction(prev, next, self.num_direct).is_some());
+            if pos == self.to && fits {
+                self.result.push(self.path.clone());
+            }
+            return;
+        }
+        let left = (self.len - self.path.len()) as i32;
+        let to = self.to;
+        for &step in STEPS.iter().take(if self.num_direct == 5 { 6 } else { 4 }) {
+            let q = (pos.0 + step.0, pos.1 + step.1, pos.2 + step.2);
+            let distance = (q.0 - to.0).abs() + (q.1 - to.1).abs() + (q.2 - to.2).abs();
+            if distance > left - 1 || relative_direction(prev, step, self.num_direct).is_none() {
+                continue;
+            }
+            // 終点には最後の1歩でだけ入り、それ以外では鎖の他の部分と窓の中で通った点を避ける
+            if q == to {
+                if left > 1 {
+                    continue;
+                }
+            } else if self.occupied.contains(&q) || self.visited.contains(&q) {
+                continue;
+            }
+            self.visited.insert(q);
+            self.path.push(step);
+            self.search(q, step);
+            self.path.pop();
+            self.visited.remove(&q);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::benchmark::{parse_amino_str, protein_from_aminos};
+    use crate::conformation::Conformation;
+    use crate::moves::grow;
+    use crate::protein::Amino;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+
+    // 原点から歩いたときに通る点(原点は含まない)
+    fn visited(path: &[Point]) -> Vec<Point> {
+        let mut pos = (0, 0, 0);
+        path.iter()
+            .map(|s| {
+                pos = (pos.0 + s.0, pos.1 + s.1, pos.2 + s.2);
+                pos
+            })
+            .collect()
+    }
+
+    // 自己回避歩行の数は2次元で4, 12, 36, 100、3次元で6, 30, 150
+    #[test]
+    fn path_table_holds_every_self_avoiding_path_by_end() {
+        for (window, num_direct, count) in [(2, 3, 12), (3, 3, 36), (4, 3, 100), (3, 5, 150)] {
+            let table = PathTable::new(window, num_direct);
+            let mut total = 0;
+            for (&end, paths) in table.paths.iter() {
+                for path in paths {
+                    assert_eq!(path.len(), window);
+                    assert!(path.iter().all(|s| STEPS.contains(s)));
+                    if num_direct == 3 {
+                        assert!(path.iter().all(|s| s.2 == 0));
+                    }
+                    let points = visited(path);
+                    assert_eq!(*points.last().unwrap(), end);
+                    let distinct: HashSet<Point> = points.iter().copied().collect();
+                    assert_eq!(distinct.len(), window);
+                    assert!(!distinct.contains(&(0, 0, 0)));
+                }
+                assert_eq!(table.get(end).len(), paths.len());
+                total += paths.len();
+            }
+            assert_eq!(total, count);
+            // 窓の長さより遠い点や、偶奇の合わない点には届かない
+            assert!(table.get((window as i32 + 1, 0, 0)).is_empty());
+            assert!(table.get((window as i32 - 1, 0, 0)).is_empty());
+        }
+    }
+
+    fn conformation(aminos: &[Amino], num_direct: i32, seed: u64) -> Conformation {
+        let template = protein_from_aminos(aminos);
+        let mut rng = ChaCha12Rng::seed_from_u64(seed);
+        let protein = loop {
+            if let Some(protein) = grow(&template, &[], num_direct, &mut rng, true) {
+                break protein;
+            }
+        };
+        Conformation::from_moves(aminos, &protein.direct).unwrap()
+    }
+
+    // 表の経路で窓を置き換えると、窓の外の座標は変わらず、窓の中は経路どおりになる
+    // 衝突するのは、経路が窓の外の鎖と重なるときだけ
+    #[test]
+    fn replace_changes_only_the_window() {
+        let aminos = parse_amino_str("HPHPPHHPHPPHPHHPPHPH");
+        for (num_direct, window) in [(3, 3), (3, 4), (5, 3)] {
+            let table = PathTable::new(window, num_direct);
+            for seed in 0..5 {
+                let original = conformation(&aminos, num_direct, seed);
+                let pos = original.positions();
+                let steps = original.steps();
+                for start in 1..steps.len() - window + 1 {
+                    let from = pos[start];
+                    let to = pos[start + window];
+                    let offset = (to.0 - from.0, to.1 - from.1, to.2 - from.2);
+                    assert!(table
+                        .get(offset)
+                        .contains(&steps[start..start + window].to_vec()));
+                    for path in table.get(offset) {
+                        let Some(moved) =
+                            replace(original.moves(), &steps, start, path, num_direct)
+                        else {
+                            continue;
+                        };
+                        assert_ne!(path.as_slice(), &steps[start..start + window]);
+                        assert_eq!(moved.len(), original.moves().len());
+                        let inside: Vec<Point> = visited(path)
+                            .iter()
+                            .map(|p| (from.0 + p.0, from.1 + p.1, from.2 + p.2))
+                            .collect();
+                        match Conformation::from_moves(&aminos, &moved) {
+                            Ok(c) => {
+                                assert_eq!(c.positions()[..=start], pos[..=start]);
+                                assert_eq!(c.positions()[start + window..], pos[start + window..]);
+                                assert_eq!(c.positions()[start + 1..=start + window], inside[..]);
+                            }
+                            Err(_) => {
+                                let outside: HashSet<Point> = pos[..=start]
+                                    .iter()
+                                    .chain(pos[start + window + 1..].iter())
+                                    .copied()
+                                    .collect();
+                                assert!(inside[..window - 1].iter().any(|p| outside.contains(p)));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // 鎖の他の部分を避けて探した経路は、置き換えても衝突しない
+    #[test]
+    fn walks_give_collision_free_windows() {
+        let aminos = parse_amino_str("HPHPPHHPHPPHPHHPPHPH");
+        for (num_direct, window) in [(3, 4), (5, 3)] {
+            for seed in 0..5 {
+                let original = conformation(&aminos, num_direct, seed);
+                let pos = original.positions();
+                let steps = original.steps();
+                for start in 1..steps.len() - window + 1 {
+                    let occupied: HashSet<Point> = pos
+                        .iter()
+                        .enumerate()
+                        .filter(|&(i, _)| i <= start || i >= start + window)
+                        .map(|(_, &p)| p)
+                        .collect();
+                    let paths = walks(
+                        pos[start],
+                        pos[start + window],
+                        window,
+                        &occupied,
+                        steps[start - 1],
+                        steps.get(start + window).copied(),
+                        num_direct,
+                    );
+                    assert!(paths.contains(&steps[start..start + window].to_vec()));
+                    for path in paths {
+                        let same = path.as_slice() == &steps[start..start + window];
+                        let moved = replace(original.moves(), &steps, start, &path, num_direct);
+                        assert_eq!(moved.is_none(), same);
+                        if let Some(moved) = moved {
+                            let c = Conformation::from_moves(&aminos, &moved).unwrap();
+                            assert_eq!(c.positions()[start + window..], pos[start + window..]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,381 @@
+use crate::error::Error;
+use crate::protein::{Amino, Protein};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use std::f64::consts::FRAC_PI_2;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// 格子上の座標とそこにあるアミノ酸
+type Site = ((i32, i32, i32), Amino);
+type Contact = (usize, usize);
+
+// 3次元で描画するときの視点。角度の単位はラジアン
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    pub yaw: f64,
+    pub pitch: f64,
+    pub scale: f64,
+}
+
+impl Camera {
+    pub const FRONT: Camera = Camera {
+        yaw: 0.0,
+        pitch: 0.0,
+        scale: 1.0,
+    };
+    pub const SIDE: Camera = Camera {
+        yaw: FRAC_PI_2,
+        pitch: 0.0,
+        scale: 1.0,
+    };
+    pub const TOP: Camera = Camera {
+        yaw: 0.0,
+        pitch: FRAC_PI_2,
+        scale: 1.0,
+    };
+}
+
+impl Default for Camera {
+    // plottersのデフォルトの投影と同じ
+    fn default() -> Self {
+        Camera {
+            yaw: 0.5,
+            pitch: 0.15,
+            scale: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    Svg,
+    Gif,
+}
+
+impl ImageFormat {
+    // 拡張子から形式を決める。わからなければPNG
+    pub fn from_path(path: &str) -> ImageFormat {
+        Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| ext.parse().ok())
+            .unwrap_or(ImageFormat::Png)
+    }
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Svg => "svg",
+            ImageFormat::Gif => "gif",
+        }
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "png" => Ok(ImageFormat::Png),
+            "svg" => Ok(ImageFormat::Svg),
+            "gif" => Ok(ImageFormat::Gif),
+            _ => Err(format!("unknown image format: {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct RenderOptions {
+    pub size: (u32, u32),
+    // GIFの1フレームの時間。単位は [ms]
+    pub frame_delay: u32,
+    // Noneなら出力先の拡張子から決める
+    pub format: Option<ImageFormat>,
+    pub show_contacts: bool,
+    pub show_labels: bool,
+    pub camera: Camera,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            size: (1200, 800),
+            frame_delay: 100,
+            format: None,
+            show_contacts: true,
+            show_labels: false,
+            camera: Camera::default(),
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn format_for(&self, path: &str) -> ImageFormat {
+        self.format.unwrap_or_else(|| ImageFormat::from_path(path))
+    }
+    // 形式が指定されていて拡張子と合わない場合は、ビットマップの保存で困らないように拡張子を付け替える
+    pub fn output_path(&self, path: &str) -> PathBuf {
+        let format = self.format_for(path);
+        if ImageFormat::from_path(path) == format && Path::new(path).extension().is_some() {
+            PathBuf::from(path)
+        } else {
+            Path::new(path).with_extension(format.extension())
+        }
+    }
+}
+
+// 出力形式に合ったバックエンドで描画し、ファイルに書き出す
+// 描画の失敗には書き出し先のパスを付ける
+macro_rules! with_backend {
+    ($path:expr, $options:expr, |$area:ident| $draw:expr) => {{
+        use $crate::error::Error;
+        let path = $options.output_path($path);
+        let result = (|| -> Result<(), Error> {
+            match $options.format_for($path) {
+                ImageFormat::Svg => {
+                    let $area = SVGBackend::new(&path, $options.size).into_drawing_area();
+                    $draw?;
+                    $area.present()?;
+                }
+                ImageFormat::Png => {
+                    let $area = BitMapBackend::new(&path, $options.size).into_drawing_area();
+                    $draw?;
+                    $area.present()?;
+                }
+                ImageFormat::Gif => {
+                    let $area = BitMapBackend::gif(&path, $options.size, $options.frame_delay)
+                        .map_err(|e| Error::Render(e.to_string()))?
+                        .into_drawing_area();
+                    $draw?;
+                    $area.present()?;
+                }
+            }
+            Ok(())
+        })();
+        match result {
+            Ok(()) => Ok(path),
+            Err(Error::Render(message)) => {
+                Err(Error::Render(format!("{}: {}", path.display(), message)))
+            }
+            Err(e) => Err(e),
+        }
+    }};
+}
+pub(crate) use with_backend;
+
+// 書き出したファイルのパスを返す
+pub fn render(
+    protein: &mut Protein,
+    dim: u8,
+    path: &str,
+    options: &RenderOptions,
+) -> Result<PathBuf, Error> {
+    with_backend!(path, options, |area| draw_protein(
+        &area, protein, dim, options
+    ))
+}
+
+// 正面・側面・上面の3方向から見た図を横に並べて書き出す
+pub fn render_views(
+    protein: &mut Protein,
+    path: &str,
+    options: &RenderOptions,
+) -> Result<PathBuf, Error> {
+    with_backend!(path, options, |area| draw_views(&area, protein, options))
+}
+
+pub fn draw_protein<DB: DrawingBackend>(
+    area: &DrawingArea<DB, Shift>,
+    protein: &mut Protein,
+    dim: u8,
+    options: &RenderOptions,
+) -> Result<(), Error> {
+    area.fill(&WHITE)?;
+    let (data, contacts) = collect(protein, options)?;
+    let caption = format!("protein structure (H-H: {})", protein.predict);
+    if dim == 2 {
+        draw_2d(area, &data, &contacts, &caption, options)
+    } else {
+        draw_3d(area, &data, &contacts, &caption, options)
+    }
+}
+
+pub fn draw_views<DB: DrawingBackend>(
+    area: &DrawingArea<DB, Shift>,
+    protein: &mut Protein,
+    options: &RenderOptions,
+) -> Result<(), Error> {
+    area.fill(&WHITE)?;
+    let (data, contacts) = collect(protein, options)?;
+    let area = area.titled(
+        &format!("protein structure (H-H: {})", protein.predict),
+        ("sans-serif", 40),
+    )?;
+    let views = [
+        ("front", Camera::FRONT),
+        ("side", Camera::SIDE),
+        ("top", Camera::TOP),
+    ];
+    for (panel, (name, camera)) in area.split_evenly((1, views.len())).iter().zip(views) {
+        let camera = Camera {
+            scale: options.camera.scale,
+            ..camera
+        };
+        let options = RenderOptions { camera, ..*options };
+        draw_3d(panel, &data, &contacts, name, &options)?;
+    }
+    Ok(())
+}
+
+// 座標を計算し、描画に使う(座標, アミノ酸)の列とH-H結合の組を返す
+fn collect(
+    protein: &mut Protein,
+    options: &RenderOptions,
+) -> Result<(Vec<Site>, Vec<Contact>), Error> {
+    protein.score()?;
+    let data = protein.aminos.iter().map(|a| (a.pos, a.amino)).collect();
+    let contacts = if options.show_contacts {
+        protein.hh_contacts()?
+    } else {
+        Vec::new()
+    };
+    Ok((data, contacts))
+}
+
+fn amino_color(amino: Amino) -> RGBColor {
+    if amino == Amino::H {
+        RED
+    } else {
+        BLUE
+    }
+}
+
+// 各軸の(最小値, 最大値)を余白1つ分広げて返す
+fn bounding_box(data: &[Site]) -> [(f64, f64); 3] {
+    let mut bounds = [(f64::MAX, f64::MIN); 3];
+    for &((x, y, z), _) in data {
+        for (k, v) in [x, y, z].into_iter().enumerate() {
+            bounds[k].0 = bounds[k].0.min(v as f64 - 1.0);
+            bounds[k].1 = bounds[k].1.max(v as f64 + 1.0);
+        }
+    }
+    bounds
+}
+
+// 中心を保ったまま範囲を広げる
+fn widen((lo, hi): (f64, f64), span: f64) -> (f64, f64) {
+    let center = (lo + hi) / 2.0;
+    (center - span / 2.0, center + span / 2.0)
+}
+
+fn draw_2d<DB: DrawingBackend>(
+    area: &DrawingArea<DB, Shift>,
+    data: &[Site],
+    contacts: &[Contact],
+    caption: &str,
+    options: &RenderOptions,
+) -> Result<(), Error> {
+    let [bx, by, _] = bounding_box(data);
+    // 格子が正方形に見えるように、描画領域の縦横比に合わせて範囲を広げる
+    let (width, height) = area.dim_in_pixel();
+    let aspect = width as f64 / height as f64;
+    let span_x = (bx.1 - bx.0).max((by.1 - by.0) * aspect);
+    let span_y = span_x / aspect;
+    let (x0, x1) = widen(bx, span_x);
+    let (y0, y1) = widen(by, span_y);
+
+    let mut chart = ChartBuilder::on(area)
+        .margin(20)
+        .caption(caption, ("sans-serif", 40))
+        .build_cartesian_2d(x0..x1, y0..y1)?;
+    chart.configure_mesh().disable_mesh().draw()?;
+
+    let grid = ShapeStyle::from(&BLACK.mix(0.08)).stroke_width(1);
+    for x in x0.ceil() as i32..=x1.floor() as i32 {
+        chart.draw_series(LineSeries::new(vec![(x as f64, y0), (x as f64, y1)], grid))?;
+    }
+    for y in y0.ceil() as i32..=y1.floor() as i32 {
+        chart.draw_series(LineSeries::new(vec![(x0, y as f64), (x1, y as f64)], grid))?;
+    }
+
+    let unit = (chart.plotting_area().dim_in_pixel().0 as f64 / span_x).max(1.0);
+    let radius = (unit * 0.25).clamp(3.0, 20.0) as i32;
+
+    for &(i, j) in contacts {
+        let ((xi, yi, _), _) = data[i];
+        let ((xj, yj, _), _) = data[j];
+        chart.draw_series(DashedLineSeries::new(
+            vec![(xi as f64, yi as f64), (xj as f64, yj as f64)],
+            radius / 2 + 2,
+            radius / 2 + 2,
+            ShapeStyle::from(&RED.mix(0.6)).stroke_width(2),
+        ))?;
+    }
+    chart.draw_series(LineSeries::new(
+        data.iter().map(|&((x, y, _), _)| (x as f64, y as f64)),
+        ShapeStyle::from(&BLACK.mix(0.6)).stroke_width(3),
+    ))?;
+    chart.draw_series(data.iter().map(|&((x, y, _), a)| {
+        Circle::new((x as f64, y as f64), radius, amino_color(a).filled())
+    }))?;
+    if options.show_labels {
+        chart.draw_series(data.iter().enumerate().map(|(i, &((x, y, _), _))| {
+            EmptyElement::at((x as f64, y as f64))
+                + Text::new(format!("{}", i), (radius, -radius - 12), ("sans-serif", 14))
+        }))?;
+    }
+    Ok(())
+}
+
+fn draw_3d<DB: DrawingBackend>(
+    area: &DrawingArea<DB, Shift>,
+    data: &[Site],
+    contacts: &[Contact],
+    caption: &str,
+    options: &RenderOptions,
+) -> Result<(), Error> {
+    let bounds = bounding_box(data);
+    // 立方格子が歪まないように3軸とも同じ幅にする
+    let span = bounds.iter().map(|&(lo, hi)| hi - lo).fold(0.0, f64::max);
+    let [(x0, x1), (y0, y1), (z0, z1)] = bounds.map(|b| widen(b, span));
+
+    let mut chart = ChartBuilder::on(area)
+        .margin(20)
+        .caption(caption, ("sans-serif", 40))
+        .build_cartesian_3d(x0..x1, y0..y1, z0..z1)?;
+    chart.with_projection(|mut p| {
+        p.yaw = options.camera.yaw;
+        p.pitch = options.camera.pitch;
+        p.scale = options.camera.scale;
+        p.into_matrix()
+    });
+    chart.configure_axes().draw()?;
+
+    let to_f64 = |(x, y, z): (i32, i32, i32)| (x as f64, y as f64, z as f64);
+    for &(i, j) in contacts {
+        chart.draw_series(DashedLineSeries::new(
+            vec![to_f64(data[i].0), to_f64(data[j].0)],
+            5,
+            5,
+            ShapeStyle::from(&RED.mix(0.6)).stroke_width(2),
+        ))?;
+    }
+    chart.draw_series(LineSeries::new(
+        data.iter().map(|&(p, _)| to_f64(p)),
+        ShapeStyle::from(&BLACK.mix(0.6)).stroke_width(3),
+    ))?;
+    chart.draw_series(
+        data.iter()
+            .map(|&(p, a)| Circle::new(to_f64(p), 6, amino_color(a).filled())),
+    )?;
+    if options.show_labels {
+        chart.draw_series(data.iter().enumerate().map(|(i, &(p, _))| {
+            EmptyElement::at(to_f64(p)) + Text::new(format!("{}", i), (6, -18), ("sans-serif", 14))
+        }))?;
+    }
+    Ok(())
+}
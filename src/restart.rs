@@ -0,0 +1,133 @@
+use crate::observer::SearchObserver;
+use crate::protein::Protein;
+use crate::solve::Solution;
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashSet};
+
+// 1回のやり直しで得られた最終結果
+#[derive(Clone, Debug)]
+pub struct RestartResult {
+    pub restart: usize,
+    pub seed: u64,
+    pub best_ans: Protein,
+    pub best_score: i32,
+    // 探索アルゴリズムが示したスコアの上限
+    pub upper_bound: Option<i32>,
+}
+
+// 初期解を変えたやり直しを管理する。やり直しごとにseedから別々のシードを作る
+pub struct RestartManager {
+    restarts: usize,
+    seed: u64,
+}
+
+impl RestartManager {
+    pub fn new(restarts: usize, seed: u64) -> RestartManager {
+        RestartManager { restarts, seed }
+    }
+    // splitmix64で混ぜて、近いシードどうしでも乱数列が似ないようにする
+    pub fn seed_for(&self, restart: usize) -> u64 {
+        let mut z = self
+            .seed
+            .wrapping_add((restart as u64 + 1).wrapping_mul(0x9E3779B97F4A7C15));
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    // やり直しをスレッドプールで並列に実行する。オブザーバーはやり直しごとにmake_observerで作り、結果と一緒に返す
+    // solveには(やり直しの番号, シード, オブザーバー)を渡す
+    pub fn run_parallel<O, M, S>(&self, make_observer: M, solve: S) -> Vec<(RestartResult, O)>
+    where
+        O: SearchObserver + Send,
+        M: Fn(usize) -> O + Sync,
+        S: Fn(usize, u64, &mut O) -> Solution + Sync,
+    {
+        (0..self.restarts)
+            .into_par_iter()
+            .map(|restart| {
+                let seed = self.seed_for(restart);
+                let mut observer = make_observer(restart);
+                observer.on_restart(restart);
+                let solution = solve(restart, seed, &mut observer);
+                let result = RestartResult {
+                    restart,
+                    seed,
+                    best_ans: solution.best_ans,
+                    best_score: solution.best_score,
+                    upper_bound: solution.upper_bound,
+                };
+                (result, observer)
+            })
+            .collect()
+    }
+
+    // やり直しを順番に実行する。スレッドをまたげないオブザーバー(GIFの記録など)を使うとき用
+    pub fn run_sequential<S>(
+        &self,
+        observer: &mut dyn SearchObserver,
+        mut solve: S,
+    ) -> Vec<RestartResult>
+    where
+        S: FnMut(usize, u64, &mut dyn SearchObserver) -> Solution,
+    {
+        (0..self.restarts)
+            .map(|restart| {
+                let seed = self.seed_for(restart);
+                observer.on_restart(restart);
+                let solution = solve(restart, seed, observer);
+                RestartResult {
+                    restart,
+                    seed,
+                    best_ans: solution.best_ans,
+                    best_score: solution.best_score,
+                    upper_bound: solution.upper_bound,
+                }
+            })
+            .collect()
+    }
+}
+
+// 全てのやり直しの結果をまとめたもの
+pub struct RestartSummary {
+    pub results: Vec<RestartResult>,
+}
+
+impl RestartSummary {
+    pub fn best(&self) -> &RestartResult {
+        self.results
+            .iter()
+            .max_by_key(|result| (result.best_score, std::cmp::Reverse(result.restart)))
+            .unwrap()
+    }
+    // エネルギー(H-H結合の数にマイナスをつけたもの)ごとのやり直しの回数
+    pub fn energy_distribution(&self) -> BTreeMap<i32, usize> {
+        let mut distribution = BTreeMap::new();
+        for result in self.results.iter() {
+            *distribution.entry(-result.best_score).or_insert(0) += 1;
+        }
+        distribution
+    }
+    // ベストのスコアに達した構造のうち、回転・鏡映で重ならないものの数
+    pub fn distinct_optima(&self) -> usize {
+        let best_score = self.best().best_score;
+        let mut keys = HashSet::new();
+        for result in self.results.iter() {
+            if result.best_score == best_score {
+                keys.insert(result.best_ans.clone().canonical_key());
+            }
+        }
+        keys.len()
+    }
+    pub fn report(&self) {
+        let best = self.best();
+        println!(
+            "best: {} (restart {}, seed {})",
+            best.best_score, best.restart, best.seed
+        );
+        for (energy, count) in self.energy_distribution() {
+            println!("  energy {}: {} restarts", energy, count);
+        }
+        println!("distinct optima: {}", self.distinct_optima());
+    }
+}
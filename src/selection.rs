@@ -0,0 +1,219 @@
+use rand::Rng;
+use std::collections::HashSet;
+use std::str::FromStr;
+
+// ビームサーチで次のビームに残す候補の選び方
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Selection {
+    // 評価値の良い順にビーム幅だけ残す
+    TopK,
+    // x番目の候補をexp(-sharpness x²/w²)の確率で残す
+    Gaussian,
+    // 評価値に比例した確率で、等間隔の目盛りを使って一度に選ぶ(確率的普遍抽出)
+    Sus,
+    // すでに選んだ候補とH-H結合の組が似ている候補ほど評価値を割り引いて選ぶ
+    Diverse,
+}
+
+impl FromStr for Selection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "top-k" | "topk" => Ok(Selection::TopK),
+            "gaussian" => Ok(Selection::Gaussian),
+            "sus" => Ok(Selection::Sus),
+            "diverse" => Ok(Selection::Diverse),
+            _ => Err(format!("unknown selection: {}", s)),
+        }
+    }
+}
+
+// 以下のvaluesは評価値の良い順に並んでいるものとし、選んだ候補の番号を返す
+
+pub fn top_k(values: &[i32], width: usize) -> Vec<usize> {
+    (0..values.len().min(width)).collect()
+}
+
+pub fn gaussian<R: Rng>(values: &[i32], width: usize, sharpness: f64, rng: &mut R) -> Vec<usize> {
+    let mut chosen = Vec::new();
+    if width == 0 {
+        return chosen;
+    }
+    let mut x = 0;
+    for i in 0..values.len() {
+        let exp = (-sharpness as f32 * (x * x) as f32 / ((width * width) as f32)).exp();
+        let prob = rng.gen_range(0.0..1.0) as f32;
+        if prob < exp {
+            x += 1;
+            chosen.push(i);
+        }
+        if chosen.len() >= width {
+            break;
+        }
+        x += 1;
+    }
+    chosen
+}
+
+// 同じ候補が何度も選ばれた場合は1つにまとめるので、ビーム幅より少なくなることがある
+pub fn sus<R: Rng>(values: &[i32], width: usize, rng: &mut R) -> Vec<usize> {
+    let Some(&min) = values.iter().min() else {
+        return Vec::new();
+    };
+    if width == 0 {
+        return Vec::new();
+    }
+    // 最も悪い候補にも少しは選ばれる機会を残す
+    let fitness: Vec<f64> = values.iter().map(|&v| (v - min + 1) as f64).collect();
+    let step = fitness.iter().sum::<f64>() / width as f64;
+    let mut pointer = rng.gen_range(0.0..step);
+    let mut chosen = Vec::new();
+    let mut cumulative = 0.0;
+    for (i, f) in fitness.iter().enumerate() {
+        cumulative += f;
+        while pointer < cumulative && chosen.len() < width {
+            if chosen.last() != Some(&i) {
+                chosen.push(i);
+            }
+            pointer += step;
+        }
+    }
+    chosen
+}
+
+// 評価値からweight×10×(選んだ候補との類似度の最大値)を引いたものが最も良い候補を順に選ぶ
+// 類似度はH-H結合の組(contacts)の集合のJaccard係数。評価値はH-H結合1つで10なので、weightは結合の数の単位
+pub fn diverse(
+    values: &[i32],
+    contacts: &[HashSet<(usize, usize)>],
+    width: usize,
+    weight: f64,
+) -> Vec<usize> {
+    let similarity = |a: &HashSet<(usize, usize)>, b: &HashSet<(usize, usize)>| {
+        let union = a.union(b).count();
+        if union == 0 {
+            1.0
+        } else {
+            a.intersection(b).count() as f64 / union as f64
+        }
+    };
+    let mut nearest = vec![0.0; values.len()];
+    let mut taken = vec![false; values.len()];
+    let mut chosen = Vec::new();
+    while chosen.len() < width.min(values.len()) {
+        let mut best: Option<(f64, usize)> = None;
+        for i in 0..values.len() {
+            if taken[i] {
+                continue;
+            }
+            let penalized = values[i] as f64 - weight * 10.0 * nearest[i];
+            if best.is_none_or(|(value, _)| penalized > value) {
+                best = Some((penalized, i));
+            }
+        }
+        let (_, i) = best.unwrap();
+        taken[i] = true;
+        chosen.push(i);
+        for j in 0..values.len() {
+            if !taken[j] {
+                let s = similarity(&contacts[i], &contacts[j]);
+                if s > nearest[j] {
+                    nearest[j] = s;
+                }
+            }
+        }
+    }
+    chosen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha12Rng;
+
+    // 評価値の良い順に並べた候補
+    fn values() -> Vec<i32> {
+        vec![90, 80, 80, 70, 50, 40, 40, 30, 20, 10, 0, -10]
+    }
+
+    fn strictly_increasing(chosen: &[usize]) -> bool {
+        chosen.windows(2).all(|w| w[0] < w[1])
+    }
+
+    #[test]
+    fn top_k_takes_the_first_width_candidates() {
+        let values = values();
+        assert_eq!(top_k(&values, 3), vec![0, 1, 2]);
+        assert_eq!(top_k(&values, 100), (0..values.len()).collect::<Vec<_>>());
+        assert!(top_k(&values, 0).is_empty());
+        assert!(top_k(&[], 3).is_empty());
+    }
+
+    #[test]
+    fn gaussian_keeps_the_best_and_stays_within_width() {
+        let values = values();
+        let mut rng = ChaCha12Rng::seed_from_u64(1);
+        for width in 1..=values.len() + 2 {
+            for _ in 0..20 {
+                let chosen = gaussian(&values, width, 1.0, &mut rng);
+                // 先頭の候補はexp(0)=1の確率で必ず残る
+                assert_eq!(chosen.first(), Some(&0));
+                assert!(chosen.len() <= width);
+                assert!(strictly_increasing(&chosen));
+                assert!(chosen.iter().all(|&i| i < values.len()));
+            }
+        }
+        assert!(gaussian(&values, 0, 1.0, &mut rng).is_empty());
+    }
+
+    #[test]
+    fn sus_chooses_distinct_candidates_within_width() {
+        let values = values();
+        let mut rng = ChaCha12Rng::seed_from_u64(2);
+        for width in 1..=values.len() + 2 {
+            for _ in 0..20 {
+                let chosen = sus(&values, width, &mut rng);
+                assert!(!chosen.is_empty());
+                assert!(chosen.len() <= width);
+                assert!(strictly_increasing(&chosen));
+                assert!(chosen.iter().all(|&i| i < values.len()));
+            }
+        }
+        assert!(sus(&values, 0, &mut rng).is_empty());
+        assert!(sus(&[], 3, &mut rng).is_empty());
+        // 評価値が全て同じなら、幅が候補の数と同じとき全てが1回ずつ選ばれる
+        assert_eq!(sus(&[5; 6], 6, &mut rng), (0..6).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn sus_prefers_better_candidates() {
+        let values = vec![100, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut rng = ChaCha12Rng::seed_from_u64(3);
+        let picked = (0..200)
+            .filter(|_| sus(&values, 2, &mut rng).contains(&0))
+            .count();
+        // 1番目の候補は目盛りの幅より大きいので必ず選ばれる
+        assert_eq!(picked, 200);
+    }
+
+    #[test]
+    fn diverse_skips_candidates_similar_to_chosen_ones() {
+        let values = vec![30, 29, 28, 10];
+        let contacts: Vec<HashSet<(usize, usize)>> = vec![
+            [(0, 3), (2, 5)].into_iter().collect(),
+            [(0, 3), (2, 5)].into_iter().collect(),
+            [(0, 3), (1, 4)].into_iter().collect(),
+            [(6, 9)].into_iter().collect(),
+        ];
+        // 重みが0なら評価値の順
+        assert_eq!(diverse(&values, &contacts, 4, 0.0), vec![0, 1, 2, 3]);
+        // 1番目と同じ結合の組を持つ候補は後回しになり、共通する結合の少ない候補が先に選ばれる
+        assert_eq!(diverse(&values, &contacts, 3, 1.0), vec![0, 2, 1]);
+        // 重みが大きければ、結合を共有しない候補が評価値の差を覆して選ばれる
+        assert_eq!(diverse(&values, &contacts, 2, 10.0), vec![0, 3]);
+        assert_eq!(diverse(&values, &contacts, 10, 1.0).len(), values.len());
+        assert!(diverse(&values, &contacts, 0, 1.0).is_empty());
+    }
+}
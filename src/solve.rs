@@ -0,0 +1,311 @@
+use crate::anneal::Annealing;
+use crate::beam::Beam;
+use crate::budget::Budget;
+use crate::checkpoint::SolverState;
+use crate::genetic::Genetic;
+use crate::hcore::HCore;
+use crate::init::Init;
+use crate::mcts::{Mcts, Rollout};
+use crate::observer::SearchObserver;
+use crate::protein::Protein;
+use crate::refine::Refine;
+use crate::restart::{RestartManager, RestartSummary};
+use crate::selection::Selection;
+use crate::tabu::Tabu;
+use std::str::FromStr;
+
+// 探索アルゴリズム
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Solver {
+    Beam,
+    Anneal,
+    Genetic,
+    Tabu,
+    Mcts,
+    // H-コアに配列を通す厳密解法
+    Hcore,
+}
+
+impl FromStr for Solver {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "beam" => Ok(Solver::Beam),
+            "anneal" => Ok(Solver::Anneal),
+            "genetic" => Ok(Solver::Genetic),
+            "tabu" => Ok(Solver::Tabu),
+            "mcts" => Ok(Solver::Mcts),
+            "hcore" => Ok(Solver::Hcore),
+            _ => Err(format!("unknown solver: {}", s)),
+        }
+    }
+}
+
+impl Solver {
+    // 乱数を使わず、やり直しても同じ結果になる
+    pub fn is_deterministic(&self) -> bool {
+        *self == Solver::Hcore
+    }
+}
+
+// 探索アルゴリズムのパラメータ。既定値はコマンドラインの既定値と同じ
+#[derive(Clone, Debug)]
+pub struct SolverSettings {
+    // 反復回数。Noneなら探索アルゴリズムごとの既定値(ビームサーチは10回、焼きなまし法は100000回、
+    // 遺伝的アルゴリズムは200世代、タブーサーチは2000回)
+    pub iterations: Option<usize>,
+    // 最初の解の作り方。(Some(探索アルゴリズム), _)はその探索アルゴリズムだけに使う
+    pub init: Vec<(Option<Solver>, Init)>,
+    // 焼きなまし法の温度
+    pub temperature: f64,
+    // ビームサーチ
+    pub beam_width: usize,
+    pub selection: Selection,
+    pub sharpness: f64,
+    pub diversity_weight: f64,
+    pub window: usize,
+    pub stride: usize,
+    pub refine: Refine,
+    pub refine_samples: usize,
+    // 遺伝的アルゴリズム
+    pub population: usize,
+    pub crossover_rate: f64,
+    pub mutation_rate: f64,
+    pub tournament_size: usize,
+    pub elitism: usize,
+    pub niche_radius: f64,
+    pub crossover_attempts: usize,
+    // タブーサーチ
+    pub tabu_tenure: usize,
+    pub stagnation: usize,
+    pub perturbation: usize,
+    // MCTS
+    pub playouts: usize,
+    pub exploration: f64,
+    pub rollout: Rollout,
+    // H-コア解法
+    pub max_cores: usize,
+    pub max_nodes: u64,
+    pub core_levels: usize,
+}
+
+impl Default for SolverSettings {
+    fn default() -> Self {
+        SolverSettings {
+            iterations: None,
+            init: Vec::new(),
+            temperature: 5.0,
+            beam_width: 200,
+            selection: Selection::Gaussian,
+            sharpness: 8.0,
+            diversity_weight: 1.0,
+            window: 5,
+            stride: 3,
+            refine: Refine::Exhaustive,
+            refine_samples: 20,
+            population: 100,
+            crossover_rate: 0.9,
+            mutation_rate: 0.3,
+            tournament_size: 3,
+            elitism: 2,
+            niche_radius: 0.1,
+            crossover_attempts: 10,
+            tabu_tenure: 50,
+            stagnation: 50,
+            perturbation: 5,
+            playouts: 200,
+            exploration: 0.5,
+            rollout: Rollout::Greedy,
+            max_cores: 10000,
+            max_nodes: 1_000_000,
+            core_levels: 4,
+        }
+    }
+}
+
+impl SolverSettings {
+    // 探索アルゴリズムごとの指定があればそれを、なければ全体の指定を使う
+    pub fn init_for(&self, solver: Solver) -> Init {
+        let specific = self.init.iter().rev().find(|(s, _)| *s == Some(solver));
+        let general = self.init.iter().rev().find(|(s, _)| s.is_none());
+        specific.or(general).map_or(Init::Random, |&(_, init)| init)
+    }
+}
+
+// 1回の探索の結果
+#[derive(Clone, Debug)]
+pub struct Solution {
+    pub best_ans: Protein,
+    pub best_score: i32,
+    // 探索アルゴリズムが示したスコアの上限(hcoreのみ)。best_scoreと等しければ最適解
+    pub upper_bound: Option<i32>,
+}
+
+// solverで1回探索する。resumeがあればその状態から続ける
+#[allow(clippy::too_many_arguments)]
+pub fn solve(
+    solver: Solver,
+    protein: &Protein,
+    settings: &SolverSettings,
+    dim: u8,
+    seed: u64,
+    budget: &Budget,
+    resume: Option<SolverState>,
+    observer: &mut dyn SearchObserver,
+) -> Solution {
+    let num_direct = if dim == 2 { 3 } else { 5 };
+    let budget = budget.clone();
+    let (best_ans, best_score) = match solver {
+        Solver::Beam => {
+            let mut beam = Beam::new(protein, num_direct, settings, seed, budget);
+            if let Some(SolverState::Beam(state)) = resume {
+                beam.restore(state);
+            }
+            beam.run(settings.iterations.unwrap_or(10), observer);
+            (beam.best_ans().clone(), beam.best_score())
+        }
+        Solver::Anneal => {
+            let mut annealing = Annealing::new(protein, num_direct, settings, seed, budget);
+            if let Some(SolverState::Anneal(state)) = resume {
+                annealing.restore(state);
+            }
+            annealing.run(observer);
+            (annealing.best_ans().clone(), annealing.best_score())
+        }
+        Solver::Genetic => {
+            let mut genetic = Genetic::new(protein, num_direct, settings, seed, budget);
+            if let Some(SolverState::Genetic(state)) = resume {
+                genetic.restore(state);
+            }
+            genetic.run(settings.iterations.unwrap_or(200), observer);
+            (genetic.best_ans().clone(), genetic.best_score())
+        }
+        Solver::Tabu => {
+            let mut tabu = Tabu::new(protein, num_direct, settings, seed, budget);
+            if let Some(SolverState::Tabu(state)) = resume {
+                tabu.restore(state);
+            }
+            tabu.run(observer);
+            (tabu.best_ans().clone(), tabu.best_score())
+        }
+        Solver::Mcts => {
+            let mut mcts = Mcts::new(protein, num_direct, settings, seed, budget);
+            if let Some(SolverState::Mcts(state)) = resume {
+                mcts.restore(state);
+            }
+            mcts.run(observer);
+            (mcts.best_ans().clone(), mcts.best_score())
+        }
+        Solver::Hcore => {
+            // 乱数を使わず、途中の状態も保存しないので、シードと再開は関係ない
+            let mut hcore = HCore::new(protein, num_direct, settings, budget);
+            hcore.run(observer);
+            return Solution {
+                best_ans: hcore.best_ans().clone(),
+                best_score: hcore.best_score(),
+                upper_bound: hcore.upper_bound(),
+            };
+        }
+    };
+    Solution {
+        best_ans,
+        best_score,
+        upper_bound: None,
+    }
+}
+
+// 1つの配列を1つの探索アルゴリズムで、初期解を変えてやり直しながら解く
+pub struct Folding<'a> {
+    solver: Solver,
+    protein: &'a Protein,
+    settings: &'a SolverSettings,
+    dim: u8,
+    budget: &'a Budget,
+    manager: RestartManager,
+}
+
+impl<'a> Folding<'a> {
+    // 決定的な探索アルゴリズムはやり直しても同じ結果になるので、restartsによらず1回だけ解く
+    pub fn new(
+        solver: Solver,
+        protein: &'a Protein,
+        settings: &'a SolverSettings,
+        dim: u8,
+        budget: &'a Budget,
+        restarts: usize,
+        seed: u64,
+    ) -> Folding<'a> {
+        let restarts = if solver.is_deterministic() {
+            1
+        } else {
+            restarts
+        };
+        Folding {
+            solver,
+            protein,
+            settings,
+            dim,
+            budget,
+            manager: RestartManager::new(restarts, seed),
+        }
+    }
+    fn solve(
+        &self,
+        seed: u64,
+        resume: Option<SolverState>,
+        observer: &mut dyn SearchObserver,
+    ) -> Solution {
+        solve(
+            self.solver,
+            self.protein,
+            self.settings,
+            self.dim,
+            seed,
+            self.budget,
+            resume,
+            observer,
+        )
+    }
+    // やり直しを並列に実行する。オブザーバーはやり直しごとにmake_observerで作り、最後に返す
+    // resumeはやり直しの番号から、再開する状態があればそれを返す
+    pub fn run_parallel<O, M, R>(&self, make_observer: M, resume: R) -> (RestartSummary, Vec<O>)
+    where
+        O: SearchObserver + Send,
+        M: Fn(usize) -> O + Sync,
+        R: Fn(usize) -> Option<SolverState> + Sync,
+    {
+        let (results, observers) = self
+            .manager
+            .run_parallel(make_observer, |restart, seed, observer| {
+                self.solve(seed, resume(restart), observer)
+            })
+            .into_iter()
+            .unzip();
+        (RestartSummary { results }, observers)
+    }
+    // やり直しを順番に実行する。スレッドをまたげないオブザーバー(GIFの記録など)を使うとき用
+    // 全てのやり直しにobserverを、やり直しごとにmake_observerで作ったものを一緒に使う
+    pub fn run_sequential<O, M, R>(
+        &self,
+        observer: &mut dyn SearchObserver,
+        mut make_observer: M,
+        resume: R,
+    ) -> (RestartSummary, Vec<O>)
+    where
+        O: SearchObserver,
+        M: FnMut(usize) -> O,
+        R: Fn(usize) -> Option<SolverState>,
+    {
+        let mut observers = Vec::new();
+        let results = self
+            .manager
+            .run_sequential(observer, |restart, seed, observer| {
+                let mut own = make_observer(restart);
+                let solution = self.solve(seed, resume(restart), &mut (observer, &mut own));
+                observers.push(own);
+                solution
+            });
+        (RestartSummary { results }, observers)
+    }
+}
@@ -0,0 +1,252 @@
+use crate::budget::Budget;
+use crate::checkpoint::{RngState, SolverState, TabuState};
+use crate::conformation::{evaluate, Conformation};
+use crate::init::Init;
+use crate::moves::{directions, neighbourhood, Move};
+use crate::observer::{Progress, SearchObserver};
+use crate::packed::PackedMoves;
+use crate::protein::{Amino, Protein};
+use crate::solve::{Solver, SolverSettings};
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use rayon::prelude::*;
+use std::collections::{HashSet, VecDeque};
+
+// on_progressとon_checkpointを呼ぶ間隔
+const REPORT_INTERVAL: usize = 10;
+
+// (評価値, スコア, 候補)。候補は向きの列だけを詰めて持ち、選んだものだけ座標を求める
+type Candidate = (i32, i32, PackedMoves);
+
+pub struct Tabu {
+    max_iter: usize,
+    // 一度離れた解に戻ることを禁止しておく反復の回数
+    tenure: usize,
+    // ベストがこの回数更新されなければ解をかき混ぜる
+    stagnation: usize,
+    // かき混ぜるときにランダムに変える向きの数
+    perturbation: usize,
+    now_ans: Protein,
+    now_score: i32,
+    best_ans: Protein,
+    best_score: i32,
+    num_direct: i32,
+    evaluations: u64,
+    rng: ChaCha12Rng,
+    // 終わった反復の回数
+    iteration: usize,
+    budget: Budget,
+    // 最近tenure回の反復で離れた解。ここに戻ることを禁止する
+    tabu: HashSet<PackedMoves>,
+    // tabuに入れた順。古いものから禁止を解く
+    recent: VecDeque<PackedMoves>,
+    // 最後にベストを更新した(またはかき混ぜた)反復
+    last_improvement: usize,
+    // 最初の解の作り方
+    init: Init,
+}
+
+impl Tabu {
+    pub fn new(
+        template: &Protein,
+        num_direct: i32,
+        settings: &SolverSettings,
+        seed: u64,
+        budget: Budget,
+    ) -> Tabu {
+        Tabu {
+            max_iter: settings.iterations.unwrap_or(2000),
+            tenure: settings.tabu_tenure,
+            stagnation: settings.stagnation,
+            perturbation: settings.perturbation,
+            now_ans: template.clone(),
+            now_score: 0,
+            best_ans: template.clone(),
+            best_score: 0,
+            num_direct,
+            evaluations: 0,
+            rng: ChaCha12Rng::seed_from_u64(seed),
+            iteration: 0,
+            budget,
+            tabu: HashSet::new(),
+            recent: VecDeque::new(),
+            last_improvement: 0,
+            init: settings.init_for(Solver::Tabu),
+        }
+    }
+    pub fn best_ans(&self) -> &Protein {
+        &self.best_ans
+    }
+    pub fn best_score(&self) -> i32 {
+        self.best_score
+    }
+    pub fn evaluations(&self) -> u64 {
+        self.evaluations
+    }
+    pub fn first_step(&mut self) {
+        let mut protein = self
+            .init
+            .build(&self.now_ans, self.num_direct, &mut self.rng);
+        // 初期解は自己回避歩行なので衝突しない
+        self.now_score = protein.score().expect("initial walks are self-avoiding");
+        self.evaluations += 1;
+        self.now_ans = protein.clone();
+        self.best_ans = protein;
+        self.best_score = self.now_score;
+        self.clear_tabu();
+    }
+    fn clear_tabu(&mut self) {
+        self.tabu.clear();
+        self.recent.clear();
+    }
+    // 離れる解を禁止し、tenure回より前に禁止したものは解く
+    fn push_tabu(&mut self, left: PackedMoves) {
+        if self.tabu.insert(left.clone()) {
+            self.recent.push_back(left);
+        }
+        while self.recent.len() > self.tenure {
+            if let Some(old) = self.recent.pop_front() {
+                self.tabu.remove(&old);
+            }
+        }
+    }
+    // 近傍の中で禁止されていない最も良い解に移る。禁止されていてもベストを更新するなら移る
+    pub fn one_step(&mut self, iteration: usize, observer: &mut dyn SearchObserver) {
+        let moves = neighbourhood(&self.now_ans, self.num_direct);
+        let aminos: Vec<Amino> = self.now_ans.aminos.iter().map(|a| a.amino).collect();
+        let now = PackedMoves::from_directions(&self.now_ans.direct, self.num_direct);
+        let num_direct = self.num_direct;
+        // 候補の評価は並列に行い、選ぶのは列挙した順に行うことでスレッド数によらず同じ結果にする
+        let candidates: Vec<Option<Candidate>> = moves
+            .par_iter()
+            .map(|m| {
+                let candidate = m.apply_packed(&now, num_direct)?;
+                let (value, score) = evaluate(&aminos, &candidate.to_directions())?;
+                Some((value, score, candidate))
+            })
+            .collect();
+        self.evaluations += moves.len() as u64;
+
+        let mut chosen: Option<Candidate> = None;
+        for (value, score, candidate) in candidates.into_iter().flatten() {
+            if self.tabu.contains(&candidate) && score <= self.best_score {
+                continue;
+            }
+            if chosen.as_ref().is_none_or(|c| value > c.0) {
+                chosen = Some((value, score, candidate));
+            }
+        }
+        if let Some((_, score, moves)) = chosen {
+            let Ok(candidate) = Conformation::from_moves(&aminos, &moves.to_directions()) else {
+                return;
+            };
+            let candidate = candidate.to_protein();
+            self.push_tabu(now);
+            observer.on_candidate_accepted(&candidate, score);
+            if self.best_score < score {
+                self.best_score = score;
+                self.best_ans = candidate.clone();
+                self.last_improvement = iteration;
+                observer.on_new_best(&candidate, score);
+            }
+            self.now_ans = candidate;
+            self.now_score = score;
+        }
+        if iteration - self.last_improvement >= self.stagnation {
+            self.diversify();
+            self.last_improvement = iteration;
+        }
+    }
+    // 行き詰まったときに、ランダムな位置の向きを変えて別の領域に移り、禁止リストを空にする
+    pub fn diversify(&mut self) {
+        let directions = directions(self.num_direct);
+        let len = self.now_ans.direct.len();
+        // 向きが1つもなければかき混ぜようがない
+        if len == 0 {
+            self.clear_tabu();
+            return;
+        }
+        let mut changed = 0;
+        for _ in 0..self.perturbation * 10 {
+            if changed >= self.perturbation {
+                break;
+            }
+            let pivot = Move::Pivot {
+                index: self.rng.gen_range(0..len),
+                direction: *directions.choose(&mut self.rng).unwrap(),
+            };
+            let Some(mut moved) = pivot.apply(&self.now_ans, self.num_direct) else {
+                continue;
+            };
+            self.evaluations += 1;
+            if let Ok(score) = moved.score() {
+                self.now_ans = moved;
+                self.now_score = score;
+                changed += 1;
+            }
+        }
+        self.clear_tabu();
+    }
+    pub fn run(&mut self, observer: &mut dyn SearchObserver) {
+        if self.evaluations == 0 {
+            observer.on_step_start(0, "first step");
+            self.first_step();
+            observer.on_step_end(0, "first step", self.best_score);
+        }
+        for i in self.iteration..self.max_iter {
+            if self.budget.is_exhausted(self.evaluations, self.best_score) {
+                if i == 0 || i % REPORT_INTERVAL != 0 {
+                    observer.on_step_end(i, "tabu", self.best_score);
+                    observer.on_progress(&self.progress(i));
+                }
+                break;
+            }
+            self.one_step(i, observer);
+            self.iteration = i + 1;
+            if (i + 1) % REPORT_INTERVAL == 0 || i + 1 == self.max_iter {
+                observer.on_step_end(i + 1, "tabu", self.best_score);
+                observer.on_progress(&self.progress(i + 1));
+                observer.on_checkpoint(&self.state());
+            }
+        }
+        observer.on_checkpoint(&self.state());
+        observer.on_finished(&self.best_ans, self.best_score);
+    }
+    pub fn progress(&self, iteration: usize) -> Progress {
+        Progress {
+            iteration,
+            evaluations: self.evaluations,
+            best_score: self.best_score,
+            current_score: self.now_score,
+            diversity: 0.0,
+        }
+    }
+    pub fn state(&self) -> SolverState {
+        SolverState::Tabu(TabuState {
+            iteration: self.iteration,
+            now_ans: self.now_ans.clone(),
+            now_score: self.now_score,
+            best_ans: self.best_ans.clone(),
+            best_score: self.best_score,
+            evaluations: self.evaluations,
+            rng: RngState::new(&self.rng),
+            tabu: self.recent.iter().cloned().collect(),
+            last_improvement: self.last_improvement,
+        })
+    }
+    pub fn restore(&mut self, state: TabuState) {
+        self.iteration = state.iteration;
+        self.now_ans = state.now_ans;
+        self.now_score = state.now_score;
+        self.best_ans = state.best_ans;
+        self.best_score = state.best_score;
+        self.evaluations = state.evaluations;
+        self.rng = state.rng.to_rng();
+        self.clear_tabu();
+        for moves in state.tabu {
+            self.push_tabu(moves);
+        }
+        self.last_improvement = state.last_improvement;
+    }
+}
@@ -0,0 +1,165 @@
+use crate::error::Error;
+use crate::observer::{Progress, SearchObserver};
+use crate::render::{with_backend, ImageFormat, RenderOptions};
+use plotters::prelude::*;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+
+// エネルギーはH-H結合の数にマイナスをつけたもの
+#[derive(Clone, Copy, Debug)]
+pub struct TrajectoryPoint {
+    pub iteration: usize,
+    pub evaluations: u64,
+    // 探索を始めてからの経過時間 [s]
+    pub wall_time: f64,
+    pub best_energy: i32,
+    pub current_energy: i32,
+    pub diversity: f64,
+}
+
+// on_progressのたびに探索の状態を記録していく
+pub struct Trajectory {
+    pub label: String,
+    pub points: Vec<TrajectoryPoint>,
+    start: Instant,
+}
+
+impl Trajectory {
+    pub fn new(label: &str) -> Trajectory {
+        Trajectory {
+            label: label.to_string(),
+            points: Vec::new(),
+            start: Instant::now(),
+        }
+    }
+}
+
+// 複数の探索の記録をsolver列で区別して1つのCSVに書き出す
+pub fn write_csv(trajectories: &[&Trajectory], path: &str) -> Result<(), Error> {
+    let error = |e| Error::io(path, e);
+    let mut writer = BufWriter::new(File::create(path).map_err(error)?);
+    writeln!(
+        writer,
+        "solver,iteration,evaluations,wall_time,best_energy,current_energy,diversity"
+    )
+    .map_err(error)?;
+    for trajectory in trajectories {
+        for p in trajectory.points.iter() {
+            writeln!(
+                writer,
+                "{},{},{},{:.3},{},{},{:.4}",
+                trajectory.label,
+                p.iteration,
+                p.evaluations,
+                p.wall_time,
+                p.best_energy,
+                p.current_energy,
+                p.diversity
+            )
+            .map_err(error)?;
+        }
+    }
+    writer.flush().map_err(error)
+}
+
+impl SearchObserver for Trajectory {
+    fn on_progress(&mut self, progress: &Progress) {
+        self.points.push(TrajectoryPoint {
+            iteration: progress.iteration,
+            evaluations: progress.evaluations,
+            wall_time: self.start.elapsed().as_secs_f64(),
+            best_energy: -progress.best_score,
+            current_energy: -progress.current_score,
+            diversity: progress.diversity,
+        });
+    }
+}
+
+// 評価回数を横軸にして、各探索のベストのエネルギー(実線)と現在のエネルギー(薄い線)を描く
+pub fn plot_convergence(
+    trajectories: &[&Trajectory],
+    optimum: Option<i32>,
+    path: &str,
+    options: &RenderOptions,
+) -> Result<PathBuf, Error> {
+    with_backend!(path, options, |area| draw_convergence(
+        &area,
+        trajectories,
+        optimum
+    ))
+}
+
+fn draw_convergence<DB: DrawingBackend>(
+    area: &DrawingArea<DB, plotters::coord::Shift>,
+    trajectories: &[&Trajectory],
+    optimum: Option<i32>,
+) -> Result<(), Error> {
+    area.fill(&WHITE)?;
+    let points = trajectories.iter().flat_map(|t| t.points.iter());
+    let max_evaluations = points.clone().map(|p| p.evaluations).max().unwrap_or(0) + 1;
+    let mut min_energy = points
+        .clone()
+        .map(|p| p.best_energy.min(p.current_energy))
+        .min()
+        .unwrap_or(0);
+    let max_energy = points
+        .map(|p| p.best_energy.max(p.current_energy))
+        .max()
+        .unwrap_or(0)
+        .max(0);
+    if let Some(optimum) = optimum {
+        min_energy = min_energy.min(-optimum);
+    }
+
+    let mut chart = ChartBuilder::on(area)
+        .margin(20)
+        .caption("convergence", ("sans-serif", 40))
+        .x_label_area_size(50)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0..max_evaluations, min_energy - 1..max_energy + 1)?;
+    chart
+        .configure_mesh()
+        .x_desc("evaluations")
+        .y_desc("energy")
+        .draw()?;
+
+    if let Some(optimum) = optimum {
+        chart
+            .draw_series(DashedLineSeries::new(
+                vec![(0, -optimum), (max_evaluations, -optimum)],
+                8,
+                6,
+                BLACK.stroke_width(1),
+            ))?
+            .label("known optimum")
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLACK));
+    }
+    for (i, trajectory) in trajectories.iter().enumerate() {
+        let color = Palette99::pick(i).to_rgba();
+        chart.draw_series(LineSeries::new(
+            trajectory
+                .points
+                .iter()
+                .map(|p| (p.evaluations, p.current_energy)),
+            color.mix(0.3),
+        ))?;
+        chart
+            .draw_series(LineSeries::new(
+                trajectory
+                    .points
+                    .iter()
+                    .map(|p| (p.evaluations, p.best_energy)),
+                color.stroke_width(2),
+            ))?
+            .label(trajectory.label.as_str())
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+    }
+    chart
+        .configure_series_labels()
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+    Ok(())
+}
@@ -0,0 +1,365 @@
+use crate::benchmark::{find_benchmark, load_protein, parse_sequence, protein_from_aminos};
+use crate::conformation::walk;
+use crate::error::Error;
+use crate::protein::{Amino, Protein};
+use crate::record::{build, FoldRecord};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+
+pub use crate::conformation::Point;
+
+// 座標で与えた構造の不正な箇所
+#[derive(Clone, Debug, PartialEq)]
+pub enum Defect {
+    // 同じ格子点に置かれたアミノ酸の番号(2つ以上)
+    Collision { residues: Vec<usize>, pos: Point },
+    // bond番目とbond+1番目のアミノ酸が隣り合っていない
+    Broken { bond: usize, from: Point, to: Point },
+}
+
+impl fmt::Display for Defect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Defect::Collision { residues, pos } => {
+                let residues: Vec<String> = residues.iter().map(|i| i.to_string()).collect();
+                write!(f, "residues {} collide at {:?}", residues.join(", "), pos)
+            }
+            Defect::Broken { bond, from, to } => {
+                let distance =
+                    (to.0 - from.0).abs() + (to.1 - from.1).abs() + (to.2 - from.2).abs();
+                write!(
+                    f,
+                    "bond {}-{} is broken: {:?} and {:?} are {} apart",
+                    bond,
+                    bond + 1,
+                    from,
+                    to,
+                    distance
+                )
+            }
+        }
+    }
+}
+
+// 鎖のつながりと自己回避を調べ、不正な箇所を全て返す
+pub fn defects(pos: &[Point]) -> Vec<Defect> {
+    let mut result = Vec::new();
+    for (bond, w) in pos.windows(2).enumerate() {
+        let (from, to) = (w[0], w[1]);
+        if (to.0 - from.0).abs() + (to.1 - from.1).abs() + (to.2 - from.2).abs() != 1 {
+            result.push(Defect::Broken { bond, from, to });
+        }
+    }
+    let mut residues: BTreeMap<Point, Vec<usize>> = BTreeMap::new();
+    for (i, &p) in pos.iter().enumerate() {
+        residues.entry(p).or_default().push(i);
+    }
+    let mut collisions: Vec<Defect> = residues
+        .into_iter()
+        .filter(|(_, residues)| residues.len() > 1)
+        .map(|(pos, residues)| Defect::Collision { residues, pos })
+        .collect();
+    // 鎖の前の方で起きた衝突から表示する
+    collisions.sort_by_key(|d| match d {
+        Defect::Collision { residues, .. } => residues[1],
+        Defect::Broken { .. } => 0,
+    });
+    result.extend(collisions);
+    result
+}
+
+// 絶対的な向きの列(R、L、U、Dは±x、±y、3次元ではF、Bが±z)から座標を求める
+pub fn absolute_positions(moves: &str, dim: u8) -> Result<Vec<Point>, Error> {
+    let mut pos = vec![(0, 0, 0)];
+    for (i, c) in moves.chars().enumerate() {
+        let step = match c.to_ascii_uppercase() {
+            'R' => (1, 0, 0),
+            'L' => (-1, 0, 0),
+            'U' => (0, 1, 0),
+            'D' => (0, -1, 0),
+            'F' if dim == 3 => (0, 0, 1),
+            'B' if dim == 3 => (0, 0, -1),
+            _ => {
+                return Err(Error::InvalidMove(format!(
+                    "unexpected absolute direction for {}D at bond {}: {:?}",
+                    dim, i, c
+                )))
+            }
+        };
+        let last = pos[pos.len() - 1];
+        pos.push((last.0 + step.0, last.1 + step.1, last.2 + step.2));
+    }
+    Ok(pos)
+}
+
+// 1行に1つのアミノ酸の座標を「x y」か「x y z」で書いたファイル。区切りは空白かカンマで、#以降は無視する
+// 行の先頭にHかPを書けば配列も読み、全ての行に書いてあればそれを返す
+pub fn load_coordinates(path: &str, dim: u8) -> Result<(Option<Vec<Amino>>, Vec<Point>), Error> {
+    let text = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+    let mut aminos = Vec::new();
+    let mut pos = Vec::new();
+    for (line_no, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error =
+            |message: String| Error::Parse(format!("{}:{}: {}", path, line_no + 1, message));
+        let mut fields: Vec<&str> = line
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|f| !f.is_empty())
+            .collect();
+        let Some(first) = fields.first() else {
+            return Err(error("no coordinates".to_string()));
+        };
+        match first.to_ascii_uppercase().as_str() {
+            "H" => aminos.push(Amino::H),
+            "P" => aminos.push(Amino::P),
+            _ => {}
+        }
+        if aminos.len() > pos.len() {
+            fields.remove(0);
+        }
+        let values: Vec<i32> = fields
+            .iter()
+            .map(|f| {
+                f.parse()
+                    .map_err(|_| error(format!("not an integer: {:?}", f)))
+            })
+            .collect::<Result<_, _>>()?;
+        let p = match values[..] {
+            [x, y] => (x, y, 0),
+            [x, y, z] => (x, y, z),
+            _ => {
+                return Err(error(format!(
+                    "expected 2 or 3 coordinates, got {}",
+                    values.len()
+                )))
+            }
+        };
+        if dim == 2 && p.2 != 0 {
+            return Err(error(format!(
+                "residue {} is not on the 2D lattice (z = {}); use --dim 3",
+                pos.len(),
+                p.2
+            )));
+        }
+        pos.push(p);
+    }
+    if !aminos.is_empty() && aminos.len() != pos.len() {
+        return Err(Error::Parse(format!(
+            "{}: H or P is given for only {} of {} residues",
+            path,
+            aminos.len(),
+            pos.len()
+        )));
+    }
+    Ok(((!aminos.is_empty()).then_some(aminos), pos))
+}
+
+// 構造の読み込み元
+pub enum Source<'a> {
+    // fold --saveなどで書き出した構造のJSONファイル
+    Record(&'a str),
+    // 1行に1つのアミノ酸の座標を書いたファイル
+    Coordinates(&'a str),
+    // 3つ目のアミノ酸からの相対的な向きの列
+    Directions(&'a str),
+    // 最初の結合からの絶対的な向きの列
+    Absolute(&'a str),
+}
+
+// 読み込んだ構造
+pub struct Loaded {
+    pub template: Protein,
+    pub pos: Vec<Point>,
+    pub dim: u8,
+    // 組み込みのベンチマークと同じ配列ならその番号
+    pub id: Option<usize>,
+}
+
+// 配列はsequenceかid番目のベンチマークから読む。座標のファイルにHとPが書いてあればそれを使う
+// dimはJSONファイルならそこに書いた次元を使う
+// 鎖が切れていたり衝突していたりする場合は、その箇所を全て持ったError::Defectsを返す
+pub fn load_conformation(
+    source: &Source,
+    sequence: Option<&str>,
+    id: usize,
+    dim: u8,
+) -> Result<Loaded, Error> {
+    let (template, pos, dim) = match *source {
+        Source::Record(path) => {
+            let record = FoldRecord::load(path)?;
+            let protein = record.to_protein()?;
+            let pos = walk(&protein.direct, protein.aminos.len());
+            (protein, pos, record.dim)
+        }
+        Source::Coordinates(path) => {
+            let (aminos, pos) = load_coordinates(path, dim)?;
+            let template = match (aminos, sequence) {
+                (Some(aminos), Some(sequence)) => {
+                    let template = parse_sequence(sequence)?;
+                    if template
+                        .aminos
+                        .iter()
+                        .map(|a| a.amino)
+                        .ne(aminos.iter().copied())
+                    {
+                        return Err(Error::Parse(format!(
+                            "{}: H and P differ from --sequence",
+                            path
+                        )));
+                    }
+                    template
+                }
+                (Some(aminos), None) if aminos.len() >= 2 => protein_from_aminos(&aminos),
+                _ => load_protein(sequence, id)?,
+            };
+            (template, pos, dim)
+        }
+        Source::Absolute(directions) => {
+            let pos = absolute_positions(directions, dim)?;
+            (load_protein(sequence, id)?, pos, dim)
+        }
+        Source::Directions(directions) => {
+            let protein = build(&load_protein(sequence, id)?, directions, dim)?;
+            let pos = walk(&protein.direct, protein.aminos.len());
+            (protein, pos, dim)
+        }
+    };
+    if pos.len() != template.aminos.len() {
+        return Err(Error::Parse(format!(
+            "the sequence has {} residues but the conformation has {}",
+            template.aminos.len(),
+            pos.len()
+        )));
+    }
+    let defects = defects(&pos);
+    if !defects.is_empty() {
+        return Err(Error::Defects(defects));
+    }
+    let id = find_benchmark(&template);
+    Ok(Loaded {
+        template,
+        pos,
+        dim,
+        id,
+    })
+}
+
+// 座標から数えたH-H結合(鎖上で隣り合うものは除く)
+pub fn contacts(aminos: &[Amino], pos: &[Point]) -> Vec<(usize, usize)> {
+    let mut result = Vec::new();
+    for i in 0..pos.len() {
+        for j in i + 2..pos.len() {
+            let (a, b) = (pos[i], pos[j]);
+            if aminos[i] == Amino::H
+                && aminos[j] == Amino::H
+                && (a.0 - b.0).abs() + (a.1 - b.1).abs() + (a.2 - b.2).abs() == 1
+            {
+                result.push((i, j));
+            }
+        }
+    }
+    result
+}
+
+// 重心からの距離の二乗平均の平方根
+pub fn radius_of_gyration(pos: &[Point]) -> f64 {
+    let n = pos.len() as f64;
+    let center = pos.iter().fold((0.0, 0.0, 0.0), |c, p| {
+        (
+            c.0 + p.0 as f64 / n,
+            c.1 + p.1 as f64 / n,
+            c.2 + p.2 as f64 / n,
+        )
+    });
+    let sum: f64 = pos
+        .iter()
+        .map(|p| {
+            (p.0 as f64 - center.0).powi(2)
+                + (p.1 as f64 - center.1).powi(2)
+                + (p.2 as f64 - center.2).powi(2)
+        })
+        .sum();
+    (sum / n).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_chain_has_no_defects() {
+        let pos = [(0, 0, 0), (1, 0, 0), (1, 1, 0), (0, 1, 0), (0, 1, 1)];
+        assert!(defects(&pos).is_empty());
+    }
+
+    #[test]
+    fn broken_chain() {
+        let pos = [(0, 0, 0), (1, 0, 0), (3, 0, 0), (3, 1, 0), (3, 1, 1)];
+        assert_eq!(
+            defects(&pos),
+            vec![Defect::Broken {
+                bond: 1,
+                from: (1, 0, 0),
+                to: (3, 0, 0)
+            }]
+        );
+    }
+
+    #[test]
+    fn colliding_chain() {
+        // 4番目は0番目に、6番目は1番目に重なる
+        let pos = [
+            (0, 0, 0),
+            (1, 0, 0),
+            (1, 1, 0),
+            (0, 1, 0),
+            (0, 0, 0),
+            (1, 0, 0),
+            (1, 0, 0),
+        ];
+        assert_eq!(
+            defects(&pos),
+            vec![
+                Defect::Broken {
+                    bond: 5,
+                    from: (1, 0, 0),
+                    to: (1, 0, 0)
+                },
+                Defect::Collision {
+                    residues: vec![0, 4],
+                    pos: (0, 0, 0)
+                },
+                Defect::Collision {
+                    residues: vec![1, 5, 6],
+                    pos: (1, 0, 0)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn loads_directions_onto_the_sequence() {
+        let loaded = load_conformation(&Source::Directions("LLRL"), Some("HPHPPH"), 0, 2).unwrap();
+        assert_eq!(loaded.template.aminos.len(), 6);
+        assert_eq!(loaded.pos.len(), 6);
+        assert_eq!(loaded.dim, 2);
+        assert_eq!(loaded.id, None);
+        // 配列を指定しなければ組み込みのベンチマークを使う
+        let loaded = load_conformation(&Source::Absolute("RRRRRRRRRRRRRRRRRRR"), None, 1, 2);
+        assert_eq!(loaded.unwrap().id, Some(1));
+    }
+
+    #[test]
+    fn rejects_invalid_conformations() {
+        let error = load_conformation(&Source::Directions("LLLL"), Some("HPHPPH"), 0, 2);
+        assert!(matches!(error, Err(Error::Defects(ref d)) if d.len() == 2));
+        let error = load_conformation(&Source::Absolute("RRR"), Some("HPHPPH"), 0, 2);
+        assert!(matches!(error, Err(Error::Parse(_))));
+        let error = load_conformation(&Source::Directions("LL"), None, 10_000, 2);
+        assert!(matches!(error, Err(Error::Parse(_))));
+    }
+}